@@ -1,23 +1,40 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Display;
 use std::fs;
-use std::io;
+use std::io::{self, BufRead, BufReader, Read};
+use std::num::NonZeroUsize;
+use std::os::unix::fs::{self as unix_fs, MetadataExt};
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use log::{debug, info, trace, warn};
+use log::{debug, error, info, trace, warn};
+
+use rayon::ThreadPoolBuilder;
 
 use thiserror::Error;
 
+use tokio::runtime::Handle;
+
+use crate::archive;
 use crate::db::PackagesDb;
-use crate::package::{LocalPackage, RemotePackage};
+use crate::frontends::{self, MessageColor};
+use crate::messages::Message;
+use crate::package::{LocalPackage, PackageData, PackageFile, RemotePackage};
+use crate::progress::{self, ProgressType};
 
+mod scheduler;
 #[cfg(test)]
 mod tests;
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Action {
-    Install(RemotePackage),
-    Remove(LocalPackage),
+    Install(Box<RemotePackage>),
+    Remove(Box<LocalPackage>),
 }
 impl Display for Action {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -28,6 +45,105 @@ impl Display for Action {
     }
 }
 
+/// Returns the lines of the final summary of the actions: the installed packages and their
+/// versions, and the removed packages.
+pub fn summarize(actions: &[Action]) -> Vec<String> {
+    let mut installed = Vec::new();
+    let mut removed = Vec::new();
+
+    for action in actions.iter() {
+        match action {
+            Action::Install(package) => installed.push(format!(
+                "{} {}",
+                package.package_data.name, package.package_data.version
+            )),
+            Action::Remove(package) => removed.push(package.package_data.name.clone()),
+        }
+    }
+
+    let mut summary = Vec::new();
+    if !installed.is_empty() {
+        summary.push(format!("Installed: {}", installed.join(", ")));
+    }
+    if !removed.is_empty() {
+        summary.push(format!("Removed: {}", removed.join(", ")));
+    }
+    if summary.is_empty() {
+        summary.push(String::from("Nothing to do"));
+    }
+
+    summary
+}
+
+/// Returns the amount of package files the removals will delete, the target of the
+/// [ProgressType::Files] progress.
+pub fn count_removed_files(actions: &[Action]) -> usize {
+    actions
+        .iter()
+        .map(|action| match action {
+            Action::Install(_) => 0,
+            Action::Remove(package) => package.package_files.len(),
+        })
+        .sum()
+}
+
+/// Returns a line counting the planned actions, such as `3 to install, 1 to remove`.
+pub fn count_summary(actions: &[Action]) -> String {
+    let installs = actions
+        .iter()
+        .filter(|action| matches!(action, Action::Install(_)))
+        .count();
+
+    format!(
+        "{installs} to install, {} to remove",
+        actions.len() - installs
+    )
+}
+
+/// How often a running command is checked for completion when it has a timeout
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long the output of a killed command is still read before it's abandoned
+const PIPE_CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+/// Options for running the commands of packages when building actions.
+#[derive(Clone, Debug, Default)]
+pub struct BuildOptions {
+    /// Commands running longer than this are killed
+    pub command_timeout: Option<Duration>,
+    /// Runtime used to display the output of commands in the frontend while they run. Output is
+    /// only logged once the command exits if not set.
+    pub output_runtime: Option<Handle>,
+    /// Maximum amount of actions built at once, all cores by default. With one job actions are
+    /// built one after another in the planned order.
+    pub jobs: Option<NonZeroUsize>,
+    /// Runtime used to report the deleted files of removals to the progressbar, see
+    /// [count_removed_files]. No progress is reported if not set.
+    pub progress_runtime: Option<Handle>,
+    /// Directory packages are installed into instead of `/`. Package files are recorded as seen
+    /// from inside it, so that the database can be used once the root is booted or chrooted into.
+    pub root: Option<PathBuf>,
+    /// Set to stop building further actions. Cancellation only takes effect before an action is
+    /// started: actions being built are finished, the ones not started yet fail with
+    /// [BuildError::Cancelled] and can be retried like any failed action. Nothing is rolled back.
+    pub cancel_requested: Arc<AtomicBool>,
+}
+
+impl BuildOptions {
+    pub fn root(&self) -> &Path {
+        self.root.as_deref().unwrap_or(Path::new("/"))
+    }
+}
+
+/// Result of a build where some of the actions failed.
+#[derive(Debug)]
+pub struct FailedBuild {
+    /// Error of the first failed action
+    pub error: BuildError,
+    pub built: Vec<Action>,
+    /// Actions that failed, as they were before building
+    pub outstanding: Vec<Action>,
+}
+
 #[derive(Error, Debug)]
 pub enum BuildError {
     #[error("Could not parse command: {0}")]
@@ -41,37 +157,110 @@ pub enum BuildError {
 
     #[error("Command {0} failed with exit code {1} and stderr:\n{2}")]
     CommandFail(String, i32, String),
+
+    #[error("File {path} is installed by multiple packages: {packages:?}")]
+    FileConflict { path: String, packages: Vec<String> },
+
+    #[error("Command {0} timed out after {1} seconds")]
+    CommandTimeout(String, u64),
+
+    #[error("{0} commands failed: {1}")]
+    Phase(CommandPhase, Box<BuildError>),
+
+    #[error("Not built as {0} could not be built")]
+    PrerequisiteFailed(String),
+
+    #[error("Not built as the operation was cancelled")]
+    Cancelled,
+}
+
+/// The package script a command belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandPhase {
+    PreInstall,
+    Install,
+    PostInstall,
+    PreRemove,
+    PostRemove,
+    PostTransaction,
+}
+impl Display for CommandPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandPhase::PreInstall => write!(f, "pre_install"),
+            CommandPhase::Install => write!(f, "install"),
+            CommandPhase::PostInstall => write!(f, "post_install"),
+            CommandPhase::PreRemove => write!(f, "pre_remove"),
+            CommandPhase::PostRemove => write!(f, "post_remove"),
+            CommandPhase::PostTransaction => write!(f, "post_transaction"),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
-pub enum CommitError<EDatabaseAdd: Display, EDatabaseRemove: Display> {
+pub enum CommitError<EDatabaseAdd: Display, EDatabaseRemove: Display, ETransaction: Display> {
     #[error("Failed to add package to database:\n{0}")]
     DatabaseAdd(EDatabaseAdd),
 
     #[error("Failed to remove package from database:\n{0}")]
     DatabaseRemove(EDatabaseRemove),
+
+    #[error("Database transaction failed:\n{0}")]
+    Transaction(ETransaction),
+}
+
+/// Files installed by the actions of a single build, along with the package that installed them.
+#[derive(Default)]
+struct FileClaims {
+    files: Mutex<HashMap<PathBuf, String>>,
 }
 
 impl Action {
-    pub fn build(&mut self, package_build_path: &str) -> Result<(), BuildError> {
-        info!("Building action {self}");
+    pub fn package_name(&self) -> &str {
+        match self {
+            Action::Install(package) => &package.package_data.name,
+            Action::Remove(package) => &package.package_data.name,
+        }
+    }
+
+    pub fn build(
+        &mut self,
+        package_build_path: &str,
+        options: &BuildOptions,
+    ) -> Result<(), BuildError> {
+        self.build_claiming(package_build_path, options, &FileClaims::default())
+    }
+
+    /// Builds the action, failing if it installs files that were already installed by another
+    /// action sharing the `claims`.
+    fn build_claiming(
+        &mut self,
+        package_build_path: &str,
+        options: &BuildOptions,
+        claims: &FileClaims,
+    ) -> Result<(), BuildError> {
+        info!("{}", Message::BuildingAction(self));
         match self {
             Action::Install(ref mut package) => {
-                install_package(package, package_build_path)?;
+                install_package(package, package_build_path, options, claims)?;
             }
             Action::Remove(ref mut package) => {
-                remove_package(package)?;
+                remove_package(package, options)?;
             }
         };
 
         Ok(())
     }
 
-    pub fn commit<EDatabaseAdd: Display, EDatabaseRemove: Display>(
+    pub fn commit<EDatabaseAdd: Display, EDatabaseRemove: Display, ETransaction: Display>(
         &self,
-        db: &mut impl PackagesDb<AddError = EDatabaseAdd, RemoveError = EDatabaseRemove>,
-    ) -> Result<(), CommitError<EDatabaseAdd, EDatabaseRemove>> {
-        info!("Commiting action {self}");
+        db: &mut impl PackagesDb<
+            AddError = EDatabaseAdd,
+            RemoveError = EDatabaseRemove,
+            TransactionError = ETransaction,
+        >,
+    ) -> Result<(), CommitError<EDatabaseAdd, EDatabaseRemove, ETransaction>> {
+        info!("{}", Message::CommitingAction(self));
         match self {
             Action::Install(ref package) => {
                 if let Err(error) = db.add_package(package) {
@@ -89,10 +278,130 @@ impl Action {
     }
 }
 
+/// Builds all actions in parallel, bounded by [BuildOptions::jobs], and returns them as built, as
+/// building fills in data that's needed to commit them. Every action is attempted even if some
+/// fail unless it depends on a failed one, so that the failed ones can be retried later without
+/// rebuilding the rest.
+///
+/// Dependencies are built before their dependents, see [scheduler::get_prerequisites]. The built
+/// and outstanding actions keep the planned order.
+pub fn build_actions(
+    actions: Vec<Action>,
+    package_build_path: &str,
+    options: &BuildOptions,
+    on_built: impl Fn(&Action) + Sync,
+) -> Result<Vec<Action>, FailedBuild> {
+    let claims = FileClaims::default();
+
+    let build = |action: Action| {
+        if options.cancel_requested.load(Ordering::Relaxed) {
+            return Err(Box::new((action, BuildError::Cancelled)));
+        }
+
+        let mut built_action = action.clone();
+        match built_action.build_claiming(package_build_path, options, &claims) {
+            Ok(()) => {
+                on_built(&built_action);
+                Ok(built_action)
+            }
+            Err(error) => {
+                error!("Could not build action {action}: {error}");
+                Err(Box::new((action, error)))
+            }
+        }
+    };
+
+    let results: Vec<scheduler::BuildResult> = match options.jobs {
+        Some(jobs) if jobs.get() == 1 => scheduler::build_in_order(actions, &build),
+        Some(jobs) => match ThreadPoolBuilder::new().num_threads(jobs.get()).build() {
+            Ok(pool) => pool.install(|| scheduler::build_scheduled(actions, &build)),
+            Err(error) => {
+                warn!("Could not limit the build to {jobs} jobs, using all cores: {error}");
+                scheduler::build_scheduled(actions, &build)
+            }
+        },
+        None => scheduler::build_scheduled(actions, &build),
+    };
+
+    let mut built = Vec::new();
+    let mut outstanding = Vec::new();
+    let mut first_error = None;
+
+    for result in results {
+        match result {
+            Ok(action) => built.push(action),
+            Err(failed) => {
+                let (action, error) = *failed;
+                outstanding.push(action);
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+
+    match first_error {
+        None => Ok(built),
+        Some(error) => Err(FailedBuild {
+            error,
+            built,
+            outstanding,
+        }),
+    }
+}
+
+/// Commits all actions inside a single database transaction, so that either all of the changes
+/// are applied or none of them are.
+pub async fn commit_actions<DB, EDatabaseAdd, EDatabaseRemove, ETransaction>(
+    actions: Vec<Action>,
+    db: &mut DB,
+) -> Result<(), CommitError<EDatabaseAdd, EDatabaseRemove, ETransaction>>
+where
+    EDatabaseAdd: Display,
+    EDatabaseRemove: Display,
+    ETransaction: Display,
+    DB: PackagesDb<
+        AddError = EDatabaseAdd,
+        RemoveError = EDatabaseRemove,
+        TransactionError = ETransaction,
+    >,
+{
+    if actions.is_empty() {
+        progress::set_completed(ProgressType::ActionsCommit).await;
+        return Ok(());
+    }
+
+    progress::increment_target(ProgressType::ActionsCommit, actions.len() as i32).await;
+
+    if let Err(error) = db.begin_transaction() {
+        return Err(CommitError::Transaction(error));
+    }
+
+    for action in actions {
+        if let Err(error) = action.commit(db) {
+            warn!("Could not commit action {action}, rolling back database changes");
+            if let Err(rollback_error) = db.rollback_transaction() {
+                error!("Could not rollback database transaction: {rollback_error}");
+            }
+            return Err(error);
+        }
+
+        progress::increment_completed(ProgressType::ActionsCommit, 1).await;
+    }
+
+    if let Err(error) = db.commit_transaction() {
+        return Err(CommitError::Transaction(error));
+    }
+
+    Ok(())
+}
+
 fn install_package(
     package: &mut RemotePackage,
     package_build_path: &str,
+    options: &BuildOptions,
+    claims: &FileClaims,
 ) -> Result<(), BuildError> {
+    validate_install_commands(package)?;
+
     let install_directory = format!("{}/{}", package_build_path, package.package_data.name);
 
     if fs::metadata(&install_directory).is_ok() {
@@ -100,40 +409,174 @@ fn install_package(
     }
     fs::create_dir_all(&install_directory)?;
 
-    run_commands(&package.pre_install, &install_directory)?;
+    if let Some(archive_files) = &package.archive_files {
+        move_archive_files(Path::new(archive_files), Path::new(&install_directory))?;
+    }
 
-    run_commands(&package.install, &install_directory)?;
+    let env = get_command_env(
+        &package.package_data,
+        &package.env,
+        &install_directory,
+        options.root(),
+    );
+
+    run_commands(
+        &package.pre_install,
+        &install_directory,
+        &env,
+        CommandPhase::PreInstall,
+        options,
+    )?;
+
+    run_commands(
+        &package.install,
+        &install_directory,
+        &env,
+        CommandPhase::Install,
+        options,
+    )?;
 
     let path_install_directory = Path::new(&install_directory);
-    let package_files = find_package_files(
+    relink_build_symlinks(
         path_install_directory,
         path_install_directory,
         Path::new("/"),
     )?;
 
+    // Claims are held until the files are moved, so that the files of other actions are either
+    // all visible or not installed yet
+    let mut claimed_files = claims
+        .files
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+
+    check_file_conflicts(
+        path_install_directory,
+        path_install_directory,
+        options.root(),
+        &package.package_data.name,
+        &claimed_files,
+    )?;
+
+    let overlaid_paths: HashSet<PathBuf> = package
+        .overlaid_files
+        .iter()
+        .flatten()
+        .map(|file| translate_to_root(Path::new(&file.path), Path::new("/"), options.root()))
+        .collect();
+
+    let package_files = find_package_files(
+        path_install_directory,
+        path_install_directory,
+        options.root(),
+        &overlaid_paths,
+    )?;
+
     debug!("Detected package files: {package_files:#?}");
 
     install_package_files(&package_files)?;
+    for (_, target) in package_files.iter() {
+        claimed_files.insert(target.clone(), package.package_data.name.clone());
+    }
+    drop(claimed_files);
+
+    if let Some(overlaid_files) = &package.overlaid_files {
+        delete_stale_files(overlaid_files, &package_files, options.root())?;
+    }
+
     package.package_files = package_files
-        .into_iter()
-        .map(|group| group.1.to_string_lossy().into_owned())
-        .collect();
+        .iter()
+        .map(|(_, target)| record_package_file(target, options.root()))
+        .collect::<Result<_, io::Error>>()?;
+
+    run_commands(
+        &package.post_install,
+        &install_directory,
+        &env,
+        CommandPhase::PostInstall,
+        options,
+    )?;
+
+    Ok(())
+}
 
-    run_commands(&package.post_install, &install_directory)?;
+/// Parses every install command, so that a malformed one fails the build before earlier ones
+/// modify the system.
+fn validate_install_commands(package: &RemotePackage) -> Result<(), BuildError> {
+    for command in package
+        .pre_install
+        .iter()
+        .chain(package.install.iter())
+        .chain(package.post_install.iter())
+    {
+        shell_words::split(command)?;
+    }
 
     Ok(())
 }
 
-fn remove_package(package: &LocalPackage) -> Result<(), BuildError> {
-    run_commands(&package.pre_remove, "/")?;
-    delete_package_files(&package.package_files)?;
-    run_commands(&package.post_remove, "/")?;
+fn remove_package(package: &LocalPackage, options: &BuildOptions) -> Result<(), BuildError> {
+    let root = options.root().to_string_lossy();
+    // The declared environment is not stored in the database, so only japm's variables are set
+    let env = get_command_env(
+        &package.package_data,
+        &BTreeMap::new(),
+        &root,
+        options.root(),
+    );
+
+    run_commands(
+        &package.pre_remove,
+        &root,
+        &env,
+        CommandPhase::PreRemove,
+        options,
+    )?;
+    delete_package_files(
+        &package.package_files,
+        options.root(),
+        options.progress_runtime.as_ref(),
+    )?;
+    run_commands(
+        &package.post_remove,
+        &root,
+        &env,
+        CommandPhase::PostRemove,
+        options,
+    )?;
 
     Ok(())
 }
 
+/// Returns the environment variables declared by the package, along with `JAPM_PKG_NAME`,
+/// `JAPM_PKG_VERSION`, `JAPM_BUILD_DIR` and `JAPM_ROOT` which are always set.
+fn get_command_env(
+    package_data: &PackageData,
+    declared_env: &BTreeMap<String, String>,
+    build_directory: &str,
+    root: &Path,
+) -> BTreeMap<String, String> {
+    let mut env = declared_env.clone();
+    env.insert(String::from("JAPM_PKG_NAME"), package_data.name.clone());
+    env.insert(
+        String::from("JAPM_PKG_VERSION"),
+        package_data.version.clone(),
+    );
+    env.insert(
+        String::from("JAPM_BUILD_DIR"),
+        String::from(build_directory),
+    );
+    env.insert(
+        String::from("JAPM_ROOT"),
+        root.to_string_lossy().into_owned(),
+    );
+
+    env
+}
+
 /// Find the files located in `path` that do not exist in `root_path`, and returns an array of
-/// original paths and their non-existing root translated equivalents.
+/// original paths and their non-existing root translated equivalents. Existing paths in
+/// `overlaid_paths` are included as well, to be replaced.
 ///
 /// For example, given normal parameters (root_path is `/`), then if an empty usr subdirectory exists
 /// in `path`, it won't be included in the result as `/usr` already exists on most filesystems.
@@ -143,25 +586,91 @@ fn find_package_files(
     path: &Path,
     base_path: &Path,
     root_path: &Path,
+    overlaid_paths: &HashSet<PathBuf>,
 ) -> Result<Vec<(PathBuf, PathBuf)>, io::Error> {
     let mut new_dirs = Vec::new();
     for subpath in fs::read_dir(path)? {
         let subpath = subpath?.path();
         let translated_subpath = translate_to_root(&subpath, base_path, root_path);
 
-        if !Path::try_exists(&translated_subpath)? {
+        if overlaid_paths.contains(&translated_subpath) || !Path::try_exists(&translated_subpath)? {
             new_dirs.push((subpath, translated_subpath));
             continue;
         }
 
-        if subpath.is_dir() {
-            new_dirs.extend(find_package_files(&subpath, base_path, root_path)?);
+        // Symlinked directories are installed as links, so their contents are not traversed
+        if fs::symlink_metadata(&subpath)?.is_dir() {
+            new_dirs.extend(find_package_files(
+                &subpath,
+                base_path,
+                root_path,
+                overlaid_paths,
+            )?);
         }
     }
 
     Ok(new_dirs)
 }
 
+/// Fails if any of the files in `path` would be installed to a path that, or whose parent, was
+/// already installed by another package of the same build.
+fn check_file_conflicts(
+    path: &Path,
+    base_path: &Path,
+    root_path: &Path,
+    package_name: &str,
+    claimed_files: &HashMap<PathBuf, String>,
+) -> Result<(), BuildError> {
+    for subpath in fs::read_dir(path)? {
+        let subpath = subpath?.path();
+
+        if fs::symlink_metadata(&subpath)?.is_dir() {
+            check_file_conflicts(&subpath, base_path, root_path, package_name, claimed_files)?;
+            continue;
+        }
+
+        let translated_subpath = translate_to_root(&subpath, base_path, root_path);
+        let owner = translated_subpath
+            .ancestors()
+            .find_map(|ancestor| claimed_files.get(ancestor));
+
+        if let Some(owner) = owner {
+            if owner != package_name {
+                return Err(BuildError::FileConflict {
+                    path: translated_subpath.to_string_lossy().into_owned(),
+                    packages: vec![owner.clone(), String::from(package_name)],
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Recreates the symlinks in `path` that point inside `base_path` to point to their root
+/// translated equivalent, so that they don't dangle once the package files are moved.
+fn relink_build_symlinks(path: &Path, base_path: &Path, root_path: &Path) -> Result<(), io::Error> {
+    for subpath in fs::read_dir(path)? {
+        let subpath = subpath?.path();
+        let file_type = fs::symlink_metadata(&subpath)?.file_type();
+
+        if file_type.is_symlink() {
+            let target = fs::read_link(&subpath)?;
+            if target.starts_with(base_path) {
+                let translated_target = translate_to_root(&target, base_path, root_path);
+
+                trace!("Relinking {subpath:?} from {target:?} to {translated_target:?}");
+                fs::remove_file(&subpath)?;
+                unix_fs::symlink(translated_target, &subpath)?;
+            }
+        } else if file_type.is_dir() {
+            relink_build_symlinks(&subpath, base_path, root_path)?;
+        }
+    }
+
+    Ok(())
+}
+
 fn translate_to_root(file: &Path, files_root_dir: &Path, root_dir: &Path) -> PathBuf {
     let relative = file
         .strip_prefix(files_root_dir)
@@ -170,36 +679,208 @@ fn translate_to_root(file: &Path, files_root_dir: &Path, root_dir: &Path) -> Pat
     root_dir.join(relative)
 }
 
+/// Records the installed file as seen from inside `root_path`, with its checksum and size if it's
+/// a regular file.
+fn record_package_file(installed_path: &Path, root_path: &Path) -> Result<PackageFile, io::Error> {
+    let path = translate_to_root(installed_path, root_path, Path::new("/"))
+        .to_string_lossy()
+        .into_owned();
+
+    let metadata = fs::symlink_metadata(installed_path)?;
+    if !metadata.is_file() {
+        return Ok(PackageFile {
+            path,
+            ..Default::default()
+        });
+    }
+
+    Ok(PackageFile {
+        path,
+        sha256: Some(archive::get_checksum(installed_path)?),
+        size: Some(metadata.len()),
+    })
+}
+
 fn install_package_files(package_files: &[(PathBuf, PathBuf)]) -> Result<(), io::Error> {
     for path_group in package_files {
         let source = &path_group.0;
         let dest = &path_group.1;
 
+        // Only overlaid paths exist already. Files are replaced by the move, directories can't be
+        if let Ok(dest_metadata) = fs::symlink_metadata(dest) {
+            if dest_metadata.is_dir() {
+                trace!("Replacing overlaid directory {dest:?}");
+                fs::remove_dir_all(dest)?;
+            } else if source.is_dir() {
+                fs::remove_file(dest)?;
+            }
+        }
+
         trace!("Moving {:?} to {:?}", source, dest);
-        fs::rename(source, dest)?;
+        move_preserving_metadata(source, dest)?;
+    }
+
+    Ok(())
+}
+
+/// Moves the file or directory keeping its permissions and ownership. Falls back to copying when
+/// the destination is on another filesystem.
+fn move_preserving_metadata(source: &Path, dest: &Path) -> Result<(), io::Error> {
+    let metadata = fs::symlink_metadata(source)?;
+
+    match fs::rename(source, dest) {
+        Ok(()) => apply_metadata(dest, &metadata),
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            debug!("{source:?} is on another filesystem than {dest:?}, copying instead");
+            copy_preserving_metadata(source, dest)?;
+
+            if metadata.is_dir() {
+                fs::remove_dir_all(source)
+            } else {
+                fs::remove_file(source)
+            }
+        }
+        Err(error) => Err(error),
+    }
+}
+
+fn copy_preserving_metadata(source: &Path, dest: &Path) -> Result<(), io::Error> {
+    let metadata = fs::symlink_metadata(source)?;
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        unix_fs::symlink(fs::read_link(source)?, dest)?;
+    } else if file_type.is_dir() {
+        fs::create_dir(dest)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_preserving_metadata(&entry.path(), &dest.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(source, dest)?;
+    }
+
+    apply_metadata(dest, &metadata)
+}
+
+fn apply_metadata(path: &Path, metadata: &fs::Metadata) -> Result<(), io::Error> {
+    if metadata.file_type().is_symlink() {
+        return unix_fs::lchown(path, Some(metadata.uid()), Some(metadata.gid()));
+    }
+
+    // Ownership is set first, as changing it clears the setuid and setgid bits
+    unix_fs::chown(path, Some(metadata.uid()), Some(metadata.gid()))?;
+    fs::set_permissions(path, metadata.permissions())
+}
+
+fn move_archive_files(archive_files: &Path, install_directory: &Path) -> Result<(), io::Error> {
+    for file in fs::read_dir(archive_files)? {
+        let file = file?;
+
+        trace!(
+            "Moving archive file {:?} to the install directory",
+            file.path()
+        );
+        move_preserving_metadata(&file.path(), &install_directory.join(file.file_name()))?;
     }
 
     Ok(())
 }
 
-fn delete_package_files(package_files: &[String]) -> Result<(), io::Error> {
-    for path in package_files {
-        info!("Deleting path {:?}", path);
-        if Path::is_dir(Path::new(&path)) {
-            fs::remove_dir_all(path)?;
+/// Deletes the overlaid files under `root_path` that the package no longer installs, except those
+/// inside an installed directory, which were replaced along with it.
+fn delete_stale_files(
+    overlaid_files: &[PackageFile],
+    package_files: &[(PathBuf, PathBuf)],
+    root_path: &Path,
+) -> Result<(), io::Error> {
+    let stale_files: Vec<PackageFile> = overlaid_files
+        .iter()
+        .filter(|file| {
+            let path = translate_to_root(Path::new(&file.path), Path::new("/"), root_path);
+            !package_files
+                .iter()
+                .any(|(_, target)| path.starts_with(target))
+        })
+        .cloned()
+        .collect();
+
+    delete_package_files(&stale_files, root_path, None)
+}
+
+/// Deletes the files, reporting every deleted or already missing file to the
+/// [ProgressType::Files] progress if `progress_runtime` is set.
+fn delete_package_files(
+    package_files: &[PackageFile],
+    root_path: &Path,
+    progress_runtime: Option<&Handle>,
+) -> Result<(), io::Error> {
+    for package_file in package_files {
+        if let Some(progress_runtime) = progress_runtime {
+            progress_runtime.spawn(progress::increment_completed(ProgressType::Files, 1));
+        }
+
+        let translated_path =
+            translate_to_root(Path::new(&package_file.path), Path::new("/"), root_path);
+
+        // Symlinks are not followed, so that dangling ones are deleted too
+        let Ok(metadata) = fs::symlink_metadata(&translated_path) else {
+            warn!(
+                "Path {:?} does not exist anymore, skipping",
+                translated_path
+            );
+            continue;
+        };
+
+        info!(
+            "{}",
+            Message::DeletingPath(&translated_path.to_string_lossy())
+        );
+        if metadata.is_dir() {
+            fs::remove_dir_all(&translated_path)?;
         } else {
-            fs::remove_file(path)?;
+            fs::remove_file(&translated_path)?;
         }
     }
 
     Ok(())
 }
 
-fn run_commands(commands: &Vec<String>, directory: &str) -> Result<(), BuildError> {
+/// Runs the post transaction hooks from the root directory. The transaction is already commited,
+/// so a failing hook is only reported and doesn't prevent the following hooks from running.
+pub fn run_post_transaction_hooks(hooks: &[String], options: &BuildOptions) {
+    for hook in hooks {
+        if let Err(error) = run_commands(
+            &vec![hook.clone()],
+            &options.root().to_string_lossy(),
+            &BTreeMap::new(),
+            CommandPhase::PostTransaction,
+            options,
+        ) {
+            warn!("Post transaction hook failed: {error}");
+        }
+    }
+}
+
+fn run_commands(
+    commands: &Vec<String>,
+    directory: &str,
+    env: &BTreeMap<String, String>,
+    phase: CommandPhase,
+    options: &BuildOptions,
+) -> Result<(), BuildError> {
     for command in commands {
-        debug!("Running command {command}");
+        debug!("Running {phase} command {command}");
 
-        let (stdout, stderr) = run_command(command, directory)?;
+        let (stdout, stderr) = match run_command(command, directory, env, options) {
+            Ok(output) => output,
+            Err(error) => return Err(BuildError::Phase(phase, Box::new(error))),
+        };
+
+        // Streamed output was already displayed
+        if options.output_runtime.is_some() {
+            continue;
+        }
 
         if !stdout.is_empty() {
             debug!("out: {stdout}");
@@ -212,7 +893,13 @@ fn run_commands(commands: &Vec<String>, directory: &str) -> Result<(), BuildErro
     Ok(())
 }
 
-fn run_command(command: &str, directory: &str) -> Result<(String, String), BuildError> {
+/// Runs the command in `directory`, with `env` overriding the inherited environment.
+fn run_command(
+    command: &str,
+    directory: &str,
+    env: &BTreeMap<String, String>,
+    options: &BuildOptions,
+) -> Result<(String, String), BuildError> {
     let args = shell_words::split(command)?;
     if args.is_empty() {
         return Err(BuildError::InvalidCommand(
@@ -231,7 +918,21 @@ fn run_command(command: &str, directory: &str) -> Result<(String, String), Build
         command_proc.arg(arg);
     }
 
-    let result = command_proc.current_dir(directory).output()?;
+    command_proc.current_dir(directory).envs(env);
+
+    let result = match run_process(
+        &mut command_proc,
+        options.command_timeout,
+        options.output_runtime.as_ref(),
+    )? {
+        Some(result) => result,
+        None => {
+            return Err(BuildError::CommandTimeout(
+                String::from(command),
+                options.command_timeout.unwrap_or_default().as_secs(),
+            ))
+        }
+    };
 
     let stdout = String::from_utf8_lossy(&result.stdout).to_string();
     let stderr = String::from_utf8_lossy(&result.stderr).to_string();
@@ -251,3 +952,145 @@ fn run_command(command: &str, directory: &str) -> Result<(String, String), Build
 
     Ok((stdout, stderr))
 }
+
+/// Like [Command::output], but kills the child and returns None if it is still running after
+/// `timeout`. Output lines are displayed in the frontend as they arrive if `output_runtime` is set.
+fn run_process(
+    command_proc: &mut Command,
+    timeout: Option<Duration>,
+    output_runtime: Option<&Handle>,
+) -> Result<Option<Output>, io::Error> {
+    // Its own process group allows killing the processes the command starts along with it
+    let mut child = command_proc
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+
+    // The pipes are drained while waiting, otherwise a child with a lot of output would block
+    let stdout_reader = read_pipe(
+        child.stdout.take(),
+        output_runtime.cloned(),
+        MessageColor::White,
+    );
+    let stderr_reader = read_pipe(
+        child.stderr.take(),
+        output_runtime.cloned(),
+        MessageColor::Yellow,
+    );
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let status = match deadline {
+        Some(deadline) => loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+
+            if Instant::now() >= deadline {
+                kill_child(&mut child);
+                break None;
+            }
+
+            thread::sleep(COMMAND_POLL_INTERVAL);
+        },
+        None => Some(child.wait()?),
+    };
+
+    let [stdout, stderr] = join_readers([stdout_reader, stderr_reader], deadline, child.id());
+
+    Ok(status.map(|status| Output {
+        status,
+        stdout,
+        stderr,
+    }))
+}
+
+/// Reads the pipe until it's closed, displaying every line in the frontend if `output_runtime`
+/// is set. Returns everything that was read.
+fn read_pipe(
+    pipe: Option<impl Read + Send + 'static>,
+    output_runtime: Option<Handle>,
+    color: MessageColor,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let Some(pipe) = pipe else {
+            return output;
+        };
+
+        let mut pipe = BufReader::new(pipe);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match pipe.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(error) => {
+                    warn!("Could not read command output: {error}");
+                    break;
+                }
+            }
+
+            output.extend_from_slice(&line);
+
+            if let Some(output_runtime) = &output_runtime {
+                let message = format!("    {}", String::from_utf8_lossy(&line).trim_end());
+                // Blocking keeps the lines in order, the runtime is not driven by this thread
+                output_runtime.block_on(frontends::display_message(message, &color));
+            }
+        }
+
+        output
+    })
+}
+
+/// Waits for the readers of the pipes of a command. Processes the command started in the
+/// background keep its pipes open after it exits, so once the deadline passes they are killed
+/// with its process group, and the pipes are abandoned if they are still open after
+/// [PIPE_CLOSE_GRACE].
+fn join_readers(
+    readers: [thread::JoinHandle<Vec<u8>>; 2],
+    deadline: Option<Instant>,
+    process_group: u32,
+) -> [Vec<u8>; 2] {
+    if let Some(mut deadline) = deadline {
+        let mut killed = false;
+        while readers.iter().any(|reader| !reader.is_finished()) {
+            if Instant::now() >= deadline {
+                if killed {
+                    warn!("Abandoning the output of a command that did not close its pipes");
+                    return [Vec::new(), Vec::new()];
+                }
+
+                kill_process_group(process_group);
+                killed = true;
+                deadline = Instant::now() + PIPE_CLOSE_GRACE;
+            }
+
+            thread::sleep(COMMAND_POLL_INTERVAL);
+        }
+    }
+
+    readers.map(|reader| reader.join().unwrap_or_default())
+}
+
+fn kill_child(child: &mut Child) {
+    warn!("Killing command that exceeded its timeout");
+
+    kill_process_group(child.id());
+    if let Err(error) = child.wait() {
+        error!("Could not wait for killed command: {error}");
+    }
+}
+
+/// Kills every process of the group, which is already gone if all its processes exited.
+fn kill_process_group(process_group: u32) {
+    // SAFETY: killpg only sends a signal, it doesn't touch the memory of this process
+    if unsafe { libc::killpg(process_group as libc::pid_t, libc::SIGKILL) } != 0 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::ESRCH) {
+            error!("Could not kill command: {error}");
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use rayon::Scope;
+
+use super::{Action, BuildError};
+
+/// The failed action is boxed with its error to keep the results small
+pub type BuildResult = Result<Action, Box<(Action, BuildError)>>;
+
+/// Returns the indices of the earlier planned actions each action has to wait for:
+/// - installs wait for the installs of their dependencies, and for the removal of the package
+///   they reinstall
+/// - removals wait for the removals of the packages depending on the removed package
+///
+/// Only earlier actions are waited for, so the plan order can't make the actions wait for each
+/// other.
+pub fn get_prerequisites(actions: &[Action]) -> Vec<Vec<usize>> {
+    actions
+        .iter()
+        .enumerate()
+        .map(|(index, action)| {
+            actions[..index]
+                .iter()
+                .enumerate()
+                .filter(|(_, earlier)| must_wait_for(action, earlier))
+                .map(|(earlier_index, _)| earlier_index)
+                .collect()
+        })
+        .collect()
+}
+
+fn must_wait_for(action: &Action, earlier: &Action) -> bool {
+    match (action, earlier) {
+        (Action::Install(package), Action::Install(dependency)) => {
+            package.dependencies.contains(&dependency.package_data.name)
+        }
+        (Action::Install(package), Action::Remove(removed)) => {
+            package.package_data.name == removed.package_data.name
+                || package.replaces.contains(&removed.package_data.name)
+        }
+        (Action::Remove(package), Action::Remove(depending)) => {
+            depending.dependencies.contains(&package.package_data.name)
+        }
+        (Action::Remove(_), Action::Install(_)) => false,
+    }
+}
+
+/// Builds the actions one after another in the planned order, which already builds
+/// prerequisites first. Actions whose prerequisites failed are not built.
+pub fn build_in_order(
+    actions: Vec<Action>,
+    build: &impl Fn(Action) -> BuildResult,
+) -> Vec<BuildResult> {
+    let prerequisites = get_prerequisites(&actions);
+    let mut results: Vec<BuildResult> = Vec::with_capacity(actions.len());
+
+    for (action, action_prerequisites) in actions.into_iter().zip(prerequisites) {
+        let failed_prerequisite =
+            action_prerequisites
+                .iter()
+                .find_map(|&prerequisite| match &results[prerequisite] {
+                    Ok(_) => None,
+                    Err(failed) => Some(failed.0.package_name().to_string()),
+                });
+
+        results.push(match failed_prerequisite {
+            Some(prerequisite) => Err(Box::new((
+                action,
+                BuildError::PrerequisiteFailed(prerequisite),
+            ))),
+            None => build(action),
+        });
+    }
+
+    results
+}
+
+/// Builds every action once all its prerequisites are built, running the actions that don't
+/// wait for each other in parallel on the current rayon pool. Actions whose prerequisites failed
+/// are not built. Results are returned in the planned order.
+pub fn build_scheduled(
+    actions: Vec<Action>,
+    build: &(impl Fn(Action) -> BuildResult + Sync),
+) -> Vec<BuildResult> {
+    let prerequisites = get_prerequisites(&actions);
+
+    let mut dependents = vec![Vec::new(); actions.len()];
+    for (index, action_prerequisites) in prerequisites.iter().enumerate() {
+        for &prerequisite in action_prerequisites {
+            dependents[prerequisite].push(index);
+        }
+    }
+
+    let schedule = Schedule {
+        remaining: prerequisites
+            .iter()
+            .map(|action_prerequisites| AtomicUsize::new(action_prerequisites.len()))
+            .collect(),
+        failed_prerequisite: actions.iter().map(|_| Mutex::new(None)).collect(),
+        results: actions.iter().map(|_| Mutex::new(None)).collect(),
+        actions: actions
+            .into_iter()
+            .map(|action| Mutex::new(Some(action)))
+            .collect(),
+        dependents,
+        build,
+    };
+
+    rayon::scope(|scope| {
+        for (index, action_prerequisites) in prerequisites.iter().enumerate() {
+            if action_prerequisites.is_empty() {
+                schedule.start(scope, index);
+            }
+        }
+    });
+
+    schedule
+        .results
+        .into_iter()
+        .map(|result| {
+            result
+                .into_inner()
+                .unwrap()
+                .expect("Every action is built once its prerequisites are")
+        })
+        .collect()
+}
+
+struct Schedule<'a, F> {
+    /// Taken once the action is started
+    actions: Vec<Mutex<Option<Action>>>,
+    dependents: Vec<Vec<usize>>,
+    /// Prerequisites that are not built yet
+    remaining: Vec<AtomicUsize>,
+    /// Name of a failed prerequisite of the action
+    failed_prerequisite: Vec<Mutex<Option<String>>>,
+    results: Vec<Mutex<Option<BuildResult>>>,
+    build: &'a F,
+}
+
+impl<F: Fn(Action) -> BuildResult + Sync> Schedule<'_, F> {
+    fn start<'s>(&'s self, scope: &Scope<'s>, index: usize) {
+        scope.spawn(move |scope| {
+            let action = self.actions[index].lock().unwrap().take().unwrap();
+
+            let result = match self.failed_prerequisite[index].lock().unwrap().take() {
+                Some(prerequisite) => Err(Box::new((
+                    action,
+                    BuildError::PrerequisiteFailed(prerequisite),
+                ))),
+                None => (self.build)(action),
+            };
+
+            let failed = match &result {
+                Ok(_) => None,
+                Err(failed) => Some(failed.0.package_name().to_string()),
+            };
+            *self.results[index].lock().unwrap() = Some(result);
+
+            for &dependent in self.dependents[index].iter() {
+                if let Some(failed) = &failed {
+                    self.failed_prerequisite[dependent]
+                        .lock()
+                        .unwrap()
+                        .get_or_insert_with(|| failed.clone());
+                }
+
+                if self.remaining[dependent].fetch_sub(1, Ordering::AcqRel) == 1 {
+                    self.start(scope, dependent);
+                }
+            }
+        });
+    }
+}
@@ -2,16 +2,18 @@ use super::*;
 
 use crate::package::{PackageData, RemotePackage};
 
-use crate::test_helpers::MockPackagesDb;
+use crate::test_helpers::{MockPackagesDb, MockProgressbar};
 
 #[test]
 fn test_package_installs() {
     let remote_package = get_mock_remote_package();
     let mut mock_db = MockPackagesDb::new();
 
-    let mut action = Action::Install(remote_package.clone());
+    let mut action = Action::Install(Box::new(remote_package.clone()));
 
-    assert!(action.build("/tmp/japm/test").is_ok());
+    assert!(action
+        .build("/tmp/japm/test", &BuildOptions::default())
+        .is_ok());
     assert!(action.commit(&mut mock_db).is_ok());
 
     assert!(mock_db
@@ -35,13 +37,755 @@ fn test_package_removes() {
 
     let local_package = mock_db.get_package(&package_name).unwrap().unwrap();
 
-    let mut action = Action::Remove(local_package);
+    let mut action = Action::Remove(Box::new(local_package));
 
-    assert!(action.build("/tmp/japm/test").is_ok());
+    assert!(action
+        .build("/tmp/japm/test", &BuildOptions::default())
+        .is_ok());
     assert!(action.commit(&mut mock_db).is_ok());
     assert!(mock_db.get_package(&package_name).unwrap().is_none());
 }
 
+#[test]
+fn test_dangling_symlink_deleted() {
+    const LINK_PATH: &str = "/tmp/japm/tests/dangling_link";
+    fs::create_dir_all("/tmp/japm/tests").unwrap();
+    fs::remove_file(LINK_PATH).ok();
+    unix_fs::symlink("/tmp/japm/tests/missing_link_target", LINK_PATH).unwrap();
+
+    let package_files = [PackageFile {
+        path: String::from(LINK_PATH),
+        sha256: None,
+        size: None,
+    }];
+    delete_package_files(&package_files, Path::new("/"), None).unwrap();
+
+    assert!(fs::symlink_metadata(LINK_PATH).is_err());
+}
+
+#[test]
+fn test_failing_post_install_identifies_phase() {
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-failing-post-install-package");
+    remote_package.post_install = vec![String::from("false")];
+
+    let mut action = Action::Install(Box::new(remote_package));
+
+    let result = action.build("/tmp/japm/test", &BuildOptions::default());
+
+    assert!(matches!(
+        result,
+        Err(BuildError::Phase(CommandPhase::PostInstall, _))
+    ));
+}
+
+#[tokio::test]
+async fn test_failed_commit_leaves_db_unchanged() {
+    progress::set_boxed_progress(Box::new(MockProgressbar));
+
+    let remote_package = get_mock_remote_package();
+    let mut mock_db = MockPackagesDb::new();
+
+    let mut installed_package = remote_package.clone();
+    installed_package.package_data.name = String::from("installed-package");
+    mock_db.add_package(&installed_package).unwrap();
+
+    let packages_before = mock_db.get_all_packages().unwrap();
+
+    let not_installed_package = LocalPackage {
+        id: None,
+        package_data: PackageData {
+            name: String::from("not-installed-package"),
+            ..Default::default()
+        },
+        dependencies: vec![],
+        replaces: vec![],
+        source_remote: None,
+        arch: vec![],
+        os: vec![],
+        pre_remove: vec![],
+        package_files: vec![],
+        post_remove: vec![],
+    };
+
+    // The install commits fine, but removing a package that is not installed fails mid-loop.
+    let actions = vec![
+        Action::Install(Box::new(remote_package)),
+        Action::Remove(Box::new(not_installed_package)),
+    ];
+
+    let result = commit_actions(actions, &mut mock_db).await;
+
+    assert!(matches!(result, Err(CommitError::DatabaseRemove(_))));
+    assert_eq!(mock_db.get_all_packages().unwrap(), packages_before);
+}
+
+#[test]
+fn test_summary_lists_packages_not_steps() {
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.version = String::from("1.0.0");
+    remote_package.install = vec![String::from("echo per-package step")];
+
+    let removed_package = LocalPackage {
+        id: Some(1),
+        package_data: PackageData {
+            name: String::from("removed-package"),
+            ..Default::default()
+        },
+        dependencies: vec![],
+        replaces: vec![],
+        source_remote: None,
+        arch: vec![],
+        os: vec![],
+        pre_remove: vec![],
+        package_files: vec![],
+        post_remove: vec![],
+    };
+
+    let summary = summarize(&[
+        Action::Install(Box::new(remote_package)),
+        Action::Remove(Box::new(removed_package)),
+    ]);
+
+    assert_eq!(
+        summary,
+        vec![
+            String::from("Installed: test-package 1.0.0"),
+            String::from("Removed: removed-package")
+        ]
+    );
+}
+
+#[test]
+fn test_planned_actions_counted() {
+    let actions = vec![
+        Action::Install(Box::new(get_mock_remote_package())),
+        Action::Install(Box::new(get_mock_remote_package())),
+        Action::Remove(Box::new(LocalPackage {
+            id: None,
+            package_data: PackageData::default(),
+            dependencies: vec![],
+            replaces: vec![],
+            source_remote: None,
+            arch: vec![],
+            os: vec![],
+            pre_remove: vec![],
+            package_files: vec![],
+            post_remove: vec![],
+        })),
+    ];
+
+    assert_eq!(count_summary(&actions), "2 to install, 1 to remove");
+    assert_eq!(count_summary(&[]), "0 to install, 0 to remove");
+}
+
+#[test]
+fn test_removed_files_counted() {
+    let removal = |package_files: Vec<PackageFile>| {
+        Action::Remove(Box::new(LocalPackage {
+            id: None,
+            package_data: PackageData::default(),
+            dependencies: vec![],
+            replaces: vec![],
+            source_remote: None,
+            arch: vec![],
+            os: vec![],
+            pre_remove: vec![],
+            package_files,
+            post_remove: vec![],
+        }))
+    };
+
+    let actions = vec![
+        removal(vec![
+            PackageFile::from("/first"),
+            PackageFile::from("/second"),
+        ]),
+        // Packages without files don't add to the progress
+        removal(vec![]),
+        Action::Install(Box::new(get_mock_remote_package())),
+    ];
+
+    assert_eq!(count_removed_files(&actions), 2);
+}
+
+#[test]
+fn test_malformed_command_fails_before_any_command_runs() {
+    const MARKER_PATH: &str = "/tmp/japm/tests/malformed_command_marker";
+    let _ = fs::remove_file(MARKER_PATH);
+
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-malformed-command-package");
+    remote_package.install = vec![
+        format!("sh -c 'mkdir -p /tmp/japm/tests && touch {MARKER_PATH}'"),
+        String::from("echo 'unbalanced"),
+    ];
+
+    let mut action = Action::Install(Box::new(remote_package));
+    let result = action.build("/tmp/japm/test", &BuildOptions::default());
+
+    assert!(matches!(result, Err(BuildError::Parse(_))));
+    assert!(!Path::new(MARKER_PATH).exists());
+}
+
+#[test]
+fn test_command_exceeding_timeout_killed() {
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-timeout-package");
+    remote_package.install = vec![String::from("sleep 5")];
+
+    let mut action = Action::Install(Box::new(remote_package));
+    let options = BuildOptions {
+        command_timeout: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+
+    let started = Instant::now();
+    let result = action.build("/tmp/japm/test", &options);
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    match result {
+        Err(BuildError::Phase(CommandPhase::Install, error)) => {
+            assert!(matches!(*error, BuildError::CommandTimeout(_, 1)))
+        }
+        _ => panic!("Expected the install command to time out"),
+    }
+}
+
+#[test]
+fn test_background_process_of_timed_out_command_killed() {
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-background-timeout-package");
+    remote_package.install = vec![String::from("sh -c 'sleep 10 & sleep 10'")];
+
+    let mut action = Action::Install(Box::new(remote_package));
+    let options = BuildOptions {
+        command_timeout: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+
+    let started = Instant::now();
+    let result = action.build("/tmp/japm/test", &options);
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_background_process_of_finished_command_killed_at_timeout() {
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-background-finished-package");
+    remote_package.install = vec![String::from("sh -c 'sleep 10 &'")];
+
+    let mut action = Action::Install(Box::new(remote_package));
+    let options = BuildOptions {
+        command_timeout: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+
+    let started = Instant::now();
+    action.build("/tmp/japm/test", &options).ok();
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_commands_read_package_env() {
+    const ENV_OUTPUT_PATH: &str = "/tmp/japm/tests/package_env";
+
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-env-package");
+    remote_package.package_data.version = String::from("1.0.0");
+    remote_package
+        .env
+        .insert(String::from("GREETING"), String::from("hello"));
+    remote_package.install = vec![format!(
+        "sh -c 'mkdir -p /tmp/japm/tests && echo $JAPM_PKG_NAME $JAPM_PKG_VERSION $GREETING > {ENV_OUTPUT_PATH}'"
+    )];
+
+    let mut action = Action::Install(Box::new(remote_package));
+    action
+        .build("/tmp/japm/test", &BuildOptions::default())
+        .unwrap();
+
+    assert_eq!(
+        fs::read_to_string(ENV_OUTPUT_PATH).unwrap(),
+        "test-env-package 1.0.0 hello\n"
+    );
+}
+
+#[test]
+fn test_symlink_into_build_dir_installed_as_link() {
+    const SYMLINK_ROOT: &str = "/tmp/japm/tests/symlink_root";
+
+    if Path::new(SYMLINK_ROOT).exists() {
+        fs::remove_dir_all(SYMLINK_ROOT).expect("Could not remove previous symlink root");
+    }
+
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-symlink-package");
+    remote_package.install = vec![
+        String::from("mkdir -p tmp/japm/tests/symlink_root"),
+        String::from("sh -c 'echo content > tmp/japm/tests/symlink_root/target'"),
+        String::from(
+            "sh -c 'ln -s $JAPM_BUILD_DIR/tmp/japm/tests/symlink_root/target tmp/japm/tests/symlink_root/link'",
+        ),
+    ];
+
+    let mut action = Action::Install(Box::new(remote_package));
+    action
+        .build("/tmp/japm/test", &BuildOptions::default())
+        .unwrap();
+
+    let link = Path::new(SYMLINK_ROOT).join("link");
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(
+        fs::read_link(&link).unwrap(),
+        Path::new(SYMLINK_ROOT).join("target")
+    );
+    assert_eq!(fs::read_to_string(&link).unwrap(), "content\n");
+
+    fs::remove_dir_all(SYMLINK_ROOT).expect("Could not cleanup symlink root");
+}
+
+#[test]
+fn test_package_installed_under_root() {
+    const INSTALL_ROOT: &str = "/tmp/japm/tests/install_root";
+
+    if Path::new(INSTALL_ROOT).exists() {
+        fs::remove_dir_all(INSTALL_ROOT).expect("Could not remove previous install root");
+    }
+    fs::create_dir_all(INSTALL_ROOT).unwrap();
+
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-rooted-package");
+    remote_package.install = vec![
+        String::from("mkdir -p opt/test-rooted-package"),
+        String::from("sh -c 'echo $JAPM_ROOT > opt/test-rooted-package/root'"),
+    ];
+
+    let options = BuildOptions {
+        root: Some(PathBuf::from(INSTALL_ROOT)),
+        ..Default::default()
+    };
+
+    let mut action = Action::Install(Box::new(remote_package));
+    action.build("/tmp/japm/test", &options).unwrap();
+
+    let installed_file = Path::new(INSTALL_ROOT).join("opt/test-rooted-package/root");
+    assert_eq!(
+        fs::read_to_string(&installed_file).unwrap(),
+        format!("{INSTALL_ROOT}\n")
+    );
+    assert!(!Path::new("/opt/test-rooted-package").exists());
+
+    let Action::Install(built_package) = action else {
+        unreachable!();
+    };
+    assert_eq!(built_package.package_files, vec![PackageFile::from("/opt")]);
+
+    let mut removal = Action::Remove(Box::new(LocalPackage {
+        id: None,
+        package_data: built_package.package_data,
+        pre_remove: vec![],
+        post_remove: vec![],
+        package_files: built_package.package_files,
+        dependencies: vec![],
+        replaces: vec![],
+        source_remote: None,
+        arch: vec![],
+        os: vec![],
+    }));
+    removal.build("/tmp/japm/test", &options).unwrap();
+
+    assert!(!Path::new(INSTALL_ROOT).join("opt").exists());
+
+    fs::remove_dir_all(INSTALL_ROOT).expect("Could not cleanup install root");
+}
+
+#[test]
+fn test_overlay_replaces_files_and_deletes_stale_ones() {
+    const INSTALL_ROOT: &str = "/tmp/japm/tests/overlay_root";
+
+    if Path::new(INSTALL_ROOT).exists() {
+        fs::remove_dir_all(INSTALL_ROOT).expect("Could not remove previous install root");
+    }
+    fs::create_dir_all(Path::new(INSTALL_ROOT).join("opt")).unwrap();
+
+    let options = BuildOptions {
+        root: Some(PathBuf::from(INSTALL_ROOT)),
+        ..Default::default()
+    };
+
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-overlaid-package");
+    remote_package.install = vec![
+        String::from("mkdir opt"),
+        String::from("sh -c 'echo 1 > opt/kept'"),
+        String::from("sh -c 'echo 1 > opt/stale'"),
+    ];
+
+    let mut action = Action::Install(Box::new(remote_package.clone()));
+    action.build("/tmp/japm/test", &options).unwrap();
+    let Action::Install(installed_package) = action else {
+        unreachable!();
+    };
+    assert_eq!(installed_package.package_files.len(), 2);
+
+    remote_package.install = vec![
+        String::from("mkdir opt"),
+        String::from("sh -c 'echo 2 > opt/kept'"),
+    ];
+    remote_package.overlaid_files = Some(installed_package.package_files);
+
+    let mut action = Action::Install(Box::new(remote_package));
+    action.build("/tmp/japm/test", &options).unwrap();
+    let Action::Install(overlaid_package) = action else {
+        unreachable!();
+    };
+
+    let root = Path::new(INSTALL_ROOT);
+    assert_eq!(fs::read_to_string(root.join("opt/kept")).unwrap(), "2\n");
+    assert!(!root.join("opt/stale").exists());
+    assert_eq!(overlaid_package.package_files.len(), 1);
+    assert_eq!(overlaid_package.package_files[0].path, "/opt/kept");
+
+    fs::remove_dir_all(INSTALL_ROOT).expect("Could not cleanup install root");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_installed_file_mode_preserved() {
+    use std::os::unix::fs::PermissionsExt;
+
+    const MODE_ROOT: &str = "/tmp/japm/tests/mode_root";
+
+    if Path::new(MODE_ROOT).exists() {
+        fs::remove_dir_all(MODE_ROOT).expect("Could not remove previous mode root");
+    }
+
+    let mut remote_package = get_mock_remote_package();
+    remote_package.package_data.name = String::from("test-mode-package");
+    remote_package.install = vec![
+        String::from("mkdir -p tmp/japm/tests/mode_root"),
+        String::from("touch tmp/japm/tests/mode_root/script"),
+        String::from("chmod 750 tmp/japm/tests/mode_root/script"),
+    ];
+
+    let mut action = Action::Install(Box::new(remote_package));
+    action
+        .build("/tmp/japm/test", &BuildOptions::default())
+        .unwrap();
+
+    let mode = fs::metadata(Path::new(MODE_ROOT).join("script"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o7777, 0o750);
+
+    fs::remove_dir_all(MODE_ROOT).expect("Could not cleanup mode root");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_copied_file_mode_preserved() {
+    use std::os::unix::fs::PermissionsExt;
+
+    const COPY_DIRECTORY: &str = "/tmp/japm/tests/mode_copy";
+
+    if Path::new(COPY_DIRECTORY).exists() {
+        fs::remove_dir_all(COPY_DIRECTORY).expect("Could not remove previous copy directory");
+    }
+    fs::create_dir_all(format!("{COPY_DIRECTORY}/source")).unwrap();
+
+    let source_file = Path::new(COPY_DIRECTORY).join("source/config");
+    fs::write(&source_file, "config").unwrap();
+    fs::set_permissions(&source_file, fs::Permissions::from_mode(0o640)).unwrap();
+
+    let dest = Path::new(COPY_DIRECTORY).join("dest");
+    copy_preserving_metadata(&Path::new(COPY_DIRECTORY).join("source"), &dest).unwrap();
+
+    let mode = fs::metadata(dest.join("config"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o7777, 0o640);
+
+    fs::remove_dir_all(COPY_DIRECTORY).expect("Could not cleanup copy directory");
+}
+
+#[test]
+fn test_packages_installing_same_file_conflict() {
+    const CONFLICT_ROOT: &str = "/tmp/japm/tests/conflict_root";
+
+    if Path::new(CONFLICT_ROOT).exists() {
+        fs::remove_dir_all(CONFLICT_ROOT).expect("Could not remove previous conflict root");
+    }
+    fs::create_dir_all(CONFLICT_ROOT).unwrap();
+
+    let actions: Vec<Action> = ["test-conflict-first", "test-conflict-second"]
+        .into_iter()
+        .map(|name| {
+            let mut remote_package = get_mock_remote_package();
+            remote_package.package_data.name = String::from(name);
+            remote_package.install = vec![
+                String::from("mkdir -p tmp/japm/tests/conflict_root"),
+                format!("sh -c 'echo {name} > tmp/japm/tests/conflict_root/tool'"),
+            ];
+            Action::Install(Box::new(remote_package))
+        })
+        .collect();
+
+    let failed_build =
+        build_actions(actions, "/tmp/japm/test", &BuildOptions::default(), |_| {}).unwrap_err();
+
+    match failed_build.error {
+        BuildError::FileConflict { path, mut packages } => {
+            packages.sort();
+            assert_eq!(path, format!("{CONFLICT_ROOT}/tool"));
+            assert_eq!(
+                packages,
+                vec!["test-conflict-first", "test-conflict-second"]
+            );
+        }
+        error => panic!("Expected a file conflict, got {error}"),
+    }
+    assert_eq!(failed_build.built.len(), 1);
+
+    fs::remove_dir_all(CONFLICT_ROOT).expect("Could not cleanup conflict root");
+}
+
+#[test]
+fn test_single_job_builds_in_planned_order() {
+    const ORDER_PATH: &str = "/tmp/japm/tests/build_order";
+
+    fs::create_dir_all("/tmp/japm/tests").unwrap();
+    let _ = fs::remove_file(ORDER_PATH);
+
+    let names = ["test-order-first", "test-order-second", "test-order-third"];
+    let actions: Vec<Action> = names
+        .into_iter()
+        .map(|name| {
+            let mut remote_package = get_mock_remote_package();
+            remote_package.package_data.name = String::from(name);
+            remote_package.install = vec![format!("sh -c 'echo {name} >> {ORDER_PATH}'")];
+            Action::Install(Box::new(remote_package))
+        })
+        .collect();
+
+    let options = BuildOptions {
+        jobs: NonZeroUsize::new(1),
+        ..Default::default()
+    };
+    let built = build_actions(actions, "/tmp/japm/test", &options, |_| {}).unwrap();
+
+    assert_eq!(built.len(), names.len());
+    assert_eq!(
+        fs::read_to_string(ORDER_PATH)
+            .unwrap()
+            .lines()
+            .collect::<Vec<&str>>(),
+        names
+    );
+}
+
+#[test]
+fn test_cancelled_build_stops_before_next_action() {
+    let actions: Vec<Action> = ["test-cancel-first", "test-cancel-second"]
+        .iter()
+        .map(|name| {
+            let mut remote_package = get_mock_remote_package();
+            remote_package.package_data.name = String::from(*name);
+            Action::Install(Box::new(remote_package))
+        })
+        .collect();
+
+    let options = BuildOptions {
+        jobs: NonZeroUsize::new(1),
+        ..Default::default()
+    };
+    let failed_build = build_actions(actions, "/tmp/japm/test", &options, |_| {
+        options.cancel_requested.store(true, Ordering::Relaxed)
+    })
+    .unwrap_err();
+
+    assert!(matches!(failed_build.error, BuildError::Cancelled));
+    assert_eq!(failed_build.built.len(), 1);
+    assert_eq!(
+        failed_build.outstanding[0].package_name(),
+        "test-cancel-second"
+    );
+}
+
+#[test]
+fn test_dependency_built_before_dependent() {
+    const ORDER_PATH: &str = "/tmp/japm/tests/dependency_build_order";
+
+    fs::create_dir_all("/tmp/japm/tests").unwrap();
+    let _ = fs::remove_file(ORDER_PATH);
+
+    let mut dependency = get_mock_remote_package();
+    dependency.package_data.name = String::from("test-schedule-dependency");
+    // Slow enough that the dependent would finish first if it didn't wait
+    dependency.install = vec![format!(
+        "sh -c 'sleep 0.2 && echo test-schedule-dependency >> {ORDER_PATH}'"
+    )];
+
+    let mut dependent = get_mock_remote_package();
+    dependent.package_data.name = String::from("test-schedule-dependent");
+    dependent.dependencies = vec![dependency.package_data.name.clone()];
+    dependent.install = vec![format!(
+        "sh -c 'echo test-schedule-dependent >> {ORDER_PATH}'"
+    )];
+
+    let actions = vec![
+        Action::Install(Box::new(dependency)),
+        Action::Install(Box::new(dependent)),
+    ];
+    let built = build_actions(actions, "/tmp/japm/test", &BuildOptions::default(), |_| {}).unwrap();
+
+    assert_eq!(built.len(), 2);
+    assert_eq!(
+        fs::read_to_string(ORDER_PATH)
+            .unwrap()
+            .lines()
+            .collect::<Vec<&str>>(),
+        ["test-schedule-dependency", "test-schedule-dependent"]
+    );
+}
+
+#[test]
+fn test_dependent_of_failed_action_not_built() {
+    let mut dependency = get_mock_remote_package();
+    dependency.package_data.name = String::from("test-failing-dependency");
+    dependency.install = vec![String::from("false")];
+
+    let mut dependent = get_mock_remote_package();
+    dependent.package_data.name = String::from("test-skipped-dependent");
+    dependent.dependencies = vec![dependency.package_data.name.clone()];
+
+    let mut unrelated = get_mock_remote_package();
+    unrelated.package_data.name = String::from("test-unrelated");
+
+    let actions = vec![
+        Action::Install(Box::new(dependency)),
+        Action::Install(Box::new(dependent)),
+        Action::Install(Box::new(unrelated)),
+    ];
+    let failed_build =
+        build_actions(actions, "/tmp/japm/test", &BuildOptions::default(), |_| {}).unwrap_err();
+
+    assert!(matches!(
+        failed_build.error,
+        BuildError::Phase(CommandPhase::Install, _)
+    ));
+    assert_eq!(failed_build.built.len(), 1);
+    assert_eq!(failed_build.built[0].package_name(), "test-unrelated");
+    assert_eq!(
+        failed_build
+            .outstanding
+            .iter()
+            .map(Action::package_name)
+            .collect::<Vec<&str>>(),
+        ["test-failing-dependency", "test-skipped-dependent"]
+    );
+}
+
+#[test]
+fn test_single_job_skips_dependent_of_failed_action() {
+    const DEPENDENT_RAN_PATH: &str = "/tmp/japm/tests/single_job_dependent_ran";
+
+    fs::create_dir_all("/tmp/japm/tests").unwrap();
+    let _ = fs::remove_file(DEPENDENT_RAN_PATH);
+
+    let mut dependency = get_mock_remote_package();
+    dependency.package_data.name = String::from("test-single-job-failing-dependency");
+    dependency.install = vec![String::from("false")];
+
+    let mut dependent = get_mock_remote_package();
+    dependent.package_data.name = String::from("test-single-job-skipped-dependent");
+    dependent.dependencies = vec![dependency.package_data.name.clone()];
+    dependent.install = vec![format!("touch {DEPENDENT_RAN_PATH}")];
+
+    let mut unrelated = get_mock_remote_package();
+    unrelated.package_data.name = String::from("test-single-job-unrelated");
+
+    let actions = vec![
+        Action::Install(Box::new(dependency)),
+        Action::Install(Box::new(dependent)),
+        Action::Install(Box::new(unrelated)),
+    ];
+    let options = BuildOptions {
+        jobs: NonZeroUsize::new(1),
+        ..Default::default()
+    };
+    let failed_build = build_actions(actions, "/tmp/japm/test", &options, |_| {}).unwrap_err();
+
+    assert!(!Path::new(DEPENDENT_RAN_PATH).exists());
+    assert_eq!(failed_build.built.len(), 1);
+    assert_eq!(
+        failed_build.built[0].package_name(),
+        "test-single-job-unrelated"
+    );
+    assert_eq!(
+        failed_build
+            .outstanding
+            .iter()
+            .map(Action::package_name)
+            .collect::<Vec<&str>>(),
+        [
+            "test-single-job-failing-dependency",
+            "test-single-job-skipped-dependent"
+        ]
+    );
+}
+
+#[test]
+fn test_prerequisites_follow_dependencies() {
+    let mut library = get_mock_remote_package();
+    library.package_data.name = String::from("library");
+    let mut tool = get_mock_remote_package();
+    tool.package_data.name = String::from("tool");
+    tool.dependencies = vec![String::from("library")];
+
+    let installed_tool = LocalPackage {
+        id: None,
+        package_data: tool.package_data.clone(),
+        dependencies: tool.dependencies.clone(),
+        replaces: vec![],
+        source_remote: None,
+        arch: vec![],
+        os: vec![],
+        pre_remove: Vec::new(),
+        package_files: Vec::new(),
+        post_remove: Vec::new(),
+    };
+    let installed_library = LocalPackage {
+        package_data: library.package_data.clone(),
+        dependencies: Vec::new(),
+        replaces: vec![],
+        source_remote: None,
+        ..installed_tool.clone()
+    };
+
+    let actions = vec![
+        Action::Install(Box::new(library.clone())),
+        Action::Remove(Box::new(installed_tool.clone())),
+        Action::Install(Box::new(tool)),
+        Action::Remove(Box::new(installed_library)),
+    ];
+
+    // The reinstalled tool waits for its library and its own removal, the library is removed
+    // after the tool that depends on it
+    assert_eq!(
+        scheduler::get_prerequisites(&actions),
+        vec![vec![], vec![], vec![0, 1], vec![1]]
+    );
+}
+
 fn get_mock_remote_package() -> RemotePackage {
     RemotePackage {
         package_data: PackageData {
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use log::{debug, trace};
+
+use serde_json::json;
+
+use sha2::{Digest, Sha256};
+
+use thiserror::Error;
+
+use crate::config;
+use crate::package::{LocalPackage, PackageParseError, RemotePackage, MANIFEST_VERSION};
+
+#[cfg(test)]
+mod tests;
+
+pub const ARCHIVE_EXTENSION: &str = "japm";
+
+const MANIFEST_PATH: &str = "package.json";
+/// Contains the sha256 checksum of every other file of the archive, in the format of `sha256sum`
+const CHECKSUMS_PATH: &str = "checksums";
+/// Contains the package files, laid out relative to the root of the filesystem
+const FILES_DIRECTORY: &str = "files";
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("An IO error has occured: {0}")]
+    IO(#[from] io::Error),
+    #[error("The package manifest is invalid: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Manifest(#[from] PackageParseError),
+    #[error("The archive has no {0}")]
+    MissingEntry(&'static str),
+    #[error("The archive contains the unexpected entry {0}")]
+    InvalidEntry(String),
+    #[error("The checksums file has an invalid line: {0}")]
+    InvalidChecksums(String),
+    #[error("The checksums file has no checksum for {0}")]
+    MissingChecksum(String),
+    #[error("Checksum of {0} does not match")]
+    ChecksumMismatch(String),
+    #[error("Package file {0} does not exist")]
+    MissingFile(String),
+}
+
+/// A `.japm` package archive is a tarball of a `package.json` manifest, a `checksums` file and
+/// the package files in the `files` directory.
+pub fn is_archive(package_name: &str) -> bool {
+    Path::new(package_name)
+        .extension()
+        .is_some_and(|extension| extension == ARCHIVE_EXTENSION)
+}
+
+/// Extracts the archive into `extract_path` after validating its structure and checksums, and
+/// returns the package it contains.
+pub fn read_archive(
+    archive_path: &Path,
+    extract_path: &Path,
+) -> Result<RemotePackage, ArchiveError> {
+    debug!("Reading package archive {archive_path:?}");
+
+    if extract_path.exists() {
+        fs::remove_dir_all(extract_path)?;
+    }
+    fs::create_dir_all(extract_path)?;
+
+    let mut archive = tar::Archive::new(File::open(archive_path)?);
+    let mut extracted_files = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = validate_entry_path(&entry.path()?)?;
+
+        trace!("Extracting archive entry {entry_path:?}");
+
+        if entry.header().entry_type().is_file() {
+            extracted_files.push(entry_path);
+        }
+
+        entry.unpack_in(extract_path)?;
+    }
+
+    for required_entry in [MANIFEST_PATH, CHECKSUMS_PATH] {
+        if !extract_path.join(required_entry).is_file() {
+            return Err(ArchiveError::MissingEntry(required_entry));
+        }
+    }
+
+    verify_checksums(extract_path, &extracted_files)?;
+
+    let manifest = fs::read_to_string(extract_path.join(MANIFEST_PATH))?;
+    let mut package = RemotePackage::from_json(&manifest)?;
+
+    let files_directory = extract_path.join(FILES_DIRECTORY);
+    if files_directory.is_dir() {
+        package.archive_files = Some(files_directory.to_string_lossy().into_owned());
+    }
+
+    Ok(package)
+}
+
+/// Writes an archive of the installed package, with the binaries it registered, and its files,
+/// installed under `root`, to `archive_path`. Fails if any of the tracked files of the package is
+/// missing. Symlinks are archived as links, not as the files they point to.
+pub fn write_archive(
+    package: &LocalPackage,
+    binaries: &[String],
+    root: Option<&Path>,
+    archive_path: &Path,
+) -> Result<(), ArchiveError> {
+    debug!("Writing package archive {archive_path:?}");
+
+    let mut files = Vec::new();
+    for package_file in package.package_files.iter() {
+        let installed_file = PathBuf::from(config::rooted(root, &package_file.path));
+        if fs::symlink_metadata(&installed_file).is_err() {
+            return Err(ArchiveError::MissingFile(package_file.path.clone()));
+        }
+
+        collect_files(
+            &installed_file,
+            &get_archive_path(&package_file.path),
+            &mut files,
+        )?;
+    }
+
+    // Install commands are not needed, as the archive already contains the installed files
+    let manifest = serde_json::to_string_pretty(&json!({
+        "manifest_version": MANIFEST_VERSION,
+        "package_data": package.package_data,
+        "dependencies": package.dependencies,
+        "replaces": package.replaces,
+        "binaries": binaries,
+        "arch": package.arch,
+        "os": package.os,
+        "install": [],
+        "pre_remove": package.pre_remove,
+        "post_remove": package.post_remove,
+    }))?;
+
+    let mut checksums = format!(
+        "{}  {MANIFEST_PATH}\n",
+        get_data_checksum(manifest.as_bytes())
+    );
+    let regular_files = files
+        .iter()
+        .filter(|(file, _)| fs::symlink_metadata(file).is_ok_and(|metadata| metadata.is_file()));
+    for (file, archived_file) in regular_files {
+        checksums.push_str(&format!(
+            "{}  {}\n",
+            get_checksum(file)?,
+            archived_file.to_string_lossy()
+        ));
+    }
+
+    let mut builder = tar::Builder::new(File::create(archive_path)?);
+    builder.follow_symlinks(false);
+    append_data(&mut builder, MANIFEST_PATH, manifest.as_bytes())?;
+    append_data(&mut builder, CHECKSUMS_PATH, checksums.as_bytes())?;
+
+    for (file, archived_file) in files.iter() {
+        trace!("Adding {file:?} to the archive");
+        builder.append_path_with_name(file, archived_file)?;
+    }
+
+    builder.finish()?;
+
+    Ok(())
+}
+
+/// Adds the path along with its path in the archive, and if it's a directory everything inside of
+/// it, to `files`.
+fn collect_files(
+    path: &Path,
+    archived_path: &Path,
+    files: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), io::Error> {
+    files.push((PathBuf::from(path), PathBuf::from(archived_path)));
+
+    // Linked directories are archived as links, like other symlinks
+    if fs::symlink_metadata(path)?.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            collect_files(&entry.path(), &archived_path.join(entry.file_name()), files)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Path of the recorded package file in the archive, relative to the root it's installed into.
+fn get_archive_path(recorded_path: &str) -> PathBuf {
+    let recorded_path = Path::new(recorded_path);
+    Path::new(FILES_DIRECTORY).join(recorded_path.strip_prefix("/").unwrap_or(recorded_path))
+}
+
+fn append_data(builder: &mut tar::Builder<File>, path: &str, data: &[u8]) -> Result<(), io::Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, path, data)
+}
+
+/// Returns the path without `.` components, if it is the manifest, checksums or inside the files
+/// directory.
+fn validate_entry_path(path: &Path) -> Result<PathBuf, ArchiveError> {
+    let invalid_entry = || ArchiveError::InvalidEntry(path.to_string_lossy().into_owned());
+
+    let mut normalized_path = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => continue,
+            Component::Normal(component) => normalized_path.push(component),
+            _ => return Err(invalid_entry()),
+        }
+    }
+
+    let is_valid = normalized_path.as_os_str().is_empty()
+        || normalized_path == Path::new(MANIFEST_PATH)
+        || normalized_path == Path::new(CHECKSUMS_PATH)
+        || normalized_path.starts_with(FILES_DIRECTORY);
+
+    if is_valid {
+        Ok(normalized_path)
+    } else {
+        Err(invalid_entry())
+    }
+}
+
+fn verify_checksums(extract_path: &Path, files: &[PathBuf]) -> Result<(), ArchiveError> {
+    let checksums_content = fs::read_to_string(extract_path.join(CHECKSUMS_PATH))?;
+
+    let mut checksums: HashMap<PathBuf, String> = HashMap::new();
+    for line in checksums_content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+    {
+        let (checksum, path) = match line.split_once(char::is_whitespace) {
+            Some((checksum, path)) => (checksum, path.trim_start()),
+            None => return Err(ArchiveError::InvalidChecksums(String::from(line))),
+        };
+
+        checksums.insert(
+            validate_entry_path(Path::new(path))?,
+            checksum.to_lowercase(),
+        );
+    }
+
+    for file in files {
+        if file == Path::new(CHECKSUMS_PATH) {
+            continue;
+        }
+
+        let file_name = file.to_string_lossy().into_owned();
+
+        let expected_checksum = match checksums.get(file) {
+            Some(checksum) => checksum,
+            None => return Err(ArchiveError::MissingChecksum(file_name)),
+        };
+
+        if &get_checksum(&extract_path.join(file))? != expected_checksum {
+            return Err(ArchiveError::ChecksumMismatch(file_name));
+        }
+    }
+
+    Ok(())
+}
+
+fn get_data_checksum(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Returns the hex encoded SHA-256 of the file.
+pub fn get_checksum(path: &Path) -> Result<String, io::Error> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,199 @@
+use crate::action::{Action, BuildOptions};
+use crate::package::{PackageData, PackageFile};
+
+use super::*;
+
+const ARCHIVES_DIRECTORY: &str = "/tmp/japm/tests/archives/";
+/// Files of the test packages are installed under this directory instead of the actual root
+const INSTALL_ROOT: &str = "/tmp/japm/tests/archive_root";
+const PACKAGE_FILE: &str = "tmp/japm/tests/archive_root/test-archive-file";
+const PACKAGE_FILE_CONTENT: &[u8] = b"installed from an archive\n";
+
+#[test]
+fn test_archive_installed_offline() {
+    if Path::new(INSTALL_ROOT).exists() {
+        fs::remove_dir_all(INSTALL_ROOT).expect("Could not remove previous install root");
+    }
+
+    let archive_path = build_test_archive("test-archive-package", true);
+
+    let package = read_archive(
+        &archive_path,
+        &Path::new(ARCHIVES_DIRECTORY).join("test-archive-package"),
+    )
+    .unwrap();
+
+    assert_eq!(package.package_data.name, "test-archive-package");
+
+    let mut action = Action::Install(Box::new(package));
+    action
+        .build("/tmp/japm/test", &BuildOptions::default())
+        .unwrap();
+
+    let installed_file = Path::new("/").join(PACKAGE_FILE);
+    assert_eq!(fs::read(installed_file).unwrap(), PACKAGE_FILE_CONTENT);
+
+    match action {
+        Action::Install(package) => {
+            assert_eq!(package.package_files, vec![PackageFile::from(INSTALL_ROOT)])
+        }
+        Action::Remove(_) => unreachable!(),
+    }
+
+    fs::remove_dir_all(INSTALL_ROOT).expect("Could not cleanup install root");
+}
+
+#[test]
+fn test_archive_with_wrong_checksum_rejected() {
+    let archive_path = build_test_archive("test-tampered-archive-package", false);
+
+    let result = read_archive(
+        &archive_path,
+        &Path::new(ARCHIVES_DIRECTORY).join("test-tampered-archive-package"),
+    );
+
+    assert!(matches!(result, Err(ArchiveError::ChecksumMismatch(_))));
+}
+
+#[test]
+fn test_written_archive_installs_back() {
+    const SOURCE_ROOT: &str = "/tmp/japm/tests/written_archive_root";
+
+    if Path::new(SOURCE_ROOT).exists() {
+        fs::remove_dir_all(SOURCE_ROOT).expect("Could not remove previous source root");
+    }
+    fs::create_dir_all(format!("{SOURCE_ROOT}/bin")).unwrap();
+    fs::write(format!("{SOURCE_ROOT}/bin/app"), PACKAGE_FILE_CONTENT).unwrap();
+    std::os::unix::fs::symlink("app", format!("{SOURCE_ROOT}/bin/app-link")).unwrap();
+    std::os::unix::fs::symlink("bin", format!("{SOURCE_ROOT}/linked-bin")).unwrap();
+
+    let package = LocalPackage {
+        id: Some(1),
+        package_data: PackageData {
+            name: String::from("test-written-archive-package"),
+            version: String::from("1.2.3"),
+            description: String::from("Written to an archive"),
+            ..Default::default()
+        },
+        dependencies: vec![],
+        replaces: vec![String::from("test-replaced-archive-package")],
+        source_remote: None,
+        arch: vec![String::from(std::env::consts::ARCH)],
+        os: vec![String::from(std::env::consts::OS)],
+        pre_remove: vec![],
+        package_files: vec![PackageFile::from(SOURCE_ROOT)],
+        post_remove: vec![String::from("true")],
+    };
+    let binaries = vec![String::from("test-written-archive-app")];
+
+    fs::create_dir_all(ARCHIVES_DIRECTORY).unwrap();
+    let archive_path = Path::new(ARCHIVES_DIRECTORY).join("test-written-archive-package.japm");
+    write_archive(&package, &binaries, None, &archive_path).unwrap();
+
+    // The archive is the only source of the files now
+    fs::remove_dir_all(SOURCE_ROOT).unwrap();
+
+    let remote_package = read_archive(
+        &archive_path,
+        &Path::new(ARCHIVES_DIRECTORY).join("test-written-archive-package"),
+    )
+    .unwrap();
+
+    assert_eq!(remote_package.package_data, package.package_data);
+    assert_eq!(remote_package.post_remove, package.post_remove);
+    assert_eq!(remote_package.replaces, package.replaces);
+    assert_eq!(remote_package.binaries, binaries);
+    assert_eq!(remote_package.arch, package.arch);
+    assert_eq!(remote_package.os, package.os);
+
+    let mut action = Action::Install(Box::new(remote_package));
+    action
+        .build("/tmp/japm/test", &BuildOptions::default())
+        .unwrap();
+
+    assert_eq!(
+        fs::read(format!("{SOURCE_ROOT}/bin/app")).unwrap(),
+        PACKAGE_FILE_CONTENT
+    );
+    for (link, target) in [("bin/app-link", "app"), ("linked-bin", "bin")] {
+        assert_eq!(
+            fs::read_link(format!("{SOURCE_ROOT}/{link}")).unwrap(),
+            Path::new(target)
+        );
+    }
+
+    fs::remove_dir_all(SOURCE_ROOT).expect("Could not cleanup source root");
+}
+
+#[test]
+fn test_archive_of_package_with_missing_files_rejected() {
+    let package = LocalPackage {
+        id: Some(1),
+        package_data: PackageData {
+            name: String::from("test-incomplete-package"),
+            ..Default::default()
+        },
+        dependencies: vec![],
+        replaces: vec![],
+        source_remote: None,
+        arch: vec![],
+        os: vec![],
+        pre_remove: vec![],
+        package_files: vec![PackageFile::from("/tmp/japm/tests/does-not-exist")],
+        post_remove: vec![],
+    };
+
+    fs::create_dir_all(ARCHIVES_DIRECTORY).unwrap();
+    let result = write_archive(
+        &package,
+        &[],
+        None,
+        &Path::new(ARCHIVES_DIRECTORY).join("test-incomplete-package.japm"),
+    );
+
+    assert!(matches!(result, Err(ArchiveError::MissingFile(_))));
+}
+
+#[test]
+fn test_entries_outside_archive_layout_rejected() {
+    assert!(validate_entry_path(Path::new("./files/usr/bin/app")).is_ok());
+    assert!(validate_entry_path(Path::new("../package.json")).is_err());
+    assert!(validate_entry_path(Path::new("/package.json")).is_err());
+    assert!(validate_entry_path(Path::new("other/file")).is_err());
+}
+
+fn build_test_archive(package_name: &str, valid_checksums: bool) -> PathBuf {
+    fs::create_dir_all(ARCHIVES_DIRECTORY).expect("Could not create test directory");
+
+    let archive_path = Path::new(ARCHIVES_DIRECTORY).join(format!("{package_name}.japm"));
+
+    let manifest = format!(
+        r#"{{ "manifest_version": 1, "package_data": {{ "name": "{package_name}", "version": "0.0.1", "description": "" }}, "install": [] }}"#
+    );
+    let files_path = format!("{FILES_DIRECTORY}/{PACKAGE_FILE}");
+
+    let package_file_checksum = if valid_checksums {
+        format!("{:x}", Sha256::digest(PACKAGE_FILE_CONTENT))
+    } else {
+        format!("{:x}", Sha256::digest(b"something else"))
+    };
+    let checksums = format!(
+        "{:x}  {MANIFEST_PATH}\n{package_file_checksum}  {files_path}\n",
+        Sha256::digest(manifest.as_bytes())
+    );
+
+    let mut builder = tar::Builder::new(File::create(&archive_path).unwrap());
+    for (path, content) in [
+        (MANIFEST_PATH, manifest.as_bytes()),
+        (CHECKSUMS_PATH, checksums.as_bytes()),
+        (files_path.as_str(), PACKAGE_FILE_CONTENT),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, content).unwrap();
+    }
+    builder.finish().unwrap();
+
+    archive_path
+}
@@ -1,15 +1,24 @@
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use async_recursion::async_recursion;
 
-use log::{debug, info, trace};
+use log::{debug, info, trace, warn};
 
 use linked_hash_map::LinkedHashMap;
 use semver::Version;
+use serde::Serialize;
 
 use crate::action::Action;
+use crate::archive;
+use crate::config;
 use crate::db::PackagesDb;
-use crate::package::{LocalPackage, RemotePackage};
+use crate::messages::Message;
+use crate::package::{LocalPackage, PackageData, PackageFile, RemotePackage};
 use crate::package_finder::PackageFinder;
 use crate::progress::{self, ProgressType};
 
@@ -26,21 +35,117 @@ mod tests;
 pub enum ReinstallOptions {
     Update,
     ForceReinstall,
+    /// Reinstall installed packages over their installed version without removing them first.
+    /// Files that are not installed anymore are deleted once the new version is built.
+    Overlay,
     Ignore,
 }
 
+/// Options that change how already installed packages are handled, regardless of
+/// [ReinstallOptions].
+#[derive(Default)]
+pub struct InstallOptions {
+    /// Explain why already installed packages are skipped
+    pub explain_skip: bool,
+    /// Reinstall installed packages that are missing some of their files even if they would be
+    /// skipped otherwise. Unlike [ReinstallOptions::ForceReinstall] intact packages are left alone.
+    pub reinstall_if_files_missing: bool,
+    /// Plan the explicitly given packages in the given order, each after its dependencies. By
+    /// default a package planned again is moved after the packages planned before.
+    pub preserve_order: bool,
+    /// Also apply [ReinstallOptions::ForceReinstall] and [ReinstallOptions::Overlay] to the
+    /// dependencies of the packages. By default installed dependencies are left alone.
+    pub reinstall_dependencies: bool,
+    /// Directory the packages are installed into, / if not set
+    pub root: Option<PathBuf>,
+}
+
+/// Why an already installed package was not reinstalled.
+#[derive(Debug, PartialEq)]
+pub enum SkipReason {
+    /// Contains the installed version
+    AlreadyInstalled(String),
+    NotNewer {
+        local: String,
+        remote: String,
+    },
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::AlreadyInstalled(version) => write!(
+                f,
+                "already installed at version {version}, use --reinstall to reinstall it"
+            ),
+            SkipReason::NotNewer { local, remote } => write!(
+                f,
+                "installed version {local} is not older than remote version {remote}"
+            ),
+        }
+    }
+}
+
+/// Splits a `name@version` package into its name and version. The suffix is only taken as a
+/// version if it is valid semver, so that paths containing `@` are left untouched.
+pub fn parse_package_spec(package: &str) -> (&str, Option<&str>) {
+    match package.rsplit_once('@') {
+        Some((name, version)) if !name.is_empty() && Version::parse(version).is_ok() => {
+            (name, Some(version))
+        }
+        _ => (package, None),
+    }
+}
+
 pub async fn install_packages<EFind: Error, EDatabase: Error>(
     packages: Vec<String>,
     package_finder: &mut impl PackageFinder<Error = EFind>,
     reinstall_options: &ReinstallOptions,
+    install_options: &InstallOptions,
     db: &mut impl PackagesDb<GetError = EDatabase>,
 ) -> Result<Vec<Action>, InstallError<EDatabase, EFind>> {
     let mut actions: LinkedHashSet<Action> = LinkedHashSet::new();
+    let mut resolved = HashSet::new();
+
+    // Requested versions also apply when the package is a dependency of another requested one
+    let mut requested_versions: HashMap<&str, &str> = HashMap::new();
+    for package in packages.iter() {
+        let (package_name, Some(version)) = parse_package_spec(package) else {
+            continue;
+        };
+
+        match requested_versions.insert(package_name, version) {
+            Some(requested) if requested != version => {
+                return Err(InstallError::ConflictingVersions {
+                    name: String::from(package_name),
+                    versions: (String::from(requested), String::from(version)),
+                })
+            }
+            _ => (),
+        }
+    }
 
     progress::increment_target(ProgressType::Packages, packages.len() as i32).await;
 
-    for package_name in packages.iter() {
-        actions.extend(install_package(package_name, package_finder, reinstall_options, db).await?);
+    for package in packages.iter() {
+        let (package_name, version) = parse_package_spec(package);
+        let package_actions = install_package(
+            package_name,
+            version,
+            package_finder,
+            reinstall_options,
+            install_options,
+            &requested_versions,
+            &mut resolved,
+            db,
+        )
+        .await?;
+
+        if install_options.preserve_order {
+            extend_keeping_order(&mut actions, package_actions);
+        } else {
+            actions.extend(package_actions);
+        }
 
         progress::increment_completed(ProgressType::Packages, 1).await;
     }
@@ -48,9 +153,13 @@ pub async fn install_packages<EFind: Error, EDatabase: Error>(
     Ok(actions.keys().cloned().collect())
 }
 
+/// Removes the packages. Packages depending on them are also removed if `recursive` is set,
+/// otherwise they break the removal unless `force` is set, which leaves them installed with a
+/// missing dependency.
 pub async fn remove_packages<EDatabase: Error>(
     package_names: Vec<String>,
     recursive: bool,
+    force: bool,
     db: &mut impl PackagesDb<GetError = EDatabase>,
 ) -> Result<Vec<Action>, RemoveError<EDatabase>> {
     let mut actions: LinkedHashSet<Action> = LinkedHashSet::new();
@@ -58,15 +167,79 @@ pub async fn remove_packages<EDatabase: Error>(
     progress::increment_target(ProgressType::Packages, package_names.len() as i32).await;
 
     for package_name in package_names.into_iter() {
-        actions.extend(remove_package(&package_name, recursive, db).await?);
+        let package_actions = remove_package(&package_name, recursive, force, db).await?;
+        extend_keeping_order(&mut actions, package_actions);
         progress::increment_completed(ProgressType::Packages, 1).await;
     }
 
     Ok(actions.keys().cloned().collect())
 }
 
+/// A file that removing its package would delete
+#[derive(Debug, PartialEq)]
+pub struct RemovedFile {
+    pub path: String,
+    /// False if the file is listed in the database but already gone from the disk
+    pub exists: bool,
+}
+
+/// What removing a package would delete, as reported by `remove --dry-run`.
+#[derive(Debug, PartialEq)]
+pub struct RemovalPreview {
+    pub package: String,
+    pub files: Vec<RemovedFile>,
+}
+
+impl Display for RemovalPreview {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Would remove {}", self.package)?;
+
+        for file in self.files.iter() {
+            write!(f, "\n    {}", file.path)?;
+            if !file.exists {
+                write!(f, " (already missing)")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Plans the removal like [remove_packages] and returns the files every removed package would
+/// delete from `root`, without deleting anything.
+pub async fn simulate_remove<EDatabase: Error>(
+    package_names: Vec<String>,
+    recursive: bool,
+    force: bool,
+    root: Option<&Path>,
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<Vec<RemovalPreview>, RemoveError<EDatabase>> {
+    let actions = remove_packages(package_names, recursive, force, db).await?;
+
+    Ok(actions
+        .into_iter()
+        .filter_map(|action| match action {
+            Action::Remove(package) => Some(RemovalPreview {
+                package: package.package_data.name,
+                files: package
+                    .package_files
+                    .into_iter()
+                    .map(|file| RemovedFile {
+                        exists: installed_path(&file, root).exists(),
+                        path: file.path,
+                    })
+                    .collect(),
+            }),
+            Action::Install(_) => None,
+        })
+        .collect())
+}
+
+/// Updates every installed package, except the ones in `exclude`.
 pub async fn update_all_packages<EDatabase: Error, EFind: Error>(
+    exclude: Vec<String>,
     package_finder: &mut impl PackageFinder<Error = EFind>,
+    install_options: &InstallOptions,
     db: &mut impl PackagesDb<GetError = EDatabase>,
 ) -> Result<Vec<Action>, UpdateError<EDatabase, EFind>> {
     let packages = match db.get_all_packages() {
@@ -74,9 +247,26 @@ pub async fn update_all_packages<EDatabase: Error, EFind: Error>(
         Err(error) => return Err(UpdateError::DatabaseGet(error)),
     };
 
-    let packages = packages.into_iter().map(|p| p.package_data.name).collect();
+    let packages = packages
+        .into_iter()
+        .map(|p| p.package_data.name)
+        .filter(|name| {
+            let excluded = exclude.contains(name);
+            if excluded {
+                debug!("Package {name} is excluded from the update");
+            }
+            !excluded
+        })
+        .collect();
 
-    let actions = install_packages(packages, package_finder, &ReinstallOptions::Update, db).await?;
+    let actions = install_packages(
+        packages,
+        package_finder,
+        &ReinstallOptions::Update,
+        install_options,
+        db,
+    )
+    .await?;
 
     Ok(actions)
 }
@@ -84,10 +274,17 @@ pub async fn update_all_packages<EDatabase: Error, EFind: Error>(
 pub async fn update_packages<EDatabase: Error, EFind: Error>(
     package_names: Vec<String>,
     package_finder: &mut impl PackageFinder<Error = EFind>,
+    install_options: &InstallOptions,
     db: &mut impl PackagesDb<GetError = EDatabase>,
 ) -> Result<Vec<Action>, UpdateError<EDatabase, EFind>> {
     let mut actions: Vec<Action> = Vec::new();
     for package_name in package_names.into_iter() {
+        match db.contains(&package_name) {
+            Ok(true) => (),
+            Ok(false) => return Err(UpdateError::PackageNotInstalled(package_name)),
+            Err(error) => return Err(UpdateError::DatabaseGet(error)),
+        }
+
         let depending = match get_depending(&package_name, db, -1) {
             Ok(depending) => depending,
             Err(error) => return Err(UpdateError::DatabaseGet(error)),
@@ -103,6 +300,7 @@ pub async fn update_packages<EDatabase: Error, EFind: Error>(
                 packages_to_update,
                 package_finder,
                 &ReinstallOptions::Update,
+                install_options,
                 db,
             )
             .await?,
@@ -112,108 +310,778 @@ pub async fn update_packages<EDatabase: Error, EFind: Error>(
     Ok(actions)
 }
 
-pub fn print_package_info<EDatabase: Error>(
+/// An installed package with a newer version available on the remotes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct AvailableUpdate {
+    pub name: String,
+    pub installed_version: String,
+    pub available_version: String,
+}
+
+impl Display for AvailableUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} -> {}",
+            self.name, self.installed_version, self.available_version
+        )
+    }
+}
+
+/// Remembers the updates found by the previous [Self::check], so that every check only reports
+/// the updates that became available since.
+#[derive(Default)]
+pub struct UpdateWatcher {
+    reported: HashSet<AvailableUpdate>,
+}
+
+impl UpdateWatcher {
+    pub async fn check<EDatabase: Error, EFind: Error>(
+        &mut self,
+        package_names: Vec<String>,
+        package_finder: &mut impl PackageFinder<Error = EFind>,
+        db: &mut impl PackagesDb<GetError = EDatabase>,
+    ) -> Result<Vec<AvailableUpdate>, UpdateError<EDatabase, EFind>> {
+        let updates = find_available_updates(package_names, package_finder, db).await?;
+
+        let new_updates = updates
+            .iter()
+            .filter(|update| !self.reported.contains(update))
+            .cloned()
+            .collect();
+
+        // Updates that are not available anymore are forgotten, and reported again if they return
+        self.reported = updates.into_iter().collect();
+
+        Ok(new_updates)
+    }
+}
+
+/// Returns the updates available for the packages without generating any actions, all installed
+/// packages are checked if none are given.
+pub async fn find_available_updates<EDatabase: Error, EFind: Error>(
     package_names: Vec<String>,
+    package_finder: &mut impl PackageFinder<Error = EFind>,
     db: &mut impl PackagesDb<GetError = EDatabase>,
-) -> Result<(), InfoError<EDatabase>> {
-    for package_name in package_names.into_iter() {
-        let package = db.get_package(&package_name)?;
-        if package.is_none() {
-            return Err(InfoError::PackageNotInstalled(package_name));
+) -> Result<Vec<AvailableUpdate>, UpdateError<EDatabase, EFind>> {
+    let packages = if package_names.is_empty() {
+        db.get_all_packages().map_err(UpdateError::DatabaseGet)?
+    } else {
+        let mut packages = Vec::new();
+        for package_name in package_names.into_iter() {
+            match db.get_package(&package_name) {
+                Ok(Some(package)) => packages.push(package),
+                Ok(None) => return Err(UpdateError::PackageNotInstalled(package_name)),
+                Err(error) => return Err(UpdateError::DatabaseGet(error)),
+            }
         }
+        packages
+    };
+
+    let mut updates = Vec::new();
+    for local_package in packages.into_iter() {
+        let name = &local_package.package_data.name;
+
+        let remote_package = match package_finder.find_package(name).await {
+            Ok(Some(remote_package)) => remote_package,
+            Ok(None) => {
+                debug!("Package {name} is not available on any remote");
+                continue;
+            }
+            Err(error) => return Err(UpdateError::Find(error)),
+        };
+
+        if remote_is_newer(&remote_package, &local_package) {
+            updates.push(AvailableUpdate {
+                name: name.clone(),
+                installed_version: local_package.package_data.version.clone(),
+                available_version: remote_package.package_data.version,
+            });
+        }
+    }
+
+    Ok(updates)
+}
 
-        let package = package.unwrap();
+/// Returns the name, installed version and remote version of the installed packages with a
+/// newer version on the remotes, without generating any actions. Packages the remotes fail to
+/// return are warned about and skipped.
+pub async fn list_outdated<EDatabase: Error, EFind: Error>(
+    package_finder: &mut impl PackageFinder<Error = EFind>,
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<Vec<(String, String, String)>, EDatabase> {
+    let mut outdated = Vec::new();
+    for local_package in db.get_all_packages()?.into_iter() {
+        let name = local_package.package_data.name.clone();
+
+        let remote_package = match package_finder.find_package(&name).await {
+            Ok(Some(remote_package)) => remote_package,
+            Ok(None) => {
+                debug!("Package {name} is not available on any remote");
+                continue;
+            }
+            Err(error) => {
+                warn!("Could not find package {name}, skipping it: {error}");
+                continue;
+            }
+        };
+
+        if remote_is_newer(&remote_package, &local_package) {
+            outdated.push((
+                name,
+                local_package.package_data.version,
+                remote_package.package_data.version,
+            ));
+        }
+    }
 
-        info!(
-            "Package {package_name}:
+    Ok(outdated)
+}
+
+/// Versions of the package available on the remotes, newest first.
+pub async fn list_versions<EFind: Error>(
+    package_name: &str,
+    package_finder: &mut impl PackageFinder<Error = EFind>,
+) -> Result<Vec<Version>, EFind> {
+    let mut versions = package_finder.available_versions(package_name).await?;
+    versions.sort_by(|a, b| b.cmp(a));
+    versions.dedup();
+
+    Ok(versions)
+}
+
+/// Bytes the install actions of a plan download, see [estimated_download_size].
+#[derive(Debug, Default, PartialEq)]
+pub struct DownloadSize {
+    /// Sum of the known sizes
+    pub bytes: u64,
+    /// Packages whose size is unknown, the total is at least [Self::bytes] if there are any
+    pub unknown: usize,
+}
+
+impl Display for DownloadSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.unknown == 0 {
+            write!(f, "{} bytes", self.bytes)
+        } else {
+            write!(
+                f,
+                "≥ {} bytes ({} packages of unknown size)",
+                self.bytes, self.unknown
+            )
+        }
+    }
+}
+
+/// Sums the download sizes of the installed packages, removals download nothing.
+pub async fn estimated_download_size<EFind: Error>(
+    actions: &[Action],
+    package_finder: &mut impl PackageFinder<Error = EFind>,
+) -> Result<DownloadSize, EFind> {
+    let mut size = DownloadSize::default();
+
+    for action in actions.iter() {
+        let Action::Install(package) = action else {
+            continue;
+        };
+
+        match package_finder.download_size(package).await? {
+            Some(bytes) => size.bytes += bytes,
+            None => size.unknown += 1,
+        }
+    }
+
+    Ok(size)
+}
+
+/// Returns the packages the given packages depend on directly or transitively, in the order they
+/// are found. Only the dependencies are requested from the finder, no actions are resolved.
+pub async fn find_dependencies<EFind: Error>(
+    packages: Vec<String>,
+    package_finder: &mut impl PackageFinder<Error = EFind>,
+) -> Result<Vec<String>, DependsError<EFind>> {
+    let mut dependencies = Vec::new();
+    let mut searched: HashSet<String> = HashSet::new();
+    // Searched depth first, popping from the end
+    let mut to_search: Vec<String> = packages.iter().rev().cloned().collect();
+
+    while let Some(package_name) = to_search.pop() {
+        if !searched.insert(package_name.clone()) {
+            continue;
+        }
+
+        let package_dependencies = match package_finder.dependencies_of(&package_name).await {
+            Ok(Some(package_dependencies)) => package_dependencies,
+            Ok(None) => return Err(DependsError::PackageNotFound(package_name)),
+            Err(error) => return Err(DependsError::Find(error)),
+        };
+
+        // Reversed so that dependencies are searched in the order they are listed
+        for dependency in package_dependencies.into_iter().rev() {
+            to_search.push(dependency);
+        }
+        if !packages.contains(&package_name) {
+            dependencies.push(package_name);
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// Returns the versions the explicitly requested packages resolved to, from the install actions
+/// or from the database for the ones already installed. Dependencies are left out, and pinned
+/// `name@version` packages are recorded by name.
+pub fn explicit_package_versions<EDatabase: Error>(
+    packages: &[String],
+    actions: &[Action],
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<Vec<(String, String)>, EDatabase> {
+    let mut versions = Vec::new();
+
+    for package in packages {
+        let (package_name, _) = parse_package_spec(package);
+        let installed_version = actions.iter().find_map(|action| match action {
+            Action::Install(package) if package.package_data.name == package_name => {
+                Some(package.package_data.version.clone())
+            }
+            _ => None,
+        });
+
+        let version = match installed_version {
+            Some(version) => Some(version),
+            None => db
+                .get_package(package_name)?
+                .map(|package| package.package_data.version),
+        };
+
+        match version {
+            Some(version) => versions.push((String::from(package_name), version)),
+            None => debug!("{package_name} is not installed, not recording it"),
+        }
+    }
+
+    Ok(versions)
+}
+
+/// An installed package as displayed by `info`, without its scripts and files.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PackageInfo {
+    #[serde(flatten)]
+    pub package_data: PackageData,
+    pub dependencies: Vec<String>,
+    pub replaces: Vec<String>,
+    pub held: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_remote: Option<String>,
+}
+
+impl From<LocalPackage> for PackageInfo {
+    fn from(package: LocalPackage) -> Self {
+        PackageInfo {
+            package_data: package.package_data,
+            dependencies: package.dependencies,
+            replaces: package.replaces,
+            held: false,
+            source_remote: package.source_remote,
+        }
+    }
+}
+
+/// Optional fields are only included if present.
+impl Display for PackageInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let package_data = &self.package_data;
+
+        write!(
+            f,
+            "Package {}:
     version: {}
     description: {}
-    dependencies: {:?}",
-            package.package_data.version, package.package_data.description, package.dependencies
-        );
+    dependencies: {:?}
+    held: {}",
+            package_data.name,
+            package_data.version,
+            package_data.description,
+            self.dependencies,
+            self.held
+        )?;
+
+        let optional_fields = [
+            ("homepage", &package_data.homepage),
+            ("license", &package_data.license),
+            ("maintainer", &package_data.maintainer),
+            ("source remote", &self.source_remote),
+        ];
+        for (field, value) in optional_fields {
+            if let Some(value) = value {
+                write!(f, "\n    {field}: {value}")?;
+            }
+        }
+        if !self.replaces.is_empty() {
+            write!(f, "\n    replaces: {:?}", self.replaces)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn get_package_info<EDatabase: Error>(
+    package_names: Vec<String>,
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<Vec<PackageInfo>, InfoError<EDatabase>> {
+    let mut infos = Vec::new();
+
+    for package_name in package_names.into_iter() {
+        match db.get_package(&package_name)? {
+            Some(package) => {
+                let mut info = PackageInfo::from(package);
+                info.held = db.is_held(&package_name)?;
+                infos.push(info);
+            }
+            None => return Err(InfoError::PackageNotInstalled(package_name)),
+        }
     }
 
+    Ok(infos)
+}
+
+/// Holds the installed packages back from updates, or releases them if `held` is false.
+pub fn set_held<EDatabase: Error>(
+    package_names: Vec<String>,
+    held: bool,
+    db: &mut impl PackagesDb<GetError = EDatabase, AddError = EDatabase>,
+) -> Result<(), HoldError<EDatabase>> {
+    for package_name in package_names.into_iter() {
+        if !db.contains(&package_name)? {
+            return Err(HoldError::PackageNotInstalled(package_name));
+        }
+
+        db.set_held(&package_name, held)?;
+
+        if held {
+            info!("Holding package {package_name}");
+        } else {
+            info!("Released hold of package {package_name}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the name of the installed package that installed the path, either directly or as part
+/// of an installed directory. The path is a path of this system, so it includes `root`.
+pub fn find_owner<EDatabase: Error>(
+    path: &str,
+    root: Option<&Path>,
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<Option<String>, EDatabase> {
+    let path = canonicalize(Path::new(path));
+
+    for package in db.get_all_packages()?.into_iter() {
+        let owns_path = package.package_files.iter().any(|package_file| {
+            path.starts_with(canonicalize(&installed_path(package_file, root)))
+        });
+
+        if owns_path {
+            return Ok(Some(package.package_data.name));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the names of all installed packages, used by the completion scripts.
+pub fn installed_package_names<EDatabase: Error>(
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<Vec<String>, EDatabase> {
+    Ok(db
+        .get_all_packages()?
+        .into_iter()
+        .map(|package| package.package_data.name)
+        .collect())
+}
+
+/// Returns the name of the installed package that registered the command as one of its binaries.
+pub fn which<EDatabase: Error>(
+    command: &str,
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<Option<String>, EDatabase> {
+    db.get_binary_owner(command)
+}
+
+/// Canonicalizes the path if it exists, otherwise only makes it absolute.
+fn canonicalize(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    match std::env::current_dir() {
+        Ok(current_dir) if path.is_relative() => current_dir.join(path),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Problems found with an installed package by [check_packages].
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PackageHealth {
+    pub name: String,
+    pub missing_files: Vec<String>,
+    /// Files whose content changed since they were installed
+    pub modified_files: Vec<String>,
+    /// Dependencies that are not installed
+    pub missing_dependencies: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CheckReport {
+    pub healthy: bool,
+    pub checked_packages: usize,
+    pub unhealthy_packages: Vec<PackageHealth>,
+}
+
+/// Checks that the files and dependencies of every installed package are present under `root`
+/// and that the files are unmodified, without modifying anything.
+pub fn check_packages<EDatabase: Error>(
+    root: Option<&Path>,
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<CheckReport, EDatabase> {
+    let packages = db.get_all_packages()?;
+    let checked_packages = packages.len();
+
+    let mut unhealthy_packages = Vec::new();
+    for package in packages.into_iter() {
+        let missing_files = get_missing_files(&package, root);
+        let modified_files = get_modified_files(&package, root);
+
+        let mut missing_dependencies = Vec::new();
+        for dependency in package.dependencies.iter() {
+            if !db.contains(dependency)? {
+                missing_dependencies.push(dependency.clone());
+            }
+        }
+
+        if !missing_files.is_empty()
+            || !modified_files.is_empty()
+            || !missing_dependencies.is_empty()
+        {
+            unhealthy_packages.push(PackageHealth {
+                name: package.package_data.name,
+                missing_files,
+                modified_files,
+                missing_dependencies,
+            });
+        }
+    }
+
+    Ok(CheckReport {
+        healthy: unhealthy_packages.is_empty(),
+        checked_packages,
+        unhealthy_packages,
+    })
+}
+
+/// Files of an installed package that don't match the installed ones, found by [verify_packages].
+#[derive(Debug, PartialEq)]
+pub struct FileMismatch {
+    pub package: String,
+    pub missing_files: Vec<String>,
+    /// Files whose size or checksum changed since they were installed
+    pub modified_files: Vec<String>,
+}
+
+/// Returns the packages that are missing some of their files under `root` or whose files were
+/// modified. All installed packages are checked if `package_names` is empty.
+pub fn verify_packages<EDatabase: Error>(
+    package_names: Vec<String>,
+    root: Option<&Path>,
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<Vec<FileMismatch>, VerifyError<EDatabase>> {
+    let packages = if package_names.is_empty() {
+        db.get_all_packages()?
+    } else {
+        let mut packages = Vec::new();
+        for package_name in package_names.into_iter() {
+            match db.get_package(&package_name)? {
+                Some(package) => packages.push(package),
+                None => return Err(VerifyError::PackageNotInstalled(package_name)),
+            }
+        }
+        packages
+    };
+
+    let mut mismatches = Vec::new();
+    for package in packages.into_iter() {
+        let missing_files = get_missing_files(&package, root);
+        let modified_files = get_modified_files(&package, root);
+        if !missing_files.is_empty() || !modified_files.is_empty() {
+            mismatches.push(FileMismatch {
+                package: package.package_data.name,
+                missing_files,
+                modified_files,
+            });
+        }
+    }
+
+    Ok(mismatches)
+}
+
+/// Build directories and leftover files removed by [clean].
+#[derive(Debug, Default, PartialEq)]
+pub struct CleanReport {
+    pub removed_paths: Vec<String>,
+    pub freed_bytes: u64,
+}
+
+/// Removes the build directories left behind under `build_path` by failed builds. Packages are
+/// moved out of their build directory once built, and every build starts from an empty one, so
+/// any directory present between operations is orphaned.
+///
+/// Assumes no other japm process is running, as the build directories of its in-progress
+/// actions would be removed as well.
+pub fn clean(build_path: &Path) -> Result<CleanReport, io::Error> {
+    let mut report = CleanReport::default();
+
+    let entries = match fs::read_dir(build_path) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(report),
+        Err(error) => return Err(error),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        trace!("Removing build directory {path:?}");
+
+        report.freed_bytes += get_size(&path)?;
+        if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+        report
+            .removed_paths
+            .push(path.to_string_lossy().to_string());
+    }
+
+    Ok(report)
+}
+
+/// Size of a file, or of everything under a directory. Symlinks are not followed.
+fn get_size(path: &Path) -> Result<u64, io::Error> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += get_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+/// Writes an archive of the installed package and its files under `root`, that can be installed
+/// offline. Defaults to `<name>-<version>.japm` in the current directory.
+pub fn build_archive<EDatabase: Error>(
+    package_name: &str,
+    output: Option<String>,
+    root: Option<&Path>,
+    db: &mut impl PackagesDb<GetError = EDatabase>,
+) -> Result<(), BuildArchiveError<EDatabase>> {
+    let package = match db.get_package(package_name)? {
+        Some(package) => package,
+        None => {
+            return Err(BuildArchiveError::PackageNotInstalled(String::from(
+                package_name,
+            )))
+        }
+    };
+    let binaries = db.get_package_binaries(package_name)?;
+
+    let output = output.unwrap_or_else(|| {
+        format!(
+            "{}-{}.{}",
+            package.package_data.name,
+            package.package_data.version,
+            archive::ARCHIVE_EXTENSION
+        )
+    });
+
+    if let Err(error) = archive::write_archive(&package, &binaries, root, Path::new(&output)) {
+        return Err(BuildArchiveError::Archive(error.to_string()));
+    }
+
+    info!("Wrote archive of package {package_name} to {output}");
+
     Ok(())
 }
 
+/// Packages in `resolved` are skipped, so that a package requested both explicitly and as a
+/// dependency is only searched and planned once. It contains the names of the packages and
+/// whether they were requested to be reinstalled, so that a dependency left alone is still
+/// reinstalled when requested explicitly. Packages without a version are resolved to their
+/// version in `requested_versions` if any.
 #[async_recursion(?Send)]
+#[allow(clippy::too_many_arguments)]
 async fn install_package<EFind: Error, EDatabase: Error>(
     package_name: &str,
+    version: Option<&str>,
     package_finder: &mut impl PackageFinder<Error = EFind>,
     reinstall_options: &ReinstallOptions,
+    install_options: &InstallOptions,
+    requested_versions: &HashMap<&str, &str>,
+    resolved: &mut HashSet<(String, bool)>,
     db: &mut impl PackagesDb<GetError = EDatabase>,
 ) -> Result<LinkedHashSet<Action>, InstallError<EDatabase, EFind>> {
-    debug!("Generating install actions for package: {package_name}");
-
     let mut actions: LinkedHashSet<Action> = LinkedHashSet::new();
 
-    let remote_package = match package_finder.find_package(package_name).await {
-        Ok(package) => {
-            if package.is_none() {
-                return Err(InstallError::PackageNotFound(String::from(package_name)));
-            }
+    let version = version.or_else(|| requested_versions.get(package_name).copied());
+    let reinstall = matches!(
+        reinstall_options,
+        ReinstallOptions::ForceReinstall | ReinstallOptions::Overlay
+    );
+    if !resolved.insert((String::from(package_name), reinstall)) {
+        debug!("Package {package_name} is already resolved");
+        return Ok(actions);
+    }
 
-            package.unwrap()
+    debug!("Generating install actions for package: {package_name}");
+
+    let found_package = match version {
+        Some(version) => {
+            package_finder
+                .find_package_version(package_name, version)
+                .await
+        }
+        None => package_finder.find_package(package_name).await,
+    };
+
+    let mut remote_package = match found_package {
+        Ok(Some(package)) => package,
+        Ok(None) => {
+            return Err(match version {
+                Some(version) => InstallError::VersionNotFound {
+                    name: String::from(package_name),
+                    version: String::from(version),
+                },
+                None => InstallError::PackageNotFound(String::from(package_name)),
+            })
         }
         Err(error) => return Err(InstallError::Find(error)),
     };
 
     trace!("Found remote package:\n{remote_package:#?}");
 
+    if let Some(field) = remote_package.incompatible_field() {
+        return Err(InstallError::Incompatible {
+            name: remote_package.package_data.name,
+            field: String::from(field),
+        });
+    }
+
     match db.get_package(&remote_package.package_data.name) {
         Ok(local_package) => {
             if let Some(local_package) = local_package {
-                match reinstall_options {
-                    ReinstallOptions::ForceReinstall => {
-                        info!("Package {package_name} already installed, reinstalling...");
-                        // It's also possible to call remove_package and get the package removal specific actions.
-                        // But this can cause issues.
-                        // - First a pointless database query for existance of the packge which is already guaranteed.
-                        // - Second, all the recursive removal related issues. We reinstall a package and there's no need to check for dependency
-                        // break as we will be installing it back again.
-                        actions.insert(Action::Remove(local_package), ());
-                    }
-                    ReinstallOptions::Update => {
-                        let remote_is_newer = match remote_is_newer(&remote_package, &local_package)
-                        {
-                            Ok(res) => res,
-                            Err(error) => {
-                                return Err(InstallError::VersionParse(error.to_string()))
-                            }
-                        };
-
-                        if remote_is_newer {
-                            actions.insert(Action::Remove(local_package), ());
-                        } else {
-                            info!(
-                                "Package {package_name} is already at latest version. Ignoring..."
-                            );
+                if let ReinstallOptions::Update = reinstall_options {
+                    match db.is_held(&remote_package.package_data.name) {
+                        Ok(true) => {
+                            info!("{}", Message::PackageHeld(package_name));
                             return Ok(actions);
                         }
+                        Ok(false) => (),
+                        Err(error) => return Err(InstallError::Database(error)),
                     }
-                    ReinstallOptions::Ignore => {
-                        info!("Package {package_name} already installed. Ignoring...");
+                }
+
+                if let Some(skip_reason) =
+                    get_skip_reason(&remote_package, &local_package, reinstall_options)
+                {
+                    let missing_files =
+                        get_missing_files(&local_package, install_options.root.as_deref());
+
+                    if install_options.reinstall_if_files_missing && !missing_files.is_empty() {
+                        info!(
+                            "{}",
+                            Message::PackageMissingFilesReinstalling(package_name, &missing_files)
+                        );
+                    } else {
+                        report_skip(package_name, &skip_reason, install_options.explain_skip);
                         return Ok(actions);
                     }
                 }
+
+                if let ReinstallOptions::ForceReinstall | ReinstallOptions::Overlay =
+                    reinstall_options
+                {
+                    info!("{}", Message::PackageReinstalling(package_name));
+                }
+
+                // It's also possible to call remove_package and get the package removal specific actions.
+                // But this can cause issues.
+                // - First a pointless database query for existance of the packge which is already guaranteed.
+                // - Second, all the recursive removal related issues. We reinstall a package and there's no need to check for dependency
+                // break as we will be installing it back again.
+                if let ReinstallOptions::Overlay = reinstall_options {
+                    remote_package.overlaid_files = Some(local_package.package_files);
+                } else {
+                    actions.insert(Action::Remove(Box::new(local_package)), ());
+                }
             }
         }
         Err(error) => return Err(InstallError::Database(error)),
     }
 
+    for replaced_name in remote_package.replaces.iter() {
+        if *replaced_name == remote_package.package_data.name {
+            continue;
+        }
+
+        match db.get_package(replaced_name) {
+            Ok(Some(replaced_package)) => {
+                info!(
+                    "{}",
+                    Message::RemovingReplaced(&remote_package.package_data.name, replaced_name)
+                );
+                actions.insert(Action::Remove(Box::new(replaced_package)), ());
+            }
+            Ok(None) => (),
+            Err(error) => return Err(InstallError::Database(error)),
+        }
+    }
+
     progress::increment_target(
         ProgressType::Packages,
         remote_package.dependencies.len() as i32,
     )
     .await;
 
+    let dependency_reinstall_options = match reinstall_options {
+        ReinstallOptions::ForceReinstall | ReinstallOptions::Overlay
+            if !install_options.reinstall_dependencies =>
+        {
+            &ReinstallOptions::Ignore
+        }
+        reinstall_options => reinstall_options,
+    };
+
     for dependency in remote_package.dependencies.iter() {
-        actions.extend(install_package(dependency, package_finder, reinstall_options, db).await?);
+        actions.extend(
+            install_package(
+                dependency,
+                None,
+                package_finder,
+                dependency_reinstall_options,
+                install_options,
+                requested_versions,
+                resolved,
+                db,
+            )
+            .await?,
+        );
 
         progress::increment_completed(ProgressType::Packages, 1).await;
     }
 
-    actions.insert(Action::Install(remote_package), ());
+    actions.insert(Action::Install(Box::new(remote_package)), ());
 
     Ok(actions)
 }
@@ -222,6 +1090,7 @@ async fn install_package<EFind: Error, EDatabase: Error>(
 async fn remove_package<EDatabase: Error>(
     package_name: &str,
     recursive: bool,
+    force: bool,
     db: &mut impl PackagesDb<GetError = EDatabase>,
 ) -> Result<LinkedHashSet<Action>, RemoveError<EDatabase>> {
     debug!("Generating remove actions for package: {package_name}");
@@ -247,12 +1116,14 @@ async fn remove_package<EDatabase: Error>(
 
     if !depending_packages.is_empty() {
         if recursive {
-            info!("Found depending packages, uninstalling...");
+            info!("{}", Message::RemovingDepending);
             progress::increment_target(ProgressType::Packages, depending_packages.len() as i32)
                 .await;
 
             for dependency in depending_packages.iter() {
-                actions.extend(remove_package(&dependency.package_data.name, recursive, db).await?);
+                let dependency_actions =
+                    remove_package(&dependency.package_data.name, recursive, force, db).await?;
+                extend_keeping_order(&mut actions, dependency_actions);
 
                 progress::increment_completed(ProgressType::Packages, 1).await;
             }
@@ -262,6 +1133,15 @@ async fn remove_package<EDatabase: Error>(
                 .map(|p| p.package_data.name)
                 .collect();
 
+            if force {
+                warn!(
+                    "{}",
+                    Message::BreakingDependencies(package_name, &depending_packages)
+                );
+                actions.insert(Action::Remove(Box::new(db_package)), ());
+                return Ok(actions);
+            }
+
             return Err(RemoveError::DependencyBreak(
                 String::from(package_name),
                 depending_packages,
@@ -269,20 +1149,156 @@ async fn remove_package<EDatabase: Error>(
         }
     }
 
-    let action = Action::Remove(db_package);
+    let action = Action::Remove(Box::new(db_package));
     actions.insert(action, ());
 
     Ok(actions)
 }
 
-fn remote_is_newer(
+/// Appends the actions that are not planned yet. Unlike [LinkedHashMap::extend], which moves
+/// already present actions to the back, planned actions keep their position so that they still
+/// run before the actions that were planned after them.
+fn extend_keeping_order(actions: &mut LinkedHashSet<Action>, new_actions: LinkedHashSet<Action>) {
+    for (action, ()) in new_actions {
+        if !actions.contains_key(&action) {
+            actions.insert(action, ());
+        }
+    }
+}
+
+/// Returns why the installed package should not be reinstalled, or None if it should be.
+fn get_skip_reason(
     remote_package: &RemotePackage,
     local_package: &LocalPackage,
-) -> Result<bool, semver::Error> {
-    let remote_version = Version::parse(&remote_package.package_data.version)?;
-    let local_version = Version::parse(&local_package.package_data.version)?;
+    reinstall_options: &ReinstallOptions,
+) -> Option<SkipReason> {
+    match reinstall_options {
+        ReinstallOptions::ForceReinstall | ReinstallOptions::Overlay => None,
+        ReinstallOptions::Update => {
+            if remote_is_newer(remote_package, local_package) {
+                None
+            } else {
+                Some(SkipReason::NotNewer {
+                    local: local_package.package_data.version.clone(),
+                    remote: remote_package.package_data.version.clone(),
+                })
+            }
+        }
+        ReinstallOptions::Ignore => Some(SkipReason::AlreadyInstalled(
+            local_package.package_data.version.clone(),
+        )),
+    }
+}
+
+fn report_skip(package_name: &str, skip_reason: &SkipReason, explain_skip: bool) {
+    let message = if explain_skip {
+        Message::PackageSkipped(package_name, skip_reason)
+    } else {
+        match skip_reason {
+            SkipReason::AlreadyInstalled(_) => Message::PackageAlreadyInstalled(package_name),
+            SkipReason::NotNewer { .. } => Message::PackageUpToDate(package_name),
+        }
+    };
+
+    info!("{message}");
+}
+
+/// Path of the installed file on this system, as package files are recorded relative to the root
+/// they are installed into.
+fn installed_path(file: &PackageFile, root: Option<&Path>) -> PathBuf {
+    PathBuf::from(config::rooted(root, &file.path))
+}
+
+/// Returns the tracked files of the package that don't exist anymore under `root`.
+pub fn get_missing_files(package: &LocalPackage, root: Option<&Path>) -> Vec<String> {
+    package
+        .package_files
+        .iter()
+        .filter(|file| !installed_path(file, root).exists())
+        .map(|file| file.path.clone())
+        .collect()
+}
+
+/// Returns the tracked files of the package whose size or checksum under `root` differs from the
+/// recorded one. Missing files and files without a recorded checksum are not included.
+pub fn get_modified_files(package: &LocalPackage, root: Option<&Path>) -> Vec<String> {
+    package
+        .package_files
+        .iter()
+        .filter(|file| is_modified(file, root))
+        .map(|file| file.path.clone())
+        .collect()
+}
+
+fn is_modified(file: &PackageFile, root: Option<&Path>) -> bool {
+    let Some(sha256) = &file.sha256 else {
+        return false;
+    };
+
+    let path = installed_path(file, root);
+    let Ok(metadata) = fs::symlink_metadata(&path) else {
+        return false;
+    };
+
+    if file.size.is_some_and(|size| size != metadata.len()) {
+        return true;
+    }
+
+    match archive::get_checksum(&path) {
+        Ok(checksum) => &checksum != sha256,
+        Err(error) => {
+            warn!("Could not compute the checksum of {path:?}: {error}");
+            false
+        }
+    }
+}
+
+/// Versions that are not semver, even after [parse_version_lenient], can't be ordered, so the
+/// remote is considered newer whenever the versions differ.
+fn remote_is_newer(remote_package: &RemotePackage, local_package: &LocalPackage) -> bool {
+    let remote_version = &remote_package.package_data.version;
+    let local_version = &local_package.package_data.version;
+
+    match (
+        parse_version_lenient(remote_version),
+        parse_version_lenient(local_version),
+    ) {
+        (Some(remote), Some(local)) => remote > local,
+        _ => {
+            warn!(
+                "Could not compare versions {local_version} and {remote_version} of package {}, updating if they differ",
+                local_package.package_data.name
+            );
+            remote_version != local_version
+        }
+    }
+}
+
+/// Parses a semver version, padding versions missing their minor or patch number such as `1.0`.
+/// Prereleases of padded versions must start with a non-numeric identifier such as `1.0-rc.1`,
+/// so that dates like `2024-01-01` are not mistaken for versions.
+fn parse_version_lenient(version: &str) -> Option<Version> {
+    if let Ok(version) = Version::parse(version) {
+        return Some(version);
+    }
+
+    let suffix_start = version.find(['-', '+']).unwrap_or(version.len());
+    let (core, suffix) = version.split_at(suffix_start);
+
+    let parts: Vec<&str> = core.split('.').collect();
+    if parts.len() > 2 || parts.iter().any(|part| part.parse::<u64>().is_err()) {
+        return None;
+    }
+
+    if let Some(prerelease) = suffix.strip_prefix('-') {
+        let first_identifier = prerelease.split(['.', '-', '+']).next().unwrap_or_default();
+        if first_identifier.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+    }
 
-    Ok(remote_version > local_version)
+    let padding = ".0".repeat(3 - parts.len());
+    Version::parse(&format!("{core}{padding}{suffix}")).ok()
 }
 
 fn get_depending<EDatabase: Error>(
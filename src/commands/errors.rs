@@ -1,39 +1,78 @@
 use std::fmt::Display;
 use thiserror::Error;
 
+use crate::messages::Message;
+
 #[derive(Error, Debug, PartialEq)]
 pub enum InstallError<EDatabase: Display, EFind: Display> {
-    #[error("Package {0} not found.")]
+    #[error("{}", Message::PackageNotFound(.0))]
     PackageNotFound(String),
     #[error("Error while searching for package {0}")]
     Find(EFind),
-    #[error("Could not parse package version: {0}")]
-    // semver::Error does not implement PartialEq so cannot be used directly. So instead should be converted to string.
-    VersionParse(String),
+    #[error("Package {name} does not support the current {field}")]
+    Incompatible { name: String, field: String },
+    #[error("Version {version} of package {name} was not found")]
+    VersionNotFound { name: String, version: String },
+    #[error("Package {name} is requested in both version {} and {}", .versions.0, .versions.1)]
+    ConflictingVersions {
+        name: String,
+        versions: (String, String),
+    },
     #[error("A database error has occured {0}")]
-    Database(EDatabase),
+    Database(#[source] EDatabase),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DependsError<EFind: Display> {
+    #[error("{}", Message::PackageNotFound(.0))]
+    PackageNotFound(String),
+    #[error("Error while searching for package {0}")]
+    Find(EFind),
 }
 
 #[derive(Error, Debug, PartialEq)]
 pub enum RemoveError<EDatabase: Display> {
-    #[error("Package {0} not installed")]
+    #[error("{}", Message::PackageNotInstalled(.0))]
     PackageNotInstalled(String),
     #[error("Removing package {0} breaks dependencies {1:?}")]
     DependencyBreak(String, Vec<String>),
     #[error("Could not get package from databae: {0}")]
-    DatabaseGet(EDatabase),
+    DatabaseGet(#[source] EDatabase),
 }
 
 #[derive(Error, Debug, PartialEq)]
 pub enum UpdateError<EDatabase: Display, EFind: Display> {
+    #[error("Package {0} is not installed")]
+    PackageNotInstalled(String),
     #[error("Could not get package from databae: {0}")]
-    DatabaseGet(EDatabase),
+    DatabaseGet(#[source] EDatabase),
+    #[error("Error while searching for package {0}")]
+    Find(EFind),
     #[error("Could not generate actions to remove packages: {0}")]
     Remove(#[from] RemoveError<EDatabase>),
     #[error("Could not generate actions to install packages: {0}")]
     Install(#[from] InstallError<EDatabase, EFind>),
 }
 
+#[derive(Error, Debug, PartialEq)]
+pub enum BuildArchiveError<EDatabase: Display> {
+    #[error("Could not get package from databae: {0}")]
+    DatabaseGet(#[from] EDatabase),
+    #[error("Package {0} is not installed")]
+    PackageNotInstalled(String),
+    #[error("Could not write package archive: {0}")]
+    // ArchiveError does not implement PartialEq, so it's converted to string.
+    Archive(String),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum VerifyError<EDatabase: Display> {
+    #[error("Could not get package from databae: {0}")]
+    DatabaseGet(#[from] EDatabase),
+    #[error("Package {0} is not installed")]
+    PackageNotInstalled(String),
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum InfoError<EDatabase: Display> {
     #[error("Could not get package from databae: {0}")]
@@ -41,3 +80,11 @@ pub enum InfoError<EDatabase: Display> {
     #[error("Package {0} is not installed")]
     PackageNotInstalled(String),
 }
+
+#[derive(Error, Debug, PartialEq)]
+pub enum HoldError<EDatabase: Display> {
+    #[error("Could not access package database: {0}")]
+    Database(#[from] EDatabase),
+    #[error("Package {0} is not installed")]
+    PackageNotInstalled(String),
+}
@@ -3,13 +3,13 @@ use super::*;
 use tokio::test;
 
 use crate::commands;
+use crate::manifest::Manifest;
+use crate::package::PackageData;
 
-use crate::test_helpers::MockPackagesDb;
+use crate::test_helpers::{MockPackagesDb, MockProgressbar};
 use mock_package_finder::MockPackageFinder;
-use mock_progressbar::MockProgressbar;
 
 mod mock_package_finder;
-mod mock_progressbar;
 
 #[test]
 async fn test_install_actions_generated_succesfully() {
@@ -20,11 +20,15 @@ async fn test_install_actions_generated_succesfully() {
         vec![remote_package.package_data.name.clone()],
         &mut package_finder,
         &ReinstallOptions::Ignore,
+        &InstallOptions::default(),
         &mut mock_db,
     )
     .await;
 
-    assert_actions(install_result, vec![Action::Install(remote_package)]);
+    assert_actions(
+        install_result,
+        vec![Action::Install(Box::new(remote_package))],
+    );
 }
 
 #[test]
@@ -34,11 +38,15 @@ async fn test_remove_package_actions_generated_succesfully() {
 
     let local_package = mock_install(&mut mock_db, &remote_package);
 
-    let remove_result =
-        commands::remove_packages(vec![remote_package.package_data.name], false, &mut mock_db)
-            .await;
+    let remove_result = commands::remove_packages(
+        vec![remote_package.package_data.name],
+        false,
+        false,
+        &mut mock_db,
+    )
+    .await;
 
-    assert_actions(remove_result, vec![Action::Remove(local_package)]);
+    assert_actions(remove_result, vec![Action::Remove(Box::new(local_package))]);
 }
 
 #[test]
@@ -52,6 +60,7 @@ async fn test_installed_package_is_ignored() {
         vec![remote_package.package_data.name.clone()],
         &mut package_finder,
         &ReinstallOptions::Ignore,
+        &InstallOptions::default(),
         &mut mock_db,
     )
     .await;
@@ -75,6 +84,7 @@ async fn test_installed_package_is_updated() {
         vec![package_name],
         &mut package_finder,
         &ReinstallOptions::Update,
+        &InstallOptions::default(),
         &mut mock_db,
     )
     .await;
@@ -82,8 +92,8 @@ async fn test_installed_package_is_updated() {
     assert_actions(
         install_result,
         vec![
-            Action::Remove(local_packge),
-            Action::Install(remote_package),
+            Action::Remove(Box::new(local_packge)),
+            Action::Install(Box::new(remote_package)),
         ],
     );
 }
@@ -99,6 +109,7 @@ async fn test_latest_ver_installed_package_is_ignored() {
         vec![remote_package.package_data.name.clone()],
         &mut package_finder,
         &ReinstallOptions::Update,
+        &InstallOptions::default(),
         &mut mock_db,
     )
     .await;
@@ -117,6 +128,181 @@ async fn test_installed_package_is_reinstalled() {
         vec![remote_package.package_data.name.clone()],
         &mut package_finder,
         &ReinstallOptions::ForceReinstall,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        install_result,
+        vec![
+            Action::Remove(Box::new(local_package)),
+            Action::Install(Box::new(remote_package)),
+        ],
+    );
+}
+
+#[test]
+async fn test_reinstall_leaves_installed_dependencies_alone() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let dependent = package_finder.get_package_with_dependency().await;
+    let dependency = package_finder.get_simple_packge().await;
+
+    let local_dependent = mock_install(&mut mock_db, &dependent);
+    mock_install(&mut mock_db, &dependency);
+
+    let install_result = commands::install_packages(
+        vec![dependent.package_data.name.clone()],
+        &mut package_finder,
+        &ReinstallOptions::ForceReinstall,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        install_result,
+        vec![
+            Action::Remove(Box::new(local_dependent)),
+            Action::Install(Box::new(dependent)),
+        ],
+    );
+}
+
+#[test]
+async fn test_reinstall_of_requested_dependency_not_skipped() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let dependent = package_finder.get_package_with_dependency().await;
+    let dependency = package_finder.get_simple_packge().await;
+
+    let local_dependent = mock_install(&mut mock_db, &dependent);
+    let local_dependency = mock_install(&mut mock_db, &dependency);
+
+    let install_result = commands::install_packages(
+        vec![
+            dependent.package_data.name.clone(),
+            dependency.package_data.name.clone(),
+        ],
+        &mut package_finder,
+        &ReinstallOptions::ForceReinstall,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        install_result,
+        vec![
+            Action::Remove(Box::new(local_dependent)),
+            Action::Install(Box::new(dependent)),
+            Action::Remove(Box::new(local_dependency)),
+            Action::Install(Box::new(dependency)),
+        ],
+    );
+}
+
+#[test]
+async fn test_reinstall_dependencies_reinstalls_installed_dependencies() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let dependent = package_finder.get_package_with_dependency().await;
+    let dependency = package_finder.get_simple_packge().await;
+
+    let local_dependent = mock_install(&mut mock_db, &dependent);
+    let local_dependency = mock_install(&mut mock_db, &dependency);
+
+    let install_result = commands::install_packages(
+        vec![dependent.package_data.name.clone()],
+        &mut package_finder,
+        &ReinstallOptions::ForceReinstall,
+        &InstallOptions {
+            reinstall_dependencies: true,
+            ..Default::default()
+        },
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        install_result,
+        vec![
+            Action::Remove(Box::new(local_dependent)),
+            Action::Remove(Box::new(local_dependency)),
+            Action::Install(Box::new(dependency)),
+            Action::Install(Box::new(dependent)),
+        ],
+    );
+}
+
+#[test]
+async fn test_overlay_reinstall_does_not_remove_first() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let mut remote_package = package_finder.get_simple_packge().await;
+    remote_package.package_files = vec![PackageFile::from("/tmp/japm/tests/overlaid_file")];
+
+    let local_package = mock_install(&mut mock_db, &remote_package);
+
+    let install_result = commands::install_packages(
+        vec![remote_package.package_data.name.clone()],
+        &mut package_finder,
+        &ReinstallOptions::Overlay,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    let overlaid_package = RemotePackage {
+        overlaid_files: Some(local_package.package_files),
+        package_files: vec![],
+        ..remote_package
+    };
+    assert_actions(
+        install_result,
+        vec![Action::Install(Box::new(overlaid_package))],
+    );
+}
+
+#[test]
+async fn test_replaced_package_is_removed() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let local_package = mock_install(&mut mock_db, &get_remote_package("foo", vec![]));
+
+    let mut renamed_package = get_remote_package("foo-ng", vec![]);
+    renamed_package.replaces = vec![String::from("foo"), String::from("foo-ng")];
+    package_finder.add_package(renamed_package.clone());
+
+    let install_result = commands::install_packages(
+        vec![String::from("foo-ng")],
+        &mut package_finder,
+        &ReinstallOptions::Update,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        install_result,
+        vec![
+            Action::Remove(Box::new(local_package)),
+            Action::Install(Box::new(renamed_package)),
+        ],
+    );
+}
+
+#[test]
+async fn test_package_requested_and_depended_on_searched_once() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let dependent = package_finder.get_package_with_dependency().await;
+    let dependency = package_finder.get_simple_packge().await;
+    package_finder.searched_packages.clear();
+
+    let install_result = commands::install_packages(
+        vec![
+            dependent.package_data.name.clone(),
+            dependency.package_data.name.clone(),
+        ],
+        &mut package_finder,
+        &ReinstallOptions::Update,
+        &InstallOptions::default(),
         &mut mock_db,
     )
     .await;
@@ -124,10 +310,14 @@ async fn test_installed_package_is_reinstalled() {
     assert_actions(
         install_result,
         vec![
-            Action::Remove(local_package),
-            Action::Install(remote_package),
+            Action::Install(Box::new(dependency)),
+            Action::Install(Box::new(dependent)),
         ],
     );
+    assert_eq!(
+        package_finder.searched_packages,
+        vec!["package_with_dependency", "simple_package"]
+    );
 }
 
 #[test]
@@ -146,6 +336,7 @@ async fn test_remove_package_with_depending_packages_is_not_allowed() {
     let remove_result = commands::remove_packages(
         vec![package_dependency.package_data.name],
         false,
+        false,
         &mut mock_db,
     )
     .await;
@@ -157,6 +348,33 @@ async fn test_remove_package_with_depending_packages_is_not_allowed() {
     ));
 }
 
+#[test]
+async fn test_forced_remove_keeps_depending() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let package_with_dependency = package_finder.get_package_with_dependency().await;
+    let package_dependency = package_finder
+        .find_package(&package_with_dependency.dependencies[0])
+        .await
+        .unwrap()
+        .unwrap();
+
+    let local_package_dependency = mock_install(&mut mock_db, &package_dependency);
+    mock_install(&mut mock_db, &package_with_dependency);
+
+    let remove_result = commands::remove_packages(
+        vec![package_dependency.package_data.name],
+        false,
+        true,
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        remove_result,
+        vec![Action::Remove(Box::new(local_package_dependency))],
+    );
+}
+
 #[test]
 async fn test_remove_package_removes_depending() {
     let (mut mock_db, mut package_finder) = get_mocks();
@@ -176,6 +394,7 @@ async fn test_remove_package_removes_depending() {
     let remove_result = commands::remove_packages(
         vec![package_dependency.package_data.name],
         true,
+        false,
         &mut mock_db,
     )
     .await;
@@ -183,27 +402,1027 @@ async fn test_remove_package_removes_depending() {
     assert_actions(
         remove_result,
         vec![
-            Action::Remove(local_package_with_dependency),
-            Action::Remove(local_package_dependency),
+            Action::Remove(Box::new(local_package_with_dependency)),
+            Action::Remove(Box::new(local_package_dependency)),
         ],
     );
 }
 
-fn assert_actions<Error: std::fmt::Debug>(
-    result: Result<Vec<Action>, Error>,
-    expected_actions: Vec<Action>,
-) {
-    assert!(result.is_ok());
-    assert!(result.unwrap() == expected_actions);
+#[test]
+async fn test_shared_depending_removed_before_all_roots() {
+    let (mut mock_db, _) = get_mocks();
+
+    let first_root = get_remote_package("first-root", vec![]);
+    let second_root = get_remote_package("second-root", vec![]);
+    let shared_depending = get_remote_package(
+        "shared-depending",
+        vec![String::from("first-root"), String::from("second-root")],
+    );
+
+    let local_first_root = mock_install(&mut mock_db, &first_root);
+    let local_second_root = mock_install(&mut mock_db, &second_root);
+    let local_shared_depending = mock_install(&mut mock_db, &shared_depending);
+
+    let remove_result = commands::remove_packages(
+        vec![String::from("first-root"), String::from("second-root")],
+        true,
+        false,
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        remove_result,
+        vec![
+            Action::Remove(Box::new(local_shared_depending)),
+            Action::Remove(Box::new(local_first_root)),
+            Action::Remove(Box::new(local_second_root)),
+        ],
+    );
 }
 
-fn mock_install(db: &mut MockPackagesDb, remote_package: &RemotePackage) -> LocalPackage {
-    db.add_package(remote_package)
-        .expect("Could not add mock package to db");
+#[test]
+async fn test_verify_reports_missing_files() {
+    const PRESENT_FILE: &str = "/tmp/japm/tests/verify_present_file";
+    const MISSING_FILE: &str = "/tmp/japm/tests/verify_missing_file";
 
-    db.get_package(&remote_package.package_data.name.clone())
-        .unwrap()
-        .unwrap()
+    let (mut mock_db, _) = get_mocks();
+
+    tokio::fs::create_dir_all("/tmp/japm/tests").await.unwrap();
+    tokio::fs::write(PRESENT_FILE, "").await.unwrap();
+    if Path::new(MISSING_FILE).exists() {
+        tokio::fs::remove_file(MISSING_FILE).await.unwrap();
+    }
+
+    let mut intact_package = get_remote_package("test-verify-intact", vec![]);
+    intact_package.package_files = vec![PackageFile::from(PRESENT_FILE)];
+    let mut broken_package = get_remote_package("test-verify-broken", vec![]);
+    broken_package.package_files = vec![
+        PackageFile::from(PRESENT_FILE),
+        PackageFile::from(MISSING_FILE),
+    ];
+
+    mock_db.add_package(&intact_package).unwrap();
+    mock_db.add_package(&broken_package).unwrap();
+
+    let expected = vec![FileMismatch {
+        package: String::from("test-verify-broken"),
+        missing_files: vec![String::from(MISSING_FILE)],
+        modified_files: vec![],
+    }];
+
+    assert_eq!(
+        commands::verify_packages(vec![], None, &mut mock_db).unwrap(),
+        expected
+    );
+    assert_eq!(
+        commands::verify_packages(vec![String::from("test-verify-intact")], None, &mut mock_db)
+            .unwrap(),
+        vec![]
+    );
+    assert!(matches!(
+        commands::verify_packages(vec![String::from("not-installed")], None, &mut mock_db),
+        Err(VerifyError::PackageNotInstalled(_))
+    ));
+}
+
+#[test]
+async fn test_verify_checks_files_under_root() {
+    const ROOT: &str = "/tmp/japm/tests/verify_root";
+    const RECORDED_FILE: &str = "/usr/share/test-verify-rooted/file";
+
+    let (mut mock_db, _) = get_mocks();
+
+    let installed_file = format!("{ROOT}{RECORDED_FILE}");
+    tokio::fs::create_dir_all(Path::new(&installed_file).parent().unwrap())
+        .await
+        .unwrap();
+    tokio::fs::write(&installed_file, "installed")
+        .await
+        .unwrap();
+
+    let mut package = get_remote_package("test-verify-rooted", vec![]);
+    package.package_files = vec![PackageFile {
+        path: String::from(RECORDED_FILE),
+        sha256: Some(archive::get_checksum(Path::new(&installed_file)).unwrap()),
+        size: Some(9),
+    }];
+    mock_db.add_package(&package).unwrap();
+
+    assert_eq!(
+        commands::verify_packages(vec![], Some(Path::new(ROOT)), &mut mock_db).unwrap(),
+        vec![]
+    );
+
+    tokio::fs::write(&installed_file, "modified!")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        commands::verify_packages(vec![], Some(Path::new(ROOT)), &mut mock_db).unwrap(),
+        vec![FileMismatch {
+            package: String::from("test-verify-rooted"),
+            missing_files: vec![],
+            modified_files: vec![String::from(RECORDED_FILE)],
+        }]
+    );
+}
+
+#[test]
+async fn test_verify_reports_modified_files() {
+    const MODIFIED_FILE: &str = "/tmp/japm/tests/verify_modified_file";
+    const UNTRACKED_FILE: &str = "/tmp/japm/tests/verify_untracked_file";
+
+    let (mut mock_db, _) = get_mocks();
+
+    tokio::fs::create_dir_all("/tmp/japm/tests").await.unwrap();
+    tokio::fs::write(MODIFIED_FILE, "installed").await.unwrap();
+    tokio::fs::write(UNTRACKED_FILE, "installed").await.unwrap();
+
+    let mut package = get_remote_package("test-verify-modified", vec![]);
+    package.package_files = vec![
+        PackageFile {
+            path: String::from(MODIFIED_FILE),
+            sha256: Some(archive::get_checksum(Path::new(MODIFIED_FILE)).unwrap()),
+            size: Some(9),
+        },
+        // Installed before checksums were recorded
+        PackageFile::from(UNTRACKED_FILE),
+    ];
+    mock_db.add_package(&package).unwrap();
+
+    assert_eq!(
+        commands::verify_packages(vec![], None, &mut mock_db).unwrap(),
+        vec![]
+    );
+
+    // Same size, different content
+    tokio::fs::write(MODIFIED_FILE, "modified!").await.unwrap();
+    tokio::fs::write(UNTRACKED_FILE, "modified").await.unwrap();
+
+    assert_eq!(
+        commands::verify_packages(vec![], None, &mut mock_db).unwrap(),
+        vec![FileMismatch {
+            package: String::from("test-verify-modified"),
+            missing_files: vec![],
+            modified_files: vec![String::from(MODIFIED_FILE)],
+        }]
+    );
+}
+
+#[test]
+async fn test_clean_removes_build_directories() {
+    const BUILD_PATH: &str = "/tmp/japm/tests/clean_build_path";
+
+    if Path::new(BUILD_PATH).exists() {
+        tokio::fs::remove_dir_all(BUILD_PATH).await.unwrap();
+    }
+    assert_eq!(
+        commands::clean(Path::new(BUILD_PATH)).unwrap(),
+        CleanReport::default()
+    );
+
+    tokio::fs::create_dir_all(format!("{BUILD_PATH}/failed-package/bin"))
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{BUILD_PATH}/failed-package/bin/binary"), "1234")
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{BUILD_PATH}/failed-package/readme"), "12")
+        .await
+        .unwrap();
+
+    let report = commands::clean(Path::new(BUILD_PATH)).unwrap();
+    assert_eq!(report.freed_bytes, 6);
+    assert_eq!(
+        report.removed_paths,
+        vec![format!("{BUILD_PATH}/failed-package")]
+    );
+
+    // The build path itself is kept for the next builds
+    assert!(Path::new(BUILD_PATH).exists());
+    assert!(!Path::new(&format!("{BUILD_PATH}/failed-package")).exists());
+}
+
+#[test]
+async fn test_simulated_remove_lists_files_to_delete() {
+    const PRESENT_FILE: &str = "/tmp/japm/tests/simulate_remove_present_file";
+    const MISSING_FILE: &str = "/tmp/japm/tests/simulate_remove_missing_file";
+
+    let (mut mock_db, _) = get_mocks();
+
+    tokio::fs::create_dir_all("/tmp/japm/tests").await.unwrap();
+    tokio::fs::write(PRESENT_FILE, "").await.unwrap();
+    if Path::new(MISSING_FILE).exists() {
+        tokio::fs::remove_file(MISSING_FILE).await.unwrap();
+    }
+
+    let mut library = get_remote_package("test-simulate-library", vec![]);
+    library.package_files = vec![PackageFile::from(PRESENT_FILE)];
+    let mut tool = get_remote_package(
+        "test-simulate-tool",
+        vec![library.package_data.name.clone()],
+    );
+    tool.package_files = vec![PackageFile::from(MISSING_FILE)];
+
+    mock_db.add_package(&library).unwrap();
+    mock_db.add_package(&tool).unwrap();
+
+    let previews = commands::simulate_remove(
+        vec![library.package_data.name.clone()],
+        true,
+        false,
+        None,
+        &mut mock_db,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        previews,
+        vec![
+            RemovalPreview {
+                package: tool.package_data.name.clone(),
+                files: vec![RemovedFile {
+                    path: String::from(MISSING_FILE),
+                    exists: false,
+                }],
+            },
+            RemovalPreview {
+                package: library.package_data.name.clone(),
+                files: vec![RemovedFile {
+                    path: String::from(PRESENT_FILE),
+                    exists: true,
+                }],
+            },
+        ]
+    );
+    assert!(previews[0].to_string().contains("(already missing)"));
+
+    // Nothing was removed
+    assert!(Path::new(PRESENT_FILE).exists());
+    assert!(mock_db.contains(&library.package_data.name).unwrap());
+}
+
+#[test]
+async fn test_owner_of_package_file_found() {
+    const OWNED_DIRECTORY: &str = "/tmp/japm/tests/owned_directory";
+
+    let (mut mock_db, _) = get_mocks();
+
+    tokio::fs::create_dir_all(OWNED_DIRECTORY).await.unwrap();
+    tokio::fs::write(format!("{OWNED_DIRECTORY}/file"), "")
+        .await
+        .unwrap();
+
+    let mut package = get_remote_package("test-owner", vec![]);
+    package.package_files = vec![PackageFile::from(OWNED_DIRECTORY)];
+    mock_db.add_package(&package).unwrap();
+
+    assert_eq!(
+        commands::find_owner(OWNED_DIRECTORY, None, &mut mock_db).unwrap(),
+        Some(String::from("test-owner"))
+    );
+    assert_eq!(
+        commands::find_owner(
+            &format!("{OWNED_DIRECTORY}/../owned_directory/file"),
+            None,
+            &mut mock_db
+        )
+        .unwrap(),
+        Some(String::from("test-owner"))
+    );
+    assert_eq!(
+        commands::find_owner("/tmp/japm/tests/not_owned", None, &mut mock_db).unwrap(),
+        None
+    );
+}
+
+#[test]
+async fn test_which_resolves_registered_binary() {
+    let (mut mock_db, _) = get_mocks();
+
+    let mut package = get_remote_package("test-binaries", vec![]);
+    package.binaries = vec![String::from("test-binary")];
+    mock_db.add_package(&package).unwrap();
+
+    assert_eq!(
+        commands::which("test-binary", &mut mock_db).unwrap(),
+        Some(String::from("test-binaries"))
+    );
+    assert_eq!(commands::which("other-binary", &mut mock_db).unwrap(), None);
+
+    mock_db.remove_package("test-binaries").unwrap();
+    assert_eq!(commands::which("test-binary", &mut mock_db).unwrap(), None);
+}
+
+#[test]
+async fn test_package_missing_files_is_reinstalled() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let mut remote_package = package_finder.get_simple_packge().await;
+
+    remote_package.package_files = vec![PackageFile::from(
+        "/tmp/japm/tests/this-file-does-not-exist",
+    )];
+    let local_package = mock_install(&mut mock_db, &remote_package);
+    let remote_package = package_finder.get_simple_packge().await;
+
+    let install_options = InstallOptions {
+        reinstall_if_files_missing: true,
+        ..Default::default()
+    };
+
+    let install_result = commands::install_packages(
+        vec![remote_package.package_data.name.clone()],
+        &mut package_finder,
+        &ReinstallOptions::Update,
+        &install_options,
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        install_result,
+        vec![
+            Action::Remove(Box::new(local_package)),
+            Action::Install(Box::new(remote_package)),
+        ],
+    );
+}
+
+#[test]
+async fn test_update_not_installed_package_is_rejected() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let remote_package = package_finder.get_simple_packge().await;
+
+    let update_result = commands::update_packages(
+        vec![remote_package.package_data.name.clone()],
+        &mut package_finder,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert!(matches!(
+        update_result,
+        Err(UpdateError::PackageNotInstalled(_))
+    ));
+}
+
+#[test]
+async fn test_not_newer_skip_reason_reported() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let remote_package = package_finder.get_simple_packge().await;
+
+    let local_package = mock_install(&mut mock_db, &remote_package);
+
+    let skip_reason = get_skip_reason(&remote_package, &local_package, &ReinstallOptions::Update);
+
+    assert_eq!(
+        skip_reason,
+        Some(SkipReason::NotNewer {
+            local: String::from("0.0.1"),
+            remote: String::from("0.0.1"),
+        })
+    );
+}
+
+#[test]
+async fn test_newer_package_is_not_skipped() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let remote_package = package_finder.get_simple_packge().await;
+
+    let local_package = mock_install(&mut mock_db, &remote_package);
+
+    package_finder.update_remote_package_version(&remote_package.package_data.name);
+    let remote_package = package_finder.get_simple_packge().await;
+
+    let skip_reason = get_skip_reason(&remote_package, &local_package, &ReinstallOptions::Update);
+
+    assert_eq!(skip_reason, None);
+}
+
+#[test]
+async fn test_semver_versions_compared() {
+    assert!(remote_version_is_newer("1.10.0", "1.9.0"));
+    assert!(!remote_version_is_newer("1.0.0-beta", "1.0.0"));
+}
+
+#[test]
+async fn test_coercible_versions_compared_as_semver() {
+    assert_eq!(parse_version_lenient("1.0"), Some(Version::new(1, 0, 0)));
+    assert_eq!(parse_version_lenient("2"), Some(Version::new(2, 0, 0)));
+    assert_eq!(
+        parse_version_lenient("1.2-rc.1"),
+        Version::parse("1.2.0-rc.1").ok()
+    );
+
+    assert!(remote_version_is_newer("1.1", "1.0.5"));
+    assert!(!remote_version_is_newer("1.0", "1.0.0"));
+}
+
+#[test]
+async fn test_non_semver_versions_compared_for_inequality() {
+    assert_eq!(parse_version_lenient("2024-01-01"), None);
+    assert_eq!(parse_version_lenient("2024.1-5"), None);
+    assert_eq!(parse_version_lenient("1.0.0.1"), None);
+
+    assert!(remote_version_is_newer("2024-02-01", "2024-01-01"));
+    assert!(!remote_version_is_newer("2024-01-01", "2024-01-01"));
+    assert!(remote_version_is_newer("1.0.0", "nightly"));
+}
+
+#[test]
+async fn test_package_for_current_arch_is_installed() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+
+    let mut package = get_remote_package("test-matching-arch", vec![]);
+    package.arch = vec![String::from("other"), String::from(std::env::consts::ARCH)];
+    package.os = vec![String::from(std::env::consts::OS)];
+    package_finder.add_package(package.clone());
+
+    let install_result = commands::install_packages(
+        vec![package.package_data.name.clone()],
+        &mut package_finder,
+        &ReinstallOptions::Ignore,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(install_result, vec![Action::Install(Box::new(package))]);
+}
+
+#[test]
+async fn test_package_for_other_arch_is_rejected() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+
+    let mut package = get_remote_package("test-other-arch", vec![]);
+    package.arch = vec![String::from("not-an-arch")];
+    package_finder.add_package(package);
+
+    let install_result = commands::install_packages(
+        vec![String::from("test-other-arch")],
+        &mut package_finder,
+        &ReinstallOptions::Ignore,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert!(matches!(
+        install_result,
+        Err(InstallError::Incompatible { name, field }) if name == "test-other-arch" && field == "arch"
+    ));
+}
+
+#[test]
+async fn test_watch_reports_newly_available_update() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let remote_package = package_finder.get_simple_packge().await;
+    let package_name = remote_package.package_data.name.clone();
+
+    mock_install(&mut mock_db, &remote_package);
+
+    let mut watcher = commands::UpdateWatcher::default();
+
+    let updates = watcher
+        .check(vec![], &mut package_finder, &mut mock_db)
+        .await
+        .unwrap();
+    assert!(updates.is_empty());
+
+    package_finder.update_remote_package_version(&package_name);
+
+    let updates = watcher
+        .check(vec![], &mut package_finder, &mut mock_db)
+        .await
+        .unwrap();
+    assert_eq!(
+        updates,
+        vec![commands::AvailableUpdate {
+            name: package_name,
+            installed_version: String::from("0.0.1"),
+            available_version: String::from("0.0.2"),
+        }]
+    );
+
+    // Already reported updates are not reported again
+    let updates = watcher
+        .check(vec![], &mut package_finder, &mut mock_db)
+        .await
+        .unwrap();
+    assert!(updates.is_empty());
+}
+
+#[test]
+async fn test_held_package_is_not_updated_by_system_update() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let remote_package = package_finder.get_simple_packge().await;
+
+    let package_name = remote_package.package_data.name.clone();
+
+    mock_install(&mut mock_db, &remote_package);
+    commands::set_held(vec![package_name.clone()], true, &mut mock_db).unwrap();
+
+    package_finder.update_remote_package_version(&package_name);
+
+    let update_result = commands::update_all_packages(
+        vec![],
+        &mut package_finder,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+    assert_actions(update_result, vec![]);
+
+    commands::set_held(vec![package_name.clone()], false, &mut mock_db).unwrap();
+
+    let update_result = commands::update_all_packages(
+        vec![],
+        &mut package_finder,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+    assert_eq!(update_result.unwrap().len(), 2);
+}
+
+#[test]
+async fn test_excluded_package_is_not_updated_by_system_update() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+    let remote_package = package_finder.get_simple_packge().await;
+
+    let package_name = remote_package.package_data.name.clone();
+
+    mock_install(&mut mock_db, &remote_package);
+    package_finder.update_remote_package_version(&package_name);
+
+    let update_result = commands::update_all_packages(
+        vec![package_name],
+        &mut package_finder,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+    assert_actions(update_result, vec![]);
+}
+
+#[test]
+async fn test_outdated_packages_listed() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+
+    let outdated = package_finder.get_simple_packge().await;
+    let up_to_date = get_remote_package("test-up-to-date", vec![]);
+    let failing = get_remote_package("test-failing", vec![]);
+    for package in [&outdated, &up_to_date, &failing] {
+        package_finder.add_package(package.clone());
+        mock_install(&mut mock_db, package);
+    }
+
+    let outdated_name = outdated.package_data.name.clone();
+    package_finder.update_remote_package_version(&outdated_name);
+    package_finder.set_failing("test-failing");
+
+    let listed = commands::list_outdated(&mut package_finder, &mut mock_db)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        listed,
+        vec![(outdated_name, String::from("0.0.1"), String::from("0.0.2"))]
+    );
+    assert!(package_finder
+        .searched_packages
+        .contains(&String::from("test-failing")));
+}
+
+#[test]
+async fn test_holding_not_installed_package_is_rejected() {
+    let (mut mock_db, _) = get_mocks();
+
+    assert!(matches!(
+        commands::set_held(vec![String::from("test-missing")], true, &mut mock_db),
+        Err(HoldError::PackageNotInstalled(package)) if package == "test-missing"
+    ));
+}
+
+#[test]
+async fn test_package_metadata_shown_in_info() {
+    let package = RemotePackage::from_json(
+        r#"{
+            "manifest_version": 1,
+            "package_data": {
+                "name": "test-metadata",
+                "version": "0.0.1",
+                "description": "",
+                "homepage": "https://example.com",
+                "license": "MIT"
+            },
+            "install": []
+        }"#,
+    )
+    .unwrap();
+
+    let (mut mock_db, _) = get_mocks();
+    let local_package = mock_install(&mut mock_db, &package);
+
+    assert_eq!(local_package.package_data, package.package_data);
+
+    let info = PackageInfo::from(local_package);
+    let text = info.to_string();
+    assert!(text.contains("homepage: https://example.com"));
+    assert!(text.contains("license: MIT"));
+    assert!(!text.contains("maintainer"));
+
+    let json = serde_json::to_value(&info).unwrap();
+    assert_eq!(json["name"], "test-metadata");
+    assert_eq!(json["license"], "MIT");
+    assert!(json.get("maintainer").is_none());
+}
+
+#[test]
+async fn test_preserve_order_keeps_explicit_packages_in_given_order() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+
+    let dependency = get_remote_package("test-shared-dependency", vec![]);
+    let first = get_remote_package("test-first", vec![dependency.package_data.name.clone()]);
+    let second = get_remote_package("test-second", vec![dependency.package_data.name.clone()]);
+    for package in [&dependency, &first, &second] {
+        package_finder.add_package(package.clone());
+    }
+
+    let install_result = commands::install_packages(
+        vec![
+            first.package_data.name.clone(),
+            second.package_data.name.clone(),
+        ],
+        &mut package_finder,
+        &ReinstallOptions::Ignore,
+        &InstallOptions {
+            preserve_order: true,
+            ..Default::default()
+        },
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        install_result,
+        vec![
+            Action::Install(Box::new(dependency)),
+            Action::Install(Box::new(first)),
+            Action::Install(Box::new(second)),
+        ],
+    );
+}
+
+#[test]
+async fn test_recorded_manifest_contains_only_explicit_packages() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+
+    let dependency = get_remote_package("test-manifest-dependency", vec![]);
+    let explicit = get_remote_package(
+        "test-manifest-explicit",
+        vec![dependency.package_data.name.clone()],
+    );
+    let installed = get_remote_package("test-manifest-installed", vec![]);
+    for package in [&dependency, &explicit, &installed] {
+        package_finder.add_package(package.clone());
+    }
+    mock_db.add_package(&installed).unwrap();
+
+    let packages = vec![
+        explicit.package_data.name.clone(),
+        installed.package_data.name.clone(),
+    ];
+    let actions = commands::install_packages(
+        packages.clone(),
+        &mut package_finder,
+        &ReinstallOptions::Ignore,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await
+    .unwrap();
+
+    let mut manifest = Manifest::default();
+    manifest
+        .record(commands::explicit_package_versions(&packages, &actions, &mut mock_db).unwrap());
+    assert_eq!(
+        manifest.package_names(),
+        [
+            explicit.package_data.name.clone(),
+            installed.package_data.name.clone()
+        ]
+    );
+
+    // Installing the recorded packages on a fresh system plans the same install
+    let (mut fresh_db, _) = get_mocks();
+    fresh_db.add_package(&installed).unwrap();
+    let reinstall_result = commands::install_packages(
+        manifest.package_names(),
+        &mut package_finder,
+        &ReinstallOptions::Ignore,
+        &InstallOptions::default(),
+        &mut fresh_db,
+    )
+    .await;
+
+    assert_actions(reinstall_result, actions);
+}
+
+#[test]
+async fn test_package_spec_parsed() {
+    assert_eq!(
+        commands::parse_package_spec("package@1.2.3"),
+        ("package", Some("1.2.3"))
+    );
+    assert_eq!(commands::parse_package_spec("package"), ("package", None));
+    // Suffixes that are not versions are part of the name, such as in paths
+    assert_eq!(
+        commands::parse_package_spec("/home/user@host/package.json"),
+        ("/home/user@host/package.json", None)
+    );
+    assert_eq!(commands::parse_package_spec("@1.2.3"), ("@1.2.3", None));
+}
+
+#[test]
+async fn test_pinned_version_installed() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+
+    let install_result = commands::install_packages(
+        vec![String::from("simple_package@0.0.1")],
+        &mut package_finder,
+        &ReinstallOptions::Ignore,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert_actions(
+        install_result,
+        vec![Action::Install(Box::new(
+            package_finder.get_simple_packge().await,
+        ))],
+    );
+}
+
+#[test]
+async fn test_missing_pinned_version_not_found() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+
+    let install_result = commands::install_packages(
+        vec![String::from("simple_package@9.9.9")],
+        &mut package_finder,
+        &ReinstallOptions::Ignore,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert!(matches!(
+        install_result,
+        Err(InstallError::VersionNotFound { name, version })
+            if name == "simple_package" && version == "9.9.9"
+    ));
+}
+
+#[test]
+async fn test_pinned_version_used_for_dependency() {
+    for packages in [
+        ["simple_package@0.0.1", "package_with_dependency"],
+        ["package_with_dependency", "simple_package@0.0.1"],
+    ] {
+        let (mut mock_db, mut package_finder) = get_mocks();
+
+        let install_result = commands::install_packages(
+            packages.map(String::from).to_vec(),
+            &mut package_finder,
+            &ReinstallOptions::Ignore,
+            &InstallOptions::default(),
+            &mut mock_db,
+        )
+        .await;
+
+        let actions = install_result.unwrap();
+        let simple_package_installs = actions
+            .iter()
+            .filter(|action| {
+                matches!(action, Action::Install(package)
+                    if package.package_data.name == "simple_package")
+            })
+            .count();
+        assert_eq!(simple_package_installs, 1);
+        assert_eq!(actions.len(), 2);
+        // The dependency is resolved to the pinned version instead of the latest one
+        assert!(!package_finder
+            .searched_packages
+            .contains(&String::from("simple_package")));
+    }
+}
+
+#[test]
+async fn test_conflicting_pinned_versions_rejected() {
+    let (mut mock_db, mut package_finder) = get_mocks();
+
+    let install_result = commands::install_packages(
+        vec![
+            String::from("simple_package@0.0.1"),
+            String::from("simple_package@0.0.2"),
+        ],
+        &mut package_finder,
+        &ReinstallOptions::Ignore,
+        &InstallOptions::default(),
+        &mut mock_db,
+    )
+    .await;
+
+    assert!(matches!(
+        install_result,
+        Err(InstallError::ConflictingVersions { name, .. }) if name == "simple_package"
+    ));
+}
+
+#[test]
+async fn test_versions_listed_newest_first() {
+    let (_, mut package_finder) = get_mocks();
+    package_finder.set_available_versions(
+        "simple_package",
+        &["0.2.0", "1.0.0-rc.1", "0.10.0", "0.2.0", "1.0.0"],
+    );
+
+    let versions = commands::list_versions("simple_package", &mut package_finder)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        versions
+            .iter()
+            .map(Version::to_string)
+            .collect::<Vec<String>>(),
+        ["1.0.0", "1.0.0-rc.1", "0.10.0", "0.2.0"]
+    );
+    assert!(
+        commands::list_versions("package_with_dependency", &mut package_finder)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+async fn test_transitive_dependencies_found_with_default_lookup() {
+    let (_, mut package_finder) = get_mocks();
+    package_finder.add_package(get_remote_package(
+        "test-depends",
+        vec![
+            String::from("package_with_dependency"),
+            String::from("simple_package"),
+        ],
+    ));
+
+    assert_eq!(
+        commands::find_dependencies(vec![String::from("test-depends")], &mut package_finder)
+            .await
+            .unwrap(),
+        vec!["package_with_dependency", "simple_package"]
+    );
+}
+
+#[test]
+async fn test_dependencies_of_missing_package_not_found() {
+    let (_, mut package_finder) = get_mocks();
+
+    assert!(matches!(
+        commands::find_dependencies(vec![String::from("test-missing")], &mut package_finder).await,
+        Err(DependsError::PackageNotFound(name)) if name == "test-missing"
+    ));
+}
+
+#[test]
+async fn test_default_dependencies_of_falls_back_to_package() {
+    let (_, mut package_finder) = get_mocks();
+
+    assert_eq!(
+        package_finder
+            .dependencies_of("package_with_dependency")
+            .await
+            .unwrap(),
+        Some(vec![String::from("simple_package")])
+    );
+}
+
+#[test]
+async fn test_download_size_sums_declared_sizes() {
+    let (_, mut package_finder) = get_mocks();
+    let mut first = get_remote_package("test-first-download", vec![]);
+    first.download_size = Some(1000);
+    let mut second = get_remote_package("test-second-download", vec![]);
+    second.download_size = Some(24);
+    let removed = mock_install(&mut MockPackagesDb::new(), &first);
+
+    let size = commands::estimated_download_size(
+        &[
+            Action::Install(Box::new(first)),
+            Action::Install(Box::new(second)),
+            Action::Remove(Box::new(removed)),
+        ],
+        &mut package_finder,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        size,
+        DownloadSize {
+            bytes: 1024,
+            unknown: 0
+        }
+    );
+    assert_eq!(size.to_string(), "1024 bytes");
+}
+
+#[test]
+async fn test_unknown_download_size_marks_total_as_minimum() {
+    let (_, mut package_finder) = get_mocks();
+    let mut known = get_remote_package("test-known-download", vec![]);
+    known.download_size = Some(1024);
+    let unknown = get_remote_package("test-unknown-download", vec![]);
+
+    let size = commands::estimated_download_size(
+        &[
+            Action::Install(Box::new(known)),
+            Action::Install(Box::new(unknown)),
+        ],
+        &mut package_finder,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        size.to_string(),
+        "≥ 1024 bytes (1 packages of unknown size)"
+    );
+}
+
+fn assert_actions<Error: std::fmt::Debug>(
+    result: Result<Vec<Action>, Error>,
+    expected_actions: Vec<Action>,
+) {
+    assert!(result.is_ok());
+    assert!(result.unwrap() == expected_actions);
+}
+
+#[test]
+async fn test_installed_package_names_returned() {
+    let mut db = MockPackagesDb::new();
+    for name in ["first-package", "second-package"] {
+        db.add_package(&RemotePackage {
+            package_data: PackageData {
+                name: String::from(name),
+                version: String::from("0.0.1"),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+    }
+
+    assert_eq!(
+        commands::installed_package_names(&mut db).unwrap(),
+        vec!["first-package", "second-package"]
+    );
+}
+
+fn mock_install(db: &mut MockPackagesDb, remote_package: &RemotePackage) -> LocalPackage {
+    db.add_package(remote_package)
+        .expect("Could not add mock package to db");
+
+    db.get_package(&remote_package.package_data.name.clone())
+        .unwrap()
+        .unwrap()
+}
+
+fn get_remote_package(name: &str, dependencies: Vec<String>) -> RemotePackage {
+    RemotePackage {
+        package_data: PackageData {
+            name: String::from(name),
+            version: String::from("0.0.1"),
+            ..Default::default()
+        },
+        dependencies,
+        ..Default::default()
+    }
+}
+
+fn remote_version_is_newer(remote_version: &str, local_version: &str) -> bool {
+    let mut remote_package = get_remote_package("test-versions", vec![]);
+    remote_package.package_data.version = String::from(local_version);
+    let local_package = mock_install(&mut MockPackagesDb::new(), &remote_package);
+
+    remote_package.package_data.version = String::from(remote_version);
+    remote_is_newer(&remote_package, &local_package)
 }
 
 fn get_mocks() -> (MockPackagesDb, MockPackageFinder) {
@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use semver::Version;
 
 use crate::commands::PackageFinder;
 use crate::package::{PackageData, RemotePackage};
@@ -6,8 +8,14 @@ use crate::test_helpers::errors::StringError;
 
 pub struct MockPackageFinder {
     packages_db: HashMap<String, RemotePackage>,
+    versions: HashMap<String, Vec<Version>>,
+    /// Names of the packages searched with find_package, in order
+    pub searched_packages: Vec<String>,
+    /// Packages find_package fails to find with an error
+    failing_packages: HashSet<String>,
 }
 
+#[async_trait::async_trait(?Send)]
 impl PackageFinder for MockPackageFinder {
     type Error = StringError;
 
@@ -15,8 +23,31 @@ impl PackageFinder for MockPackageFinder {
         &mut self,
         package_name: &str,
     ) -> Result<Option<RemotePackage>, Self::Error> {
+        self.searched_packages.push(String::from(package_name));
+        if self.failing_packages.contains(package_name) {
+            return Err(StringError::from("remote failed"));
+        }
         Ok(self.packages_db.get(&String::from(package_name)).cloned())
     }
+
+    async fn find_package_version(
+        &mut self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<Option<RemotePackage>, Self::Error> {
+        Ok(self
+            .packages_db
+            .get(package_name)
+            .filter(|package| package.package_data.version == version)
+            .cloned())
+    }
+
+    async fn available_versions(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Vec<Version>, Self::Error> {
+        Ok(self.versions.get(package_name).cloned().unwrap_or_default())
+    }
 }
 
 impl MockPackageFinder {
@@ -46,7 +77,31 @@ impl MockPackageFinder {
             },
         );
 
-        MockPackageFinder { packages_db }
+        MockPackageFinder {
+            packages_db,
+            versions: HashMap::new(),
+            searched_packages: Vec::new(),
+            failing_packages: HashSet::new(),
+        }
+    }
+
+    pub fn add_package(&mut self, package: RemotePackage) {
+        self.packages_db
+            .insert(package.package_data.name.clone(), package);
+    }
+
+    pub fn set_failing(&mut self, package_name: &str) {
+        self.failing_packages.insert(String::from(package_name));
+    }
+
+    pub fn set_available_versions(&mut self, package_name: &str, versions: &[&str]) {
+        self.versions.insert(
+            String::from(package_name),
+            versions
+                .iter()
+                .map(|version| Version::parse(version).unwrap())
+                .collect(),
+        );
     }
 
     pub fn update_remote_package_version(&mut self, package_name: &str) {
@@ -0,0 +1,48 @@
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use crate::Args;
+
+#[cfg(test)]
+mod tests;
+
+const BINARY_NAME: &str = "japm";
+
+/// Subcommands whose arguments are names of installed packages.
+const INSTALLED_PACKAGES_SUBCOMMANDS: &str = "remove|info|update|verify|build-archive|hold|unhold";
+
+/// Completes installed package names for [INSTALLED_PACKAGES_SUBCOMMANDS], and falls back to the
+/// generated completion otherwise.
+const BASH_INSTALLED_PACKAGES_COMPLETION: &str = r#"
+_japm_installed_packages() {
+    local current="${COMP_WORDS[COMP_CWORD]}"
+    if [[ ${COMP_CWORD} -ge 2 && "${current}" != -* ]]; then
+        case "${COMP_WORDS[1]}" in
+            SUBCOMMANDS)
+                COMPREPLY=( $(compgen -W "$(japm complete-installed 2>/dev/null)" -- "${current}") )
+                return 0
+                ;;
+        esac
+    fi
+    _japm "$@"
+}
+
+complete -F _japm_installed_packages -o bashdefault -o default japm
+"#;
+
+/// Returns the completion script for the shell. Only bash completes the names of installed
+/// packages, other shells complete the subcommands and options.
+pub fn generate(shell: Shell) -> String {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Args::command(), BINARY_NAME, &mut script);
+
+    let mut script = String::from_utf8_lossy(&script).into_owned();
+    if let Shell::Bash = shell {
+        script.push_str(
+            &BASH_INSTALLED_PACKAGES_COMPLETION
+                .replace("SUBCOMMANDS", INSTALLED_PACKAGES_SUBCOMMANDS),
+        );
+    }
+
+    script
+}
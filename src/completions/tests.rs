@@ -0,0 +1,17 @@
+use super::*;
+
+#[test]
+fn test_completion_script_includes_subcommands() {
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+        let script = generate(shell);
+
+        for subcommand in ["install", "remove", "update", "info", "completions"] {
+            assert!(
+                script.contains(subcommand),
+                "{shell} completion is missing {subcommand}"
+            );
+        }
+    }
+
+    assert!(generate(Shell::Bash).contains("japm complete-installed"));
+}
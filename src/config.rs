@@ -1,27 +1,139 @@
 use std::collections::HashMap;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use tokio::fs;
 
-use serde_json::Value as JsonValue;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use serde_json::{Map as JsonMap, Value as JsonValue};
 
 use thiserror::Error;
 
+use url::Url;
+
 use log::trace;
 
+use crate::action::Action;
+
 #[cfg(test)]
 mod tests;
 
+#[derive(Serialize)]
 pub struct Config {
-    pub remotes: HashMap<String, String>,
+    pub remotes: HashMap<String, Remote>,
+    pub database_path: String,
+    /// Seconds after which package commands are killed, no timeout if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_timeout: Option<u64>,
+    /// Command taking a filesystem snapshot, run with --snapshot before building actions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_command: Option<String>,
+    #[serde(skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    #[serde(skip_serializing_if = "ConfirmPolicy::is_empty")]
+    pub confirm: ConfirmPolicy,
+    /// Directory japm operates on instead of /, set with --root
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+/// When the planned actions are displayed and confirmed before being built, never by default
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ConfirmPolicy {
+    /// Confirm plans affecting more packages than this
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package_threshold: Option<usize>,
+    /// Confirm plans removing any package
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub removals: bool,
+}
+
+impl ConfirmPolicy {
+    fn is_empty(&self) -> bool {
+        *self == ConfirmPolicy::default()
+    }
+
+    pub fn requires_confirmation(&self, actions: &[Action]) -> bool {
+        let exceeds_threshold = self
+            .package_threshold
+            .is_some_and(|package_threshold| actions.len() > package_threshold);
+        let removes = self.removals
+            && actions
+                .iter()
+                .any(|action| matches!(action, Action::Remove(_)));
+
+        exceeds_threshold || removes
+    }
+}
+
+/// Commands run once per operation, regardless of the packages it affects
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Hooks {
+    /// Run after all actions are commited to the database
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub post_transaction: Vec<String>,
+}
+
+impl Hooks {
+    fn is_empty(&self) -> bool {
+        self.post_transaction.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Remote {
+    pub url: String,
+    /// Value of the Authorization header sent with every request to the remote
+    pub auth_header: Option<String>,
+    /// Redirects of trusted remotes to other hosts are rejected
+    pub trusted: bool,
 }
 
+/// Remotes without any options are written in the plain string form.
+impl Serialize for Remote {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.auth_header.is_none() && !self.trusted {
+            return serializer.serialize_str(&self.url);
+        }
+
+        let mut remote = serializer.serialize_struct("Remote", 3)?;
+        remote.serialize_field("url", &self.url)?;
+        if let Some(auth_header) = &self.auth_header {
+            remote.serialize_field("auth_header", auth_header)?;
+        } else {
+            remote.skip_field("auth_header")?;
+        }
+        remote.serialize_field("trusted", &self.trusted)?;
+        remote.end()
+    }
+}
+
+const DEFAULT_DATABASE_PATH: &str = "/var/lib/japm/packages.db";
+
+/// Resolves the absolute `path` under `root`, or returns it unchanged without a root.
+pub fn rooted(root: Option<&Path>, path: &str) -> String {
+    let Some(root) = root else {
+        return String::from(path);
+    };
+
+    let path = Path::new(path);
+    let relative_path = path.strip_prefix("/").unwrap_or(path);
+
+    root.join(relative_path).to_string_lossy().into_owned()
+}
+/// Environment variables starting with this prefix define remotes, named after the rest of the
+/// variable name in lowercase. For example `JAPM_REMOTE_BASE` overrides the `base` remote.
+const REMOTE_ENV_PREFIX: &str = "JAPM_REMOTE_";
+/// Environment variable replacing the url of the `base` remote in the default config.
+const DEFAULT_REMOTE_ENV: &str = "JAPM_DEFAULT_REMOTE";
+
 const DEFAULT_CONFIG: &str = r#"
 {
     "remotes": {
         "base": "https://raw.githubusercontent.com/TheAlexDev23/japm-official-packages/main/"
-    }
+    },
+    "database_path": "/var/lib/japm/packages.db"
 }"#;
 
 #[derive(Error, Debug)]
@@ -32,6 +144,8 @@ pub enum Error {
     Json(#[from] serde_json::Error),
     #[error("The config file has invalid json syntax: {0}")]
     Syntax(String),
+    #[error("Remote {0} does not exist")]
+    RemoteNotFound(String),
 }
 
 impl Config {
@@ -55,7 +169,73 @@ impl Config {
     }
 
     pub async fn write_default_config(config_path: &str) -> Result<(), io::Error> {
-        fs::write(config_path, DEFAULT_CONFIG).await
+        let default_config = Self::default_config(std::env::var(DEFAULT_REMOTE_ENV).ok());
+        fs::write(config_path, default_config).await
+    }
+
+    /// Returns the default config, with the url of the `base` remote replaced by `default_remote`
+    /// if present.
+    fn default_config(default_remote: Option<String>) -> String {
+        let Some(default_remote) = default_remote else {
+            return String::from(DEFAULT_CONFIG);
+        };
+
+        trace!("Using {default_remote} as the default remote");
+
+        let mut root: JsonValue =
+            serde_json::from_str(DEFAULT_CONFIG).expect("Default config should be valid json");
+        root["remotes"]["base"] = JsonValue::String(default_remote);
+
+        serde_json::to_string_pretty(&root).expect("Default config should be serializable")
+    }
+
+    /// Writes the config to `config_path`. Keys of the existing file that are not part of the
+    /// config are kept as they are.
+    pub async fn save(&self, config_path: &str) -> Result<(), Error> {
+        trace!("Saving config to {config_path}");
+
+        let mut root = match fs::read_to_string(config_path).await {
+            Ok(content) if !content.trim().is_empty() => {
+                match serde_json::from_str::<JsonValue>(&content)? {
+                    JsonValue::Object(root) => root,
+                    _ => JsonMap::new(),
+                }
+            }
+            Ok(_) => JsonMap::new(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => JsonMap::new(),
+            Err(error) => return Err(Error::IO(error)),
+        };
+
+        if let JsonValue::Object(config) = serde_json::to_value(self)? {
+            root.extend(config);
+        }
+
+        fs::write(config_path, serde_json::to_string_pretty(&root)?).await?;
+
+        Ok(())
+    }
+
+    /// Adds or replaces the remote called `name`.
+    pub fn add_remote(&mut self, name: &str, remote: Remote) -> Result<(), Error> {
+        Self::validate_remote_url(name, &remote.url, false)?;
+
+        if self.remotes.insert(String::from(name), remote).is_some() {
+            trace!("Replaced existing remote {name}");
+        }
+
+        Ok(())
+    }
+
+    /// Removes the remote called `name`, returns it if it existed.
+    pub fn remove_remote(&mut self, name: &str) -> Option<Remote> {
+        self.remotes.remove(name)
+    }
+
+    /// Moves the database under `root`, so that installing into another root records the
+    /// packages in the database of that root.
+    pub fn set_root(&mut self, root: &Path) {
+        self.database_path = rooted(Some(root), &self.database_path);
+        self.root = Some(root.to_path_buf());
     }
 
     pub async fn from_file(config_path: &str) -> Result<Config, Error> {
@@ -66,29 +246,143 @@ impl Config {
         Self::from_json(&config_content)
     }
 
+    /// Loads every config file in order, later files overriding the remotes of earlier ones, and
+    /// lastly applies remotes from `JAPM_REMOTE_*` environment variables. Missing and empty files
+    /// are skipped.
+    pub async fn load_layered(config_paths: &[&str]) -> Result<Config, Error> {
+        let mut config = Config {
+            remotes: HashMap::new(),
+            database_path: String::from(DEFAULT_DATABASE_PATH),
+            command_timeout: None,
+            snapshot_command: None,
+            hooks: Hooks::default(),
+            confirm: ConfirmPolicy::default(),
+            root: None,
+        };
+
+        for config_path in config_paths {
+            trace!("Parsing config layer {config_path}");
+
+            let config_content = match fs::read_to_string(config_path).await {
+                Ok(config_content) => config_content,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    trace!("Config layer {config_path} does not exist, skipping.");
+                    continue;
+                }
+                Err(error) => return Err(Error::IO(error)),
+            };
+
+            if config_content.trim().is_empty() {
+                trace!("Config layer {config_path} is empty, skipping.");
+                continue;
+            }
+
+            config.merge_json(&config_content)?;
+        }
+
+        config
+            .remotes
+            .extend(Self::get_remotes_from_env(std::env::vars()));
+
+        Ok(config)
+    }
+
+    /// Returns the path of the config file of the current user, if it can be determined.
+    pub fn user_config_path() -> Option<String> {
+        if let Ok(config_home) = std::env::var("XDG_CONFIG_HOME") {
+            return Some(format!("{config_home}/japm/config.json"));
+        }
+
+        std::env::var("HOME")
+            .ok()
+            .map(|home| format!("{home}/.config/japm/config.json"))
+    }
+
+    /// Overrides the current config with the values present in `json_content`. Unlike
+    /// [Self::from_json] all fields are optional.
+    fn merge_json(&mut self, json_content: &str) -> Result<(), Error> {
+        let root: JsonValue = serde_json::from_str(json_content)?;
+
+        if root.get("remotes").is_some() {
+            self.remotes.extend(Self::get_remotes_from_config(&root)?);
+        }
+
+        if root.get("database_path").is_some() {
+            self.database_path = Self::get_database_path_from_config(&root)?;
+        }
+
+        if root.get("command_timeout").is_some() {
+            self.command_timeout = Self::get_command_timeout_from_config(&root)?;
+        }
+
+        if root.get("snapshot_command").is_some() {
+            self.snapshot_command = Self::get_snapshot_command_from_config(&root)?;
+        }
+
+        if root.get("hooks").is_some() {
+            self.hooks = Self::get_hooks_from_config(&root)?;
+        }
+
+        if root.get("confirm").is_some() {
+            self.confirm = Self::get_confirm_policy_from_config(&root)?;
+        }
+
+        Ok(())
+    }
+
+    fn get_remotes_from_env(
+        variables: impl Iterator<Item = (String, String)>,
+    ) -> HashMap<String, Remote> {
+        variables
+            .filter_map(|(key, value)| {
+                key.strip_prefix(REMOTE_ENV_PREFIX).map(|name| {
+                    (
+                        name.to_lowercase(),
+                        Remote {
+                            url: value,
+                            ..Default::default()
+                        },
+                    )
+                })
+            })
+            .collect()
+    }
+
     pub fn from_json(json_content: &str) -> Result<Config, Error> {
+        let root: JsonValue = serde_json::from_str(json_content)?;
+
         Ok(Config {
-            remotes: Self::get_remotes_from_config(json_content)?,
+            remotes: Self::get_remotes_from_config(&root)?,
+            database_path: Self::get_database_path_from_config(&root)?,
+            command_timeout: Self::get_command_timeout_from_config(&root)?,
+            snapshot_command: Self::get_snapshot_command_from_config(&root)?,
+            hooks: Self::get_hooks_from_config(&root)?,
+            confirm: Self::get_confirm_policy_from_config(&root)?,
+            root: None,
         })
     }
 
-    fn get_remotes_from_config(config_content: &str) -> Result<HashMap<String, String>, Error> {
+    fn get_remotes_from_config(root: &JsonValue) -> Result<HashMap<String, Remote>, Error> {
         trace!("Parsing config for remotes.");
 
-        let root: JsonValue = serde_json::from_str(config_content)?;
+        let allow_file_remotes = match root.get("allow_file_remotes") {
+            Some(JsonValue::Bool(allow_file_remotes)) => *allow_file_remotes,
+            Some(_) => {
+                return Err(Error::Syntax(String::from(
+                    "\"allow_file_remotes\" should be a boolean.",
+                )))
+            }
+            None => false,
+        };
 
         match root.get("remotes") {
             Some(remotes) => match remotes.as_object() {
                 Some(remotes) => {
-                    let mut return_map: HashMap<String, String> = HashMap::new();
+                    let mut return_map: HashMap<String, Remote> = HashMap::new();
                     for (key, value) in remotes.into_iter() {
-                        if let JsonValue::String(url) = value {
-                            return_map.insert(key.clone(), url.clone());
-                        } else {
-                            return Err(Error::Syntax(String::from(
-                                "All keys and values in \"remotes\" should be strings",
-                            )));
-                        }
+                        let remote = Self::get_remote(key, value)?;
+                        Self::validate_remote_url(key, &remote.url, allow_file_remotes)?;
+                        return_map.insert(key.clone(), remote);
                     }
 
                     Ok(return_map)
@@ -100,4 +394,193 @@ impl Config {
             None => Err(Error::Syntax(String::from("Config has no remotes object."))),
         }
     }
+
+    /// A remote is either just the url string, or an object with an url and optional auth header.
+    fn get_remote(key: &str, value: &JsonValue) -> Result<Remote, Error> {
+        match value {
+            JsonValue::String(url) => Ok(Remote {
+                url: url.clone(),
+                ..Default::default()
+            }),
+            JsonValue::Object(remote) => {
+                let url = match remote.get("url") {
+                    Some(JsonValue::String(url)) => url.clone(),
+                    _ => {
+                        return Err(Error::Syntax(format!(
+                            "Remote \"{key}\" needs an \"url\" string"
+                        )))
+                    }
+                };
+
+                let auth_header = match remote.get("auth_header") {
+                    Some(JsonValue::String(auth_header)) => Some(auth_header.clone()),
+                    Some(_) => {
+                        return Err(Error::Syntax(format!(
+                            "\"auth_header\" of remote \"{key}\" should be a string"
+                        )))
+                    }
+                    None => None,
+                };
+
+                let trusted = match remote.get("trusted") {
+                    Some(JsonValue::Bool(trusted)) => *trusted,
+                    Some(_) => {
+                        return Err(Error::Syntax(format!(
+                            "\"trusted\" of remote \"{key}\" should be a boolean"
+                        )))
+                    }
+                    None => false,
+                };
+
+                Ok(Remote {
+                    url,
+                    auth_header,
+                    trusted,
+                })
+            }
+            _ => Err(Error::Syntax(String::from(
+                "All values in \"remotes\" should be strings or objects",
+            ))),
+        }
+    }
+
+    /// Remotes need to be http(s) urls with a host, or file urls when `allow_file_remotes` is set.
+    fn validate_remote_url(key: &str, url: &str, allow_file_remotes: bool) -> Result<(), Error> {
+        let parsed_url = match Url::parse(url) {
+            Ok(parsed_url) => parsed_url,
+            Err(error) => {
+                return Err(Error::Syntax(format!(
+                    "Remote \"{key}\" has an invalid url {url}: {error}"
+                )))
+            }
+        };
+
+        match parsed_url.scheme() {
+            "http" | "https" => {
+                if parsed_url.host_str().is_none() {
+                    return Err(Error::Syntax(format!(
+                        "Remote \"{key}\" has no host in url {url}"
+                    )));
+                }
+                Ok(())
+            }
+            "file" if allow_file_remotes => Ok(()),
+            scheme => Err(Error::Syntax(format!(
+                "Remote \"{key}\" has unsupported scheme {scheme} in url {url}"
+            ))),
+        }
+    }
+
+    fn get_database_path_from_config(root: &JsonValue) -> Result<String, Error> {
+        trace!("Parsing config for database path.");
+
+        match root.get("database_path") {
+            Some(JsonValue::String(database_path)) => Ok(database_path.clone()),
+            Some(_) => Err(Error::Syntax(String::from(
+                "\"database_path\" should be a string.",
+            ))),
+            None => Ok(String::from(DEFAULT_DATABASE_PATH)),
+        }
+    }
+
+    fn get_command_timeout_from_config(root: &JsonValue) -> Result<Option<u64>, Error> {
+        trace!("Parsing config for command timeout.");
+
+        match root.get("command_timeout") {
+            Some(command_timeout) => match command_timeout.as_u64() {
+                Some(command_timeout) => Ok(Some(command_timeout)),
+                None => Err(Error::Syntax(String::from(
+                    "\"command_timeout\" should be a positive amount of seconds.",
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn get_snapshot_command_from_config(root: &JsonValue) -> Result<Option<String>, Error> {
+        trace!("Parsing config for snapshot command.");
+
+        match root.get("snapshot_command") {
+            Some(JsonValue::String(snapshot_command)) => Ok(Some(snapshot_command.clone())),
+            Some(_) => Err(Error::Syntax(String::from(
+                "\"snapshot_command\" should be a string.",
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    fn get_hooks_from_config(root: &JsonValue) -> Result<Hooks, Error> {
+        trace!("Parsing config for hooks.");
+
+        let hooks = match root.get("hooks") {
+            Some(JsonValue::Object(hooks)) => hooks,
+            Some(_) => {
+                return Err(Error::Syntax(String::from(
+                    "\"hooks\" should be a json object.",
+                )))
+            }
+            None => return Ok(Hooks::default()),
+        };
+
+        let post_transaction = match hooks.get("post_transaction") {
+            Some(JsonValue::Array(commands)) => commands
+                .iter()
+                .map(|command| match command {
+                    JsonValue::String(command) => Ok(command.clone()),
+                    _ => Err(Error::Syntax(String::from(
+                        "\"post_transaction\" hooks should be strings.",
+                    ))),
+                })
+                .collect::<Result<Vec<String>, Error>>()?,
+            Some(_) => {
+                return Err(Error::Syntax(String::from(
+                    "\"post_transaction\" hooks should be an array of commands.",
+                )))
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Hooks { post_transaction })
+    }
+
+    fn get_confirm_policy_from_config(root: &JsonValue) -> Result<ConfirmPolicy, Error> {
+        trace!("Parsing config for confirm policy.");
+
+        let confirm = match root.get("confirm") {
+            Some(JsonValue::Object(confirm)) => confirm,
+            Some(_) => {
+                return Err(Error::Syntax(String::from(
+                    "\"confirm\" should be a json object.",
+                )))
+            }
+            None => return Ok(ConfirmPolicy::default()),
+        };
+
+        let package_threshold = match confirm.get("package_threshold") {
+            Some(package_threshold) => match package_threshold.as_u64() {
+                Some(package_threshold) => Some(package_threshold as usize),
+                None => {
+                    return Err(Error::Syntax(String::from(
+                        "\"package_threshold\" should be a positive amount of packages.",
+                    )))
+                }
+            },
+            None => None,
+        };
+
+        let removals = match confirm.get("removals") {
+            Some(JsonValue::Bool(removals)) => *removals,
+            Some(_) => {
+                return Err(Error::Syntax(String::from(
+                    "\"removals\" should be a boolean.",
+                )))
+            }
+            None => false,
+        };
+
+        Ok(ConfirmPolicy {
+            package_threshold,
+            removals,
+        })
+    }
 }
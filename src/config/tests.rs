@@ -1,8 +1,11 @@
 use tokio::test;
 
 use super::*;
+use crate::package::{LocalPackage, PackageData, RemotePackage};
 
 const CONFIG_PATH: &str = "/tmp/japm/tests/config.json";
+const LAYERED_CONFIG_DIRECTORY: &str = "/tmp/japm/tests/layered_config/";
+const SAVED_CONFIG_PATH: &str = "/tmp/japm/tests/saved_config.json";
 
 #[test]
 async fn test_default_config_created_properly() {
@@ -27,15 +30,26 @@ async fn test_default_config_created_properly() {
     let config = config.unwrap();
 
     assert_eq!(
-        config.remotes.get("base").unwrap(),
+        config.remotes.get("base").unwrap().url,
         "https://raw.githubusercontent.com/TheAlexDev23/japm-official-packages/main/"
     );
+    assert_eq!(config.database_path, "/var/lib/japm/packages.db");
 
     fs::remove_file(CONFIG_PATH)
         .await
         .expect("Could not cleanup test config");
 }
 
+#[test]
+async fn test_default_remote_replaced() {
+    let default_remote = String::from("https://staging.example.com/packages/");
+
+    let config = Config::from_json(&Config::default_config(Some(default_remote.clone()))).unwrap();
+
+    assert_eq!(config.remotes.get("base").unwrap().url, default_remote);
+    assert_eq!(config.database_path, "/var/lib/japm/packages.db");
+}
+
 #[test]
 async fn test_config_parsed_correctly() {
     let config = r#"
@@ -50,7 +64,7 @@ async fn test_config_parsed_correctly() {
     assert!(config.is_ok());
 
     assert_eq!(
-        config.unwrap().remotes.get("test").unwrap(),
+        config.unwrap().remotes.get("test").unwrap().url,
         "http://test.com"
     )
 }
@@ -99,3 +113,375 @@ async fn test_non_string_remotes_rejected() {
     assert!(config.is_err());
     assert!(matches!(config, Err(Error::Syntax(_))));
 }
+
+#[test]
+async fn test_database_path_parsed_correctly() {
+    let config = r#"
+{
+    "remotes": {},
+    "database_path": "/home/user/.local/share/japm/packages.db"
+}
+"#;
+
+    let config = Config::from_json(config);
+    assert!(config.is_ok());
+
+    assert_eq!(
+        config.unwrap().database_path,
+        "/home/user/.local/share/japm/packages.db"
+    );
+}
+
+#[test]
+async fn test_missing_database_path_defaults() {
+    let config = Config::from_json(r#"{ "remotes": {} }"#);
+    assert!(config.is_ok());
+
+    assert_eq!(config.unwrap().database_path, DEFAULT_DATABASE_PATH);
+}
+
+#[test]
+async fn test_non_string_database_path_rejected() {
+    let config = Config::from_json(r#"{ "remotes": {}, "database_path": 42 }"#);
+
+    assert!(config.is_err());
+    assert!(matches!(config, Err(Error::Syntax(_))));
+}
+
+#[test]
+async fn test_command_timeout_parsed_correctly() {
+    let config = Config::from_json(r#"{ "remotes": {}, "command_timeout": 30 }"#).unwrap();
+    assert_eq!(config.command_timeout, Some(30));
+
+    let config = Config::from_json(r#"{ "remotes": {} }"#).unwrap();
+    assert_eq!(config.command_timeout, None);
+
+    assert!(Config::from_json(r#"{ "remotes": {}, "command_timeout": -1 }"#).is_err());
+}
+
+#[test]
+async fn test_hooks_parsed_correctly() {
+    let config = Config::from_json(
+        r#"{ "remotes": {}, "hooks": { "post_transaction": ["update-desktop-database", "ldconfig"] } }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.hooks.post_transaction,
+        vec!["update-desktop-database", "ldconfig"]
+    );
+
+    let config = Config::from_json(r#"{ "remotes": {} }"#).unwrap();
+    assert!(config.hooks.post_transaction.is_empty());
+
+    assert!(Config::from_json(r#"{ "remotes": {}, "hooks": [] }"#).is_err());
+    assert!(
+        Config::from_json(r#"{ "remotes": {}, "hooks": { "post_transaction": [42] } }"#).is_err()
+    );
+}
+
+#[test]
+async fn test_confirm_policy_parsed_correctly() {
+    let config = Config::from_json(
+        r#"{ "remotes": {}, "confirm": { "package_threshold": 5, "removals": true } }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.confirm,
+        ConfirmPolicy {
+            package_threshold: Some(5),
+            removals: true,
+        }
+    );
+
+    let config = Config::from_json(r#"{ "remotes": {} }"#).unwrap();
+    assert_eq!(config.confirm, ConfirmPolicy::default());
+
+    assert!(
+        Config::from_json(r#"{ "remotes": {}, "confirm": { "package_threshold": -1 } }"#).is_err()
+    );
+    assert!(Config::from_json(r#"{ "remotes": {}, "confirm": { "removals": 1 } }"#).is_err());
+}
+
+#[test]
+async fn test_confirmation_required_by_policy() {
+    let install = |name: &str| {
+        Action::Install(Box::new(RemotePackage {
+            package_data: PackageData {
+                name: String::from(name),
+                ..Default::default()
+            },
+            ..Default::default()
+        }))
+    };
+    let remove = Action::Remove(Box::new(LocalPackage {
+        id: None,
+        package_data: PackageData::default(),
+        dependencies: Vec::new(),
+        replaces: Vec::new(),
+        source_remote: None,
+        arch: vec![],
+        os: vec![],
+        pre_remove: Vec::new(),
+        package_files: Vec::new(),
+        post_remove: Vec::new(),
+    }));
+
+    let policy = ConfirmPolicy {
+        package_threshold: Some(2),
+        removals: true,
+    };
+
+    // Small installs proceed without confirmation
+    assert!(!policy.requires_confirmation(&[]));
+    assert!(!policy.requires_confirmation(&[install("a"), install("b")]));
+
+    assert!(policy.requires_confirmation(&[install("a"), install("b"), install("c")]));
+    assert!(policy.requires_confirmation(std::slice::from_ref(&remove)));
+
+    // Nothing is confirmed by default
+    assert!(!ConfirmPolicy::default().requires_confirmation(&[remove]));
+}
+
+#[test]
+async fn test_layered_configs_override_remotes() {
+    let system_config = write_layer(
+        "system.json",
+        r#"{ "remotes": { "base": "http://system.com", "system": "http://system.com" } }"#,
+    )
+    .await;
+    let user_config = write_layer(
+        "user.json",
+        r#"{ "remotes": { "base": "http://user.com" }, "database_path": "/tmp/packages.db" }"#,
+    )
+    .await;
+
+    let config = Config::load_layered(&[&system_config, &user_config])
+        .await
+        .unwrap();
+
+    assert_eq!(config.remotes.get("base").unwrap().url, "http://user.com");
+    assert_eq!(
+        config.remotes.get("system").unwrap().url,
+        "http://system.com"
+    );
+    assert_eq!(config.database_path, "/tmp/packages.db");
+}
+
+#[test]
+async fn test_missing_and_empty_layers_are_skipped() {
+    let system_config = write_layer(
+        "skipped_system.json",
+        r#"{ "remotes": { "base": "http://system.com" } }"#,
+    )
+    .await;
+    let empty_config = write_layer("empty.json", "").await;
+    let missing_config = format!("{LAYERED_CONFIG_DIRECTORY}missing.json");
+
+    let config = Config::load_layered(&[&system_config, &empty_config, &missing_config])
+        .await
+        .unwrap();
+
+    assert_eq!(config.remotes.get("base").unwrap().url, "http://system.com");
+}
+
+#[test]
+async fn test_env_remotes_override_config_remotes() {
+    let variables = vec![
+        (
+            String::from("JAPM_REMOTE_BASE"),
+            String::from("http://env.com"),
+        ),
+        (
+            String::from("JAPM_REMOTE_EXTRA"),
+            String::from("http://extra.com"),
+        ),
+        (
+            String::from("UNRELATED"),
+            String::from("http://unrelated.com"),
+        ),
+    ];
+
+    let mut config = Config::from_json(r#"{ "remotes": { "base": "http://file.com" } }"#).unwrap();
+    config
+        .remotes
+        .extend(Config::get_remotes_from_env(variables.into_iter()));
+
+    assert_eq!(config.remotes.get("base").unwrap().url, "http://env.com");
+    assert_eq!(config.remotes.get("extra").unwrap().url, "http://extra.com");
+    assert_eq!(config.remotes.len(), 2);
+}
+
+async fn write_layer(name: &str, content: &str) -> String {
+    fs::create_dir_all(LAYERED_CONFIG_DIRECTORY)
+        .await
+        .expect("Could not create test config directory");
+
+    let path = format!("{LAYERED_CONFIG_DIRECTORY}{name}");
+    fs::write(&path, content)
+        .await
+        .expect("Could not write test config layer");
+
+    path
+}
+
+#[test]
+async fn test_bad_remote_scheme_rejected() {
+    let config = Config::from_json(r#"{ "remotes": { "typo": "htp://test.com" } }"#);
+
+    assert!(matches!(config, Err(Error::Syntax(message)) if message.contains("typo")));
+}
+
+#[test]
+async fn test_remote_without_host_rejected() {
+    let config = Config::from_json(r#"{ "remotes": { "no_host": "http://" } }"#);
+
+    assert!(matches!(config, Err(Error::Syntax(message)) if message.contains("no_host")));
+}
+
+#[test]
+async fn test_file_remote_requires_flag() {
+    let config = Config::from_json(r#"{ "remotes": { "local": "file:///srv/japm/" } }"#);
+    assert!(matches!(config, Err(Error::Syntax(_))));
+
+    let config = Config::from_json(
+        r#"{ "allow_file_remotes": true, "remotes": { "local": "file:///srv/japm/" } }"#,
+    );
+    assert!(config.is_ok());
+}
+
+#[test]
+async fn test_string_remote_has_no_auth_header() {
+    let config = Config::from_json(r#"{ "remotes": { "public": "https://public.com" } }"#).unwrap();
+
+    assert_eq!(
+        config.remotes.get("public").unwrap(),
+        &Remote {
+            url: String::from("https://public.com"),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+async fn test_object_remote_with_auth_header_parsed_correctly() {
+    let config = r#"
+{
+    "remotes": {
+        "private": {
+            "url": "https://private.com",
+            "auth_header": "Bearer token"
+        }
+    }
+}
+"#;
+
+    let config = Config::from_json(config).unwrap();
+
+    assert_eq!(
+        config.remotes.get("private").unwrap(),
+        &Remote {
+            url: String::from("https://private.com"),
+            auth_header: Some(String::from("Bearer token")),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+async fn test_added_remote_round_trips() {
+    fs::create_dir_all(Path::new(SAVED_CONFIG_PATH).parent().unwrap())
+        .await
+        .unwrap();
+    fs::write(
+        SAVED_CONFIG_PATH,
+        r#"{ "remotes": { "base": "http://base.com" }, "future_field": 42 }"#,
+    )
+    .await
+    .unwrap();
+
+    let mut config = Config::from_file(SAVED_CONFIG_PATH).await.unwrap();
+    config
+        .add_remote(
+            "private",
+            Remote {
+                url: String::from("https://private.com"),
+                auth_header: Some(String::from("Bearer token")),
+                trusted: true,
+            },
+        )
+        .unwrap();
+    config.save(SAVED_CONFIG_PATH).await.unwrap();
+
+    let saved_config = Config::from_file(SAVED_CONFIG_PATH).await.unwrap();
+    assert_eq!(
+        saved_config.remotes.get("base").unwrap().url,
+        "http://base.com"
+    );
+    assert_eq!(
+        saved_config.remotes.get("private").unwrap().auth_header,
+        Some(String::from("Bearer token"))
+    );
+    assert!(saved_config.remotes.get("private").unwrap().trusted);
+
+    let mut config = saved_config;
+    assert!(config.remove_remote("base").is_some());
+    assert!(config.remove_remote("base").is_none());
+    config.save(SAVED_CONFIG_PATH).await.unwrap();
+
+    let saved_config = Config::from_file(SAVED_CONFIG_PATH).await.unwrap();
+    assert!(!saved_config.remotes.contains_key("base"));
+    assert!(saved_config.remotes.contains_key("private"));
+
+    let saved_json: JsonValue =
+        serde_json::from_str(&fs::read_to_string(SAVED_CONFIG_PATH).await.unwrap()).unwrap();
+    assert_eq!(saved_json.get("future_field").unwrap(), 42);
+
+    fs::remove_file(SAVED_CONFIG_PATH)
+        .await
+        .expect("Could not cleanup test config");
+}
+
+#[test]
+async fn test_invalid_remote_not_added() {
+    let mut config = Config::from_json(r#"{ "remotes": {} }"#).unwrap();
+
+    let result = config.add_remote(
+        "typo",
+        Remote {
+            url: String::from("htp://typo.com"),
+            ..Default::default()
+        },
+    );
+
+    assert!(matches!(result, Err(Error::Syntax(_))));
+    assert!(config.remotes.is_empty());
+}
+
+#[test]
+async fn test_database_moved_under_root() {
+    let mut config =
+        Config::from_json(r#"{ "remotes": {}, "database_path": "/var/lib/japm/packages.db" }"#)
+            .unwrap();
+
+    config.set_root(Path::new("/tmp/japm/tests/root"));
+
+    assert_eq!(
+        config.database_path,
+        "/tmp/japm/tests/root/var/lib/japm/packages.db"
+    );
+}
+
+#[test]
+async fn test_rooted_path() {
+    assert_eq!(
+        rooted(
+            Some(Path::new("/tmp/japm/tests/root")),
+            "/var/lib/japm/japm.lock"
+        ),
+        "/tmp/japm/tests/root/var/lib/japm/japm.lock"
+    );
+    assert_eq!(
+        rooted(None, "/var/lib/japm/japm.lock"),
+        "/var/lib/japm/japm.lock"
+    );
+}
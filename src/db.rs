@@ -2,6 +2,7 @@ use std::fmt::Display;
 use std::fs::File;
 use std::io;
 use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use tokio::fs;
 
@@ -9,34 +10,135 @@ use log::{info, trace};
 
 use crate::package::{LocalPackage, PackageData, RemotePackage};
 
+use diesel::connection::{AnsiTransactionManager, SimpleConnection, TransactionManager};
 use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool, PooledConnection};
 
 pub use errors::*;
 
 // diesel has questionable naming
-use diesel::result::{ConnectionError, Error as QueryError};
+use diesel::result::Error as QueryError;
 
 mod errors;
+#[cfg(test)]
+mod tests;
 
 pub trait PackagesDb {
     type AddError: Display;
     type RemoveError: Display;
     type GetError: Display;
+    type TransactionError: Display;
 
+    /// Adds an installed package. An installed package with the same name is replaced, and kept in
+    /// the history like a removed one.
     fn add_package(&mut self, package: &RemotePackage) -> Result<(), Self::AddError>;
     fn remove_package(&mut self, package_name: &str) -> Result<(), Self::RemoveError>;
     fn get_package(&mut self, package_name: &str) -> Result<Option<LocalPackage>, Self::GetError>;
+    /// Checks whether a package is installed without fetching it
+    fn contains(&mut self, package_name: &str) -> Result<bool, Self::GetError>;
     fn get_all_packages(&mut self) -> Result<Vec<LocalPackage>, Self::GetError>;
     fn get_depending_packages(
         &mut self,
         package_name: &str,
     ) -> Result<Vec<LocalPackage>, Self::GetError>;
+    /// Returns the name of the installed package that registered the binary
+    fn get_binary_owner(&mut self, binary: &str) -> Result<Option<String>, Self::GetError>;
+    /// Returns the binaries registered by the installed package, sorted by name
+    fn get_package_binaries(&mut self, package_name: &str) -> Result<Vec<String>, Self::GetError>;
+    /// Returns every recorded version of a package, from oldest to newest. Removed versions are
+    /// kept in the history, the currently installed version (if any) is the last one.
+    fn get_package_versions(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Vec<LocalPackage>, Self::GetError>;
+    /// Holds an installed package back from updates, or releases it. The hold is dropped along
+    /// with the package when it is removed.
+    fn set_held(&mut self, package_name: &str, held: bool) -> Result<(), Self::AddError>;
+    fn is_held(&mut self, package_name: &str) -> Result<bool, Self::GetError>;
+
+    /// Starts a transaction, all changes made until [Self::commit_transaction] or
+    /// [Self::rollback_transaction] are applied or discarded together.
+    fn begin_transaction(&mut self) -> Result<(), Self::TransactionError>;
+    fn commit_transaction(&mut self) -> Result<(), Self::TransactionError>;
+    fn rollback_transaction(&mut self) -> Result<(), Self::TransactionError>;
 }
 
+/// SQLite only allows a single writer at a time, so all writes go through one shared connection
+/// while reads are served by a pool. Cloning the database is cheap and shares both, which allows
+/// handing it to blocking tasks without serializing their reads.
+#[derive(Clone)]
 pub struct SqlitePackagesDb {
-    connection: SqliteConnection,
+    readers: Pool<ConnectionManager<SqliteConnection>>,
+    writer: Arc<Mutex<SqliteConnection>>,
 }
 
+#[derive(Debug)]
+struct ConnectionOptions;
+
+#[derive(QueryableByName)]
+struct UserVersion {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    user_version: i32,
+}
+
+const CONNECTION_PRAGMAS: &str = "PRAGMA busy_timeout = 5000;";
+
+/// Schema migrations, applied in order. The `user_version` of the database is the number of
+/// migrations already applied, so existing migrations must never be changed, only appended to.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS packages (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        description TEXT,
+        pre_remove TEXT,
+        package_files TEXT,
+        post_remove TEXT,
+        dependencies TEXT
+    );",
+    // Dependencies of the packages installed before they were indexed are read from their json
+    "CREATE TABLE package_dependencies (
+        package TEXT NOT NULL,
+        dependency TEXT NOT NULL,
+        PRIMARY KEY (package, dependency)
+    );
+    CREATE INDEX package_dependencies_dependency ON package_dependencies (dependency);
+    INSERT OR IGNORE INTO package_dependencies (package, dependency)
+        SELECT packages.name, json_each.value FROM packages, json_each(packages.dependencies);",
+    "CREATE TABLE package_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        description TEXT,
+        pre_remove TEXT,
+        package_files TEXT,
+        post_remove TEXT,
+        dependencies TEXT
+    );",
+    "CREATE TABLE package_binaries (
+        binary TEXT PRIMARY KEY NOT NULL,
+        package TEXT NOT NULL
+    );",
+    "ALTER TABLE packages ADD COLUMN homepage TEXT;
+    ALTER TABLE packages ADD COLUMN license TEXT;
+    ALTER TABLE packages ADD COLUMN maintainer TEXT;
+    ALTER TABLE package_history ADD COLUMN homepage TEXT;
+    ALTER TABLE package_history ADD COLUMN license TEXT;
+    ALTER TABLE package_history ADD COLUMN maintainer TEXT;",
+    "ALTER TABLE packages ADD COLUMN held BOOLEAN NOT NULL DEFAULT 0;
+    ALTER TABLE package_history ADD COLUMN held BOOLEAN NOT NULL DEFAULT 0;",
+    "ALTER TABLE packages ADD COLUMN replaces TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE package_history ADD COLUMN replaces TEXT NOT NULL DEFAULT '[]';",
+    "ALTER TABLE packages ADD COLUMN source_remote TEXT;
+    ALTER TABLE package_history ADD COLUMN source_remote TEXT;",
+    "ALTER TABLE packages ADD COLUMN arch TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE packages ADD COLUMN os TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE package_history ADD COLUMN arch TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE package_history ADD COLUMN os TEXT NOT NULL DEFAULT '[]';",
+];
+
+const READERS_POOL_SIZE: u32 = 4;
+
 table! {
     packages {
         id -> Integer,
@@ -47,6 +149,50 @@ table! {
         package_files -> Text,
         post_remove -> Text,
         dependencies -> Text,
+        homepage -> Nullable<Text>,
+        license -> Nullable<Text>,
+        maintainer -> Nullable<Text>,
+        held -> Bool,
+        replaces -> Text,
+        source_remote -> Nullable<Text>,
+        arch -> Text,
+        os -> Text,
+    }
+}
+
+table! {
+    /// Previously installed versions of packages, same layout as [packages]
+    package_history {
+        id -> Integer,
+        name -> Text,
+        version -> Text,
+        description -> Text,
+        pre_remove -> Text,
+        package_files -> Text,
+        post_remove -> Text,
+        dependencies -> Text,
+        homepage -> Nullable<Text>,
+        license -> Nullable<Text>,
+        maintainer -> Nullable<Text>,
+        held -> Bool,
+        replaces -> Text,
+        source_remote -> Nullable<Text>,
+        arch -> Text,
+        os -> Text,
+    }
+}
+
+table! {
+    package_dependencies (package, dependency) {
+        package -> Text,
+        dependency -> Text,
+    }
+}
+
+table! {
+    package_binaries (binary) {
+        binary -> Text,
+        package -> Text,
     }
 }
 
@@ -65,14 +211,25 @@ struct AddPackage {
     post_remove: String,
     /// Json array of dependencies' names
     dependencies: String,
+    homepage: Option<String>,
+    license: Option<String>,
+    maintainer: Option<String>,
+    /// Json array of the names of replaced packages
+    replaces: String,
+    source_remote: Option<String>,
+    /// Json array of the supported architectures
+    arch: String,
+    /// Json array of the supported operating systems
+    os: String,
 }
 
 #[derive(Queryable, Debug)]
 #[diesel(table_name = packages)]
 /// Represents a queryable package from the package database.
 struct GetPackage {
-    /// Id is generally not used as packages are accessed with strings in the database
-    _id: i32,
+    /// Packages are generally accessed with names, the id is exposed to be able to reference them
+    /// regardless of name.
+    pub id: i32,
     pub name: String,
     pub version: String,
     pub description: String,
@@ -84,58 +241,155 @@ struct GetPackage {
     pub post_remove: String,
     /// Json array of dependencies' names
     pub dependencies: String,
+    pub homepage: Option<String>,
+    pub license: Option<String>,
+    pub maintainer: Option<String>,
+    /// Held packages are not updated
+    pub held: bool,
+    /// Json array of the names of replaced packages
+    pub replaces: String,
+    /// Url of the remote the package was installed from
+    pub source_remote: Option<String>,
+    /// Json array of the supported architectures
+    pub arch: String,
+    /// Json array of the supported operating systems
+    pub os: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = package_history)]
+/// Represents a removed package to keep in the package history
+struct AddHistoryPackage {
+    name: String,
+    version: String,
+    description: String,
+    pre_remove: String,
+    package_files: String,
+    post_remove: String,
+    dependencies: String,
+    homepage: Option<String>,
+    license: Option<String>,
+    maintainer: Option<String>,
+    held: bool,
+    replaces: String,
+    source_remote: Option<String>,
+    arch: String,
+    os: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = package_dependencies)]
+/// Represents a single package -> dependency relation in the package database
+struct AddPackageDependency {
+    package: String,
+    dependency: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = package_binaries)]
+/// Represents a binary registered by an installed package
+struct AddPackageBinary {
+    binary: String,
+    package: String,
 }
 
-const DATABASE_SOURCE: &str = "/var/lib/japm/packages.db";
 impl SqlitePackagesDb {
-    pub fn new() -> Result<SqlitePackagesDb, ConnectionError> {
-        let mut url = String::from("sqlite://");
-        url.push_str(DATABASE_SOURCE);
+    pub fn new(source: &str) -> Result<SqlitePackagesDb, ConnectError> {
+        Self::connect(format!("sqlite://{source}"), false)
+    }
+
+    /// Opens the database without ever writing to it or creating any files. The database is
+    /// treated as immutable, so changes made by other processes while it's open may not be seen.
+    pub fn open_read_only(source: &str) -> Result<SqlitePackagesDb, ConnectError> {
+        Self::connect(format!("sqlite://{source}?mode=ro&immutable=1"), true)
+    }
 
+    fn connect(url: String, read_only: bool) -> Result<SqlitePackagesDb, ConnectError> {
         trace!("Establishing SQL connection with source:\n{url}");
 
-        let connection = SqliteConnection::establish(&url)?;
+        let mut writer = SqliteConnection::establish(&url)?;
+
+        // Write ahead logging lets the readers proceed while the writer holds a transaction.
+        if !read_only {
+            writer.batch_execute("PRAGMA journal_mode = WAL;")?;
+        }
+        writer.batch_execute(CONNECTION_PRAGMAS)?;
+
+        trace!("Creating readers connection pool of size {READERS_POOL_SIZE}");
+
+        let readers = Pool::builder()
+            .max_size(READERS_POOL_SIZE)
+            .connection_customizer(Box::new(ConnectionOptions))
+            .build(ConnectionManager::<SqliteConnection>::new(url))?;
+
+        Ok(SqlitePackagesDb {
+            readers,
+            writer: Arc::new(Mutex::new(writer)),
+        })
+    }
 
-        Ok(SqlitePackagesDb { connection })
+    fn reader(
+        &self,
+    ) -> Result<PooledConnection<ConnectionManager<SqliteConnection>>, diesel::r2d2::PoolError>
+    {
+        self.readers.get()
     }
 
-    pub async fn create_db_file_if_necessary() -> Result<bool, io::Error> {
+    fn writer(&self) -> MutexGuard<'_, SqliteConnection> {
+        self.writer
+            .lock()
+            .expect("Database writer lock is poisoned")
+    }
+
+    pub async fn create_db_file_if_necessary(source: &str) -> Result<bool, io::Error> {
         trace!("Creating db file if necessary");
 
-        let database_path = Path::new(DATABASE_SOURCE);
+        let database_path = Path::new(source);
         match database_path.try_exists()? {
             true => Ok(false),
             false => {
                 info!("Database does not exist, creating...");
 
-                trace!("Creating database parent directory");
-
-                // Hardcoded directory allways has parent, unwrap is ok
-                fs::create_dir_all(database_path.parent().unwrap()).await?;
+                if let Some(parent) = database_path.parent() {
+                    trace!("Creating database parent directory");
+                    fs::create_dir_all(parent).await?;
+                }
 
                 trace!("Creating database file");
-                File::create(DATABASE_SOURCE)?;
+                File::create(database_path)?;
 
                 Ok(true)
             }
         }
     }
 
-    pub fn initialize_database(&mut self) -> Result<(), QueryError> {
-        const CREATE_TABLE_QUERY: &str = "CREATE TABLE packages (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                name TEXT NOT NULL,
-                version TEXT NOT NULL,
-                description TEXT,
-                pre_remove TEXT,
-                package_files TEXT,
-                post_remove TEXT,
-                dependencies TEXT
-            )";
-
-        trace!("Executing SQL create table query:\n{CREATE_TABLE_QUERY}");
-
-        diesel::sql_query(CREATE_TABLE_QUERY).execute(&mut self.connection)?;
+    /// Brings the schema of the database up to date, creating the tables of a new database. Must
+    /// be called every time the database is opened for writing.
+    pub fn migrate_database(&mut self) -> Result<(), QueryError> {
+        let mut connection = self.writer();
+
+        let applied = diesel::sql_query("PRAGMA user_version")
+            .get_result::<UserVersion>(&mut *connection)?
+            .user_version as usize;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+            let version = index + 1;
+            trace!("Executing SQL migration to version {version}:\n{migration}");
+
+            // Commands that don't take the japm lock may migrate concurrently, so the version is
+            // checked again once the database is locked for writing
+            connection.immediate_transaction(|connection| {
+                let applied = diesel::sql_query("PRAGMA user_version")
+                    .get_result::<UserVersion>(connection)?
+                    .user_version as usize;
+                if applied >= version {
+                    return Ok(());
+                }
+
+                connection.batch_execute(migration)?;
+                connection.batch_execute(&format!("PRAGMA user_version = {version}"))
+            })?;
+        }
 
         Ok(())
     }
@@ -145,27 +399,59 @@ impl PackagesDb for SqlitePackagesDb {
     type AddError = TranslatedPackageQueryError;
     type GetError = TranslatedPackageQueryError;
     type RemoveError = QueryError;
+    type TransactionError = QueryError;
 
     fn add_package(&mut self, package: &RemotePackage) -> Result<(), TranslatedPackageQueryError> {
         use self::packages::dsl::*;
 
         let db_package: AddPackage = package.try_into()?;
+        let db_dependencies: Vec<AddPackageDependency> = package
+            .dependencies
+            .iter()
+            .map(|package_dependency| AddPackageDependency {
+                package: package.package_data.name.clone(),
+                dependency: package_dependency.clone(),
+            })
+            .collect();
+        let db_binaries: Vec<AddPackageBinary> = package
+            .binaries
+            .iter()
+            .map(|package_binary| AddPackageBinary {
+                binary: package_binary.clone(),
+                package: package.package_data.name.clone(),
+            })
+            .collect();
 
         trace!("Inserting {db_package:#?} into the database");
 
-        diesel::insert_into(packages)
-            .values(db_package)
-            .execute(&mut self.connection)?;
+        self.writer().transaction(|connection| {
+            // Installing over an installed package replaces it
+            remove_installed_package(connection, &package.package_data.name)?;
 
-        Ok(())
-    }
+            diesel::insert_into(packages)
+                .values(db_package)
+                .execute(connection)?;
 
-    fn remove_package(&mut self, package_name: &str) -> Result<(), QueryError> {
-        use self::packages::dsl::*;
+            trace!("Inserting {db_dependencies:#?} into the database");
 
-        diesel::delete(packages.filter(name.eq(package_name))).execute(&mut self.connection)?;
+            diesel::insert_or_ignore_into(package_dependencies::table)
+                .values(&db_dependencies)
+                .execute(connection)?;
 
-        Ok(())
+            trace!("Inserting {db_binaries:#?} into the database");
+
+            // A binary is owned by the last package that registered it
+            diesel::replace_into(package_binaries::table)
+                .values(&db_binaries)
+                .execute(connection)?;
+
+            Ok(())
+        })
+    }
+
+    fn remove_package(&mut self, package_name: &str) -> Result<(), QueryError> {
+        self.writer()
+            .transaction(|connection| remove_installed_package(connection, package_name))
     }
 
     fn get_package(
@@ -176,7 +462,7 @@ impl PackagesDb for SqlitePackagesDb {
 
         match packages
             .filter(name.eq(package_name))
-            .first::<GetPackage>(&mut self.connection)
+            .first::<GetPackage>(&mut self.reader()?)
             .optional()?
         {
             Some(package) => Ok(Some(<GetPackage as TryInto<LocalPackage>>::try_into(
@@ -186,12 +472,23 @@ impl PackagesDb for SqlitePackagesDb {
         }
     }
 
+    fn contains(&mut self, package_name: &str) -> Result<bool, TranslatedPackageQueryError> {
+        use self::packages::dsl::*;
+
+        let count = packages
+            .filter(name.eq(package_name))
+            .count()
+            .get_result::<i64>(&mut self.reader()?)?;
+
+        Ok(count > 0)
+    }
+
     fn get_all_packages(&mut self) -> Result<Vec<LocalPackage>, TranslatedPackageQueryError> {
         use self::packages::dsl::*;
 
         let all_packages = packages
             .select(packages::all_columns())
-            .load::<GetPackage>(&mut self.connection)?;
+            .load::<GetPackage>(&mut self.reader()?)?;
 
         let convert_into = |item: GetPackage| -> Result<LocalPackage, TranslatedPackageQueryError> {
             match item.try_into() {
@@ -207,18 +504,168 @@ impl PackagesDb for SqlitePackagesDb {
         &mut self,
         package_name: &str,
     ) -> Result<Vec<LocalPackage>, TranslatedPackageQueryError> {
-        let all_packages = self.get_all_packages()?;
-        let mut depending_packages: Vec<LocalPackage> = Vec::new();
+        use self::packages::dsl::*;
 
-        let package_name = String::from(package_name);
+        let mut connection = self.reader()?;
 
-        for package in all_packages.into_iter() {
-            if package.dependencies.contains(&package_name) {
-                depending_packages.push(package);
+        let depending_names = package_dependencies::table
+            .filter(package_dependencies::dependency.eq(package_name))
+            .select(package_dependencies::package)
+            .load::<String>(&mut connection)?;
+
+        if depending_names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let depending_packages = packages
+            .filter(name.eq_any(depending_names))
+            .load::<GetPackage>(&mut connection)?;
+
+        let convert_into = |item: GetPackage| -> Result<LocalPackage, TranslatedPackageQueryError> {
+            match item.try_into() {
+                Ok(package) => Ok(package),
+                Err(error) => Err(TranslatedPackageQueryError::Json(error)),
             }
+        };
+
+        depending_packages.into_iter().map(convert_into).collect()
+    }
+
+    fn get_binary_owner(
+        &mut self,
+        binary: &str,
+    ) -> Result<Option<String>, TranslatedPackageQueryError> {
+        Ok(package_binaries::table
+            .filter(package_binaries::binary.eq(binary))
+            .select(package_binaries::package)
+            .first::<String>(&mut self.reader()?)
+            .optional()?)
+    }
+
+    fn get_package_binaries(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Vec<String>, TranslatedPackageQueryError> {
+        Ok(package_binaries::table
+            .filter(package_binaries::package.eq(package_name))
+            .select(package_binaries::binary)
+            .order(package_binaries::binary)
+            .load::<String>(&mut self.reader()?)?)
+    }
+
+    fn get_package_versions(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Vec<LocalPackage>, TranslatedPackageQueryError> {
+        let mut connection = self.reader()?;
+
+        let history = package_history::table
+            .filter(package_history::name.eq(package_name))
+            .order(package_history::id.asc())
+            .load::<GetPackage>(&mut connection)?;
+
+        let installed = packages::table
+            .filter(packages::name.eq(package_name))
+            .first::<GetPackage>(&mut connection)
+            .optional()?;
+
+        let mut versions = Vec::new();
+        for item in history.into_iter() {
+            let mut package: LocalPackage = item.try_into()?;
+            // History ids don't reference installed packages
+            package.id = None;
+            versions.push(package);
+        }
+
+        if let Some(installed) = installed {
+            versions.push(installed.try_into()?);
         }
 
-        Ok(depending_packages)
+        Ok(versions)
+    }
+
+    fn set_held(
+        &mut self,
+        package_name: &str,
+        package_held: bool,
+    ) -> Result<(), TranslatedPackageQueryError> {
+        use self::packages::dsl::*;
+
+        trace!("Setting held of {package_name} to {package_held}");
+
+        diesel::update(packages.filter(name.eq(package_name)))
+            .set(held.eq(package_held))
+            .execute(&mut *self.writer())?;
+
+        Ok(())
+    }
+
+    fn is_held(&mut self, package_name: &str) -> Result<bool, TranslatedPackageQueryError> {
+        use self::packages::dsl::*;
+
+        Ok(packages
+            .filter(name.eq(package_name))
+            .select(held)
+            .first::<bool>(&mut self.reader()?)
+            .optional()?
+            .unwrap_or(false))
+    }
+
+    fn begin_transaction(&mut self) -> Result<(), QueryError> {
+        trace!("Beginning database transaction");
+        AnsiTransactionManager::begin_transaction(&mut *self.writer())
+    }
+
+    fn commit_transaction(&mut self) -> Result<(), QueryError> {
+        trace!("Commiting database transaction");
+        AnsiTransactionManager::commit_transaction(&mut *self.writer())
+    }
+
+    fn rollback_transaction(&mut self) -> Result<(), QueryError> {
+        trace!("Rolling back database transaction");
+        AnsiTransactionManager::rollback_transaction(&mut *self.writer())
+    }
+}
+
+/// Moves the installed package into the history, along with its dependencies and binaries. Does
+/// nothing if the package is not installed.
+fn remove_installed_package(
+    connection: &mut SqliteConnection,
+    package_name: &str,
+) -> Result<(), QueryError> {
+    use self::packages::dsl::*;
+
+    let removed_package = packages
+        .filter(name.eq(package_name))
+        .first::<GetPackage>(connection)
+        .optional()?;
+
+    if let Some(removed_package) = removed_package {
+        trace!("Moving {removed_package:#?} into the package history");
+
+        diesel::insert_into(package_history::table)
+            .values(AddHistoryPackage::from(removed_package))
+            .execute(connection)?;
+    }
+
+    diesel::delete(packages.filter(name.eq(package_name))).execute(connection)?;
+
+    diesel::delete(
+        package_dependencies::table.filter(package_dependencies::package.eq(package_name)),
+    )
+    .execute(connection)?;
+
+    diesel::delete(package_binaries::table.filter(package_binaries::package.eq(package_name)))
+        .execute(connection)?;
+
+    Ok(())
+}
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, connection: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        connection
+            .batch_execute(CONNECTION_PRAGMAS)
+            .map_err(diesel::r2d2::Error::QueryError)
     }
 }
 
@@ -234,24 +681,61 @@ impl TryFrom<&RemotePackage> for AddPackage {
             package_files: serde_json::to_string(&package.package_files)?,
             post_remove: serde_json::to_string(&package.post_remove)?,
             dependencies: serde_json::to_string(&package.dependencies)?,
+            homepage: package.package_data.homepage.clone(),
+            license: package.package_data.license.clone(),
+            maintainer: package.package_data.maintainer.clone(),
+            replaces: serde_json::to_string(&package.replaces)?,
+            source_remote: package.source_remote.clone(),
+            arch: serde_json::to_string(&package.arch)?,
+            os: serde_json::to_string(&package.os)?,
         })
     }
 }
 
+impl From<GetPackage> for AddHistoryPackage {
+    fn from(package: GetPackage) -> Self {
+        AddHistoryPackage {
+            name: package.name,
+            version: package.version,
+            description: package.description,
+            pre_remove: package.pre_remove,
+            package_files: package.package_files,
+            post_remove: package.post_remove,
+            dependencies: package.dependencies,
+            homepage: package.homepage,
+            license: package.license,
+            maintainer: package.maintainer,
+            held: package.held,
+            replaces: package.replaces,
+            source_remote: package.source_remote,
+            arch: package.arch,
+            os: package.os,
+        }
+    }
+}
+
 impl TryInto<LocalPackage> for GetPackage {
     type Error = serde_json::Error;
 
     fn try_into(self) -> Result<LocalPackage, Self::Error> {
         Ok(LocalPackage {
+            id: Some(self.id),
             package_data: PackageData {
                 name: self.name,
                 version: self.version,
                 description: self.description,
+                homepage: self.homepage,
+                license: self.license,
+                maintainer: self.maintainer,
             },
             pre_remove: serde_json::from_str(&self.pre_remove)?,
             package_files: serde_json::from_str(&self.package_files)?,
             post_remove: serde_json::from_str(&self.post_remove)?,
             dependencies: serde_json::from_str(&self.dependencies)?,
+            replaces: serde_json::from_str(&self.replaces)?,
+            source_remote: self.source_remote,
+            arch: serde_json::from_str(&self.arch)?,
+            os: serde_json::from_str(&self.os)?,
         })
     }
 }
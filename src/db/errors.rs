@@ -1,6 +1,7 @@
 use thiserror::Error;
 
-use diesel::result::Error as QueryError;
+use diesel::r2d2::PoolError;
+use diesel::result::{ConnectionError, Error as QueryError};
 
 /// Error for performing any package db query that involves
 /// json serialization/deserialization at any point
@@ -10,4 +11,17 @@ pub enum TranslatedPackageQueryError {
     Query(#[from] QueryError),
     #[error("A json serialization error has occured: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("Could not get a connection from the pool: {0}")]
+    Pool(#[from] PoolError),
+}
+
+/// Error for connecting to the package db
+#[derive(Error, Debug)]
+pub enum ConnectError {
+    #[error("Could not establish connection: {0}")]
+    Connection(#[from] ConnectionError),
+    #[error("Could not configure connection: {0}")]
+    Query(#[from] QueryError),
+    #[error("Could not create connection pool: {0}")]
+    Pool(#[from] PoolError),
 }
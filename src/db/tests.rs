@@ -0,0 +1,372 @@
+use std::thread;
+
+use super::*;
+
+use crate::package::PackageFile;
+
+/// Every test gets its own database file as tests run in parallel
+const TEST_DATABASES_DIRECTORY: &str = "/tmp/japm/tests/";
+
+#[test]
+fn test_concurrent_reads_while_writer_holds_transaction() {
+    let mut db = get_test_db("concurrent_reads");
+
+    db.add_package(&get_mock_remote_package("committed-package", "0.0.1"))
+        .unwrap();
+
+    db.begin_transaction().unwrap();
+    db.add_package(&get_mock_remote_package("pending-package", "0.0.1"))
+        .unwrap();
+
+    thread::scope(|scope| {
+        for _ in 0..READERS_POOL_SIZE {
+            let mut reader = db.clone();
+            scope.spawn(move || {
+                assert!(reader.get_package("committed-package").unwrap().is_some());
+                // Uncommited changes of the writer are not visible to the readers
+                assert!(reader.get_package("pending-package").unwrap().is_none());
+            });
+        }
+    });
+
+    db.commit_transaction().unwrap();
+
+    assert!(db.get_package("pending-package").unwrap().is_some());
+}
+
+#[test]
+fn test_reinstalling_newer_version_keeps_old_version_in_history() {
+    let mut db = get_test_db("history");
+
+    db.add_package(&get_mock_remote_package("test-package", "0.0.1"))
+        .unwrap();
+
+    // A reinstall is a remove followed by an install
+    db.remove_package("test-package").unwrap();
+    db.add_package(&get_mock_remote_package("test-package", "0.0.2"))
+        .unwrap();
+
+    let versions: Vec<String> = db
+        .get_package_versions("test-package")
+        .unwrap()
+        .into_iter()
+        .map(|p| p.package_data.version)
+        .collect();
+
+    assert_eq!(versions, vec!["0.0.1", "0.0.2"]);
+    assert_eq!(
+        db.get_package("test-package")
+            .unwrap()
+            .unwrap()
+            .package_data
+            .version,
+        "0.0.2"
+    );
+}
+
+#[test]
+fn test_adding_installed_package_replaces_it() {
+    let mut db = get_test_db("replace");
+
+    let mut package = get_mock_remote_package("test-package", "0.0.1");
+    package.binaries = vec![String::from("test-binary")];
+    db.add_package(&package).unwrap();
+    db.add_package(&get_mock_remote_package("test-package", "0.0.2"))
+        .unwrap();
+
+    assert_eq!(db.get_all_packages().unwrap().len(), 1);
+    assert_eq!(
+        db.get_package("test-package")
+            .unwrap()
+            .unwrap()
+            .package_data
+            .version,
+        "0.0.2"
+    );
+    assert_eq!(db.get_binary_owner("test-binary").unwrap(), None);
+
+    let versions: Vec<String> = db
+        .get_package_versions("test-package")
+        .unwrap()
+        .into_iter()
+        .map(|p| p.package_data.version)
+        .collect();
+    assert_eq!(versions, vec!["0.0.1", "0.0.2"]);
+}
+
+#[test]
+fn test_package_id_is_populated_and_stable() {
+    let mut db = get_test_db("package_id");
+
+    db.add_package(&get_mock_remote_package("first-package", "0.0.1"))
+        .unwrap();
+    db.add_package(&get_mock_remote_package("second-package", "0.0.1"))
+        .unwrap();
+
+    let first_id = db.get_package("first-package").unwrap().unwrap().id;
+    let second_id = db.get_package("second-package").unwrap().unwrap().id;
+
+    assert!(first_id.is_some());
+    assert!(second_id.is_some());
+    assert_ne!(first_id, second_id);
+
+    assert_eq!(
+        db.get_package("first-package").unwrap().unwrap().id,
+        first_id
+    );
+    assert_eq!(
+        db.get_all_packages()
+            .unwrap()
+            .into_iter()
+            .find(|p| p.package_data.name == "second-package")
+            .unwrap()
+            .id,
+        second_id
+    );
+}
+
+#[test]
+fn test_binaries_registered_until_package_removed() {
+    let mut db = get_test_db("binaries");
+
+    let mut package = get_mock_remote_package("test-package", "0.0.1");
+    package.binaries = vec![
+        String::from("test-other-binary"),
+        String::from("test-binary"),
+    ];
+    db.add_package(&package).unwrap();
+
+    assert_eq!(
+        db.get_binary_owner("test-binary").unwrap(),
+        Some(String::from("test-package"))
+    );
+    assert_eq!(
+        db.get_package_binaries("test-package").unwrap(),
+        ["test-binary", "test-other-binary"]
+    );
+
+    db.remove_package("test-package").unwrap();
+
+    assert_eq!(db.get_binary_owner("test-binary").unwrap(), None);
+    assert!(db.get_package_binaries("test-package").unwrap().is_empty());
+}
+
+#[test]
+fn test_hold_dropped_when_package_removed() {
+    let mut db = get_test_db("held");
+
+    let package = get_mock_remote_package("test-package", "0.0.1");
+    db.add_package(&package).unwrap();
+    assert!(!db.is_held("test-package").unwrap());
+
+    db.set_held("test-package", true).unwrap();
+    assert!(db.is_held("test-package").unwrap());
+
+    db.remove_package("test-package").unwrap();
+    assert!(!db.is_held("test-package").unwrap());
+    assert_eq!(db.get_package_versions("test-package").unwrap().len(), 1);
+
+    db.add_package(&package).unwrap();
+    assert!(!db.is_held("test-package").unwrap());
+}
+
+#[test]
+fn test_package_files_round_trip() {
+    let mut db = get_test_db("package_files");
+
+    let mut package = get_mock_remote_package("test-package", "0.0.1");
+    package.package_files = vec![
+        PackageFile {
+            path: String::from("/usr/bin/test-package"),
+            sha256: Some("ab".repeat(32)),
+            size: Some(42),
+        },
+        PackageFile::from("/usr/share/test-package"),
+    ];
+    db.add_package(&package).unwrap();
+
+    let installed = db.get_package("test-package").unwrap().unwrap();
+    assert_eq!(installed.package_files, package.package_files);
+
+    // Files used to be stored as plain paths
+    diesel::sql_query(r#"UPDATE packages SET package_files = '["/usr/bin/test-package"]'"#)
+        .execute(&mut *db.writer())
+        .unwrap();
+
+    let installed = db.get_package("test-package").unwrap().unwrap();
+    assert_eq!(
+        installed.package_files,
+        vec![PackageFile::from("/usr/bin/test-package")]
+    );
+}
+
+#[test]
+fn test_package_metadata_round_trips() {
+    let mut db = get_test_db("metadata");
+
+    let mut package = get_mock_remote_package("test-package", "0.0.1");
+    package.package_data.homepage = Some(String::from("https://example.com"));
+    package.package_data.maintainer = Some(String::from("Maintainer <maintainer@example.com>"));
+    package.arch = vec![String::from("x86_64")];
+    package.os = vec![String::from("linux")];
+    db.add_package(&package).unwrap();
+    db.add_package(&get_mock_remote_package("bare-package", "0.0.1"))
+        .unwrap();
+
+    let installed = db.get_package("test-package").unwrap().unwrap();
+    assert_eq!(installed.package_data, package.package_data);
+    assert_eq!(installed.package_data.license, None);
+    assert_eq!(installed.arch, package.arch);
+    assert_eq!(installed.os, package.os);
+
+    let bare = db.get_package("bare-package").unwrap().unwrap();
+    assert_eq!(bare.package_data.homepage, None);
+
+    db.remove_package("test-package").unwrap();
+    let history = db.get_package_versions("test-package").unwrap();
+    assert_eq!(history[0].package_data, package.package_data);
+    assert_eq!(history[0].arch, package.arch);
+}
+
+#[test]
+fn test_check_has_no_side_effects() {
+    let source = format!("{TEST_DATABASES_DIRECTORY}check_read_only.db");
+
+    {
+        let mut db = get_test_db("check_read_only");
+        db.add_package(&get_mock_remote_package("test-package", "0.0.1"))
+            .unwrap();
+    }
+
+    let files_before = get_database_files_state(&source);
+
+    let mut db = SqlitePackagesDb::open_read_only(&source).unwrap();
+    let report = crate::commands::check_packages(None, &mut db).unwrap();
+    assert!(report.healthy);
+    assert_eq!(report.checked_packages, 1);
+
+    assert!(db
+        .add_package(&get_mock_remote_package("other-package", "0.0.1"))
+        .is_err());
+    drop(db);
+
+    assert_eq!(get_database_files_state(&source), files_before);
+}
+
+#[test]
+fn test_read_only_db_does_not_create_missing_file() {
+    let source = format!("{TEST_DATABASES_DIRECTORY}missing_read_only.db");
+    if Path::new(&source).exists() {
+        std::fs::remove_file(&source).unwrap();
+    }
+
+    assert!(SqlitePackagesDb::open_read_only(&source).is_err());
+    assert!(!Path::new(&source).exists());
+}
+
+#[test]
+fn test_migrations_applied_once() {
+    let mut db = get_test_db("migrations_applied_once");
+    db.add_package(&get_mock_remote_package("test-package", "0.0.1"))
+        .unwrap();
+
+    db.migrate_database().unwrap();
+
+    assert_eq!(get_user_version(&db), MIGRATIONS.len());
+    assert!(db.get_package("test-package").unwrap().is_some());
+}
+
+#[test]
+fn test_database_created_before_migrations_is_migrated() {
+    let source = format!("{TEST_DATABASES_DIRECTORY}unmigrated.db");
+    if Path::new(&source).exists() {
+        std::fs::remove_file(&source).unwrap();
+    }
+    std::fs::create_dir_all(TEST_DATABASES_DIRECTORY).unwrap();
+
+    let mut connection = SqliteConnection::establish(&format!("sqlite://{source}")).unwrap();
+    connection
+        .batch_execute(
+            "CREATE TABLE packages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                description TEXT,
+                pre_remove TEXT,
+                package_files TEXT,
+                post_remove TEXT,
+                dependencies TEXT
+            );
+            INSERT INTO packages
+                (name, version, description, pre_remove, package_files, post_remove, dependencies)
+                VALUES ('test-old-package', '0.0.1', '', '[]', '[\"/tmp/file\"]', '[]',
+                    '[\"test-old-dependency\"]');",
+        )
+        .unwrap();
+    drop(connection);
+
+    let mut db = SqlitePackagesDb::new(&source).unwrap();
+    db.migrate_database().unwrap();
+
+    let package = db.get_package("test-old-package").unwrap().unwrap();
+    assert_eq!(package.package_files[0].path, "/tmp/file");
+    assert_eq!(package.source_remote, None);
+    assert_eq!(
+        db.get_depending_packages("test-old-dependency")
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(get_user_version(&db), MIGRATIONS.len());
+}
+
+fn get_user_version(db: &SqlitePackagesDb) -> usize {
+    diesel::sql_query("PRAGMA user_version")
+        .get_result::<UserVersion>(&mut *db.writer())
+        .unwrap()
+        .user_version as usize
+}
+
+/// Existence, size and modification time of the database and its temporary files.
+fn get_database_files_state(source: &str) -> Vec<Option<(u64, std::time::SystemTime)>> {
+    ["", "-wal", "-shm", "-journal"]
+        .into_iter()
+        .map(|suffix| {
+            std::fs::metadata(format!("{source}{suffix}"))
+                .ok()
+                .map(|metadata| (metadata.len(), metadata.modified().unwrap()))
+        })
+        .collect()
+}
+
+fn get_test_db(name: &str) -> SqlitePackagesDb {
+    let source = format!("{TEST_DATABASES_DIRECTORY}{name}.db");
+
+    for suffix in ["", "-wal", "-shm"] {
+        let path = format!("{source}{suffix}");
+        if Path::new(&path).exists() {
+            std::fs::remove_file(path).expect("Could not remove test database");
+        }
+    }
+
+    std::fs::create_dir_all(TEST_DATABASES_DIRECTORY)
+        .expect("Could not create test database directory");
+    File::create(&source).expect("Could not create test database");
+
+    let mut db = SqlitePackagesDb::new(&source).expect("Could not connect to test database");
+    db.migrate_database()
+        .expect("Could not migrate test database");
+
+    db
+}
+
+fn get_mock_remote_package(name: &str, version: &str) -> RemotePackage {
+    RemotePackage {
+        package_data: PackageData {
+            name: String::from(name),
+            version: String::from(version),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
@@ -1,13 +1,20 @@
-use std::sync::Arc;
+use std::sync::OnceLock;
+
+use crossterm::event::KeyCode;
 
 use crate::action::Action;
+use crate::progress::ProgressUpdate;
 
-use messaging::UIWriteHandle;
+use messaging::{TransactionPlan, UIWriteHandle};
 
 pub mod messaging;
+pub mod plain;
 pub mod stdout;
 pub mod tui;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Clone)]
 pub enum MessageColor {
     White,
@@ -15,13 +22,15 @@ pub enum MessageColor {
     Green,
     Yellow,
     Purple,
+    Red,
 }
 
-static mut UI_MESSENGER: Option<Arc<UIWriteHandle>> = None;
+static UI_MESSENGER: OnceLock<UIWriteHandle> = OnceLock::new();
 
+/// Can only be set once, the frontend reading from the other end is started once.
 pub fn set_ui_messenger(messenger: UIWriteHandle) {
-    unsafe {
-        UI_MESSENGER = Some(Arc::new(messenger));
+    if UI_MESSENGER.set(messenger).is_err() {
+        panic!("The UI messenger is already set");
     }
 }
 
@@ -33,8 +42,15 @@ pub async fn display_action(action: &Action) -> Option<()> {
     get_messenger()?.display_action(action).await;
     Some(())
 }
-pub async fn set_progressbar(percentage: f32) -> Option<()> {
-    get_messenger()?.set_progressbar(percentage).await;
+/// Displays the actions planned before building them.
+pub async fn display_plan(actions: &[Action]) -> Option<()> {
+    get_messenger()?
+        .display_plan(TransactionPlan::from(actions))
+        .await;
+    Some(())
+}
+pub async fn set_progressbar(update: ProgressUpdate) -> Option<()> {
+    get_messenger()?.set_progressbar(update).await;
     Some(())
 }
 pub async fn exit() -> Option<()> {
@@ -44,6 +60,40 @@ pub async fn exit() -> Option<()> {
     Some(())
 }
 
-fn get_messenger<'a>() -> Option<&'a UIWriteHandle> {
-    unsafe { UI_MESSENGER.as_deref() }
+/// Waits for a key press the frontend doesn't handle itself. Keys pressed before waiting are
+/// ignored.
+pub async fn wait_for_key() -> Option<KeyCode> {
+    let mut key_press = get_messenger()?.key_press.lock().await;
+    while key_press.try_recv().is_ok() {}
+    key_press.recv().await
+}
+
+/// Lines of the actions display screen, the packages to install in green followed by the
+/// packages to remove in red, each section headed by its count. Empty sections are left out.
+pub fn plan_lines(plan: &TransactionPlan) -> Vec<(String, MessageColor)> {
+    let mut lines = Vec::new();
+    for (title, packages, color) in [
+        ("To install", &plan.installs, MessageColor::Green),
+        ("To remove", &plan.removes, MessageColor::Red),
+    ] {
+        if packages.is_empty() {
+            continue;
+        }
+
+        lines.push((format!("{title} ({}):", packages.len()), color.clone()));
+        for package in packages {
+            lines.push((format!("    {package}"), color.clone()));
+        }
+    }
+
+    lines
+}
+
+/// Waits until the user asks to cancel the operation, which only the TUI supports.
+pub async fn wait_for_cancel() -> Option<()> {
+    get_messenger()?.cancel.lock().await.recv().await
+}
+
+fn get_messenger() -> Option<&'static UIWriteHandle> {
+    UI_MESSENGER.get()
 }
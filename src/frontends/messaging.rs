@@ -1,50 +1,96 @@
+use crossterm::event::KeyCode;
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tokio::sync::Mutex;
 
+use crate::package::PackageData;
+use crate::progress::ProgressUpdate;
 use crate::Action;
 
 use super::MessageColor;
 
+/// Packages a transaction is about to install and remove, displayed before building it. Each
+/// package is described as `name version`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransactionPlan {
+    pub installs: Vec<String>,
+    pub removes: Vec<String>,
+}
+
+impl From<&[Action]> for TransactionPlan {
+    fn from(actions: &[Action]) -> Self {
+        let mut plan = TransactionPlan::default();
+        for action in actions.iter() {
+            match action {
+                Action::Install(package) => plan.installs.push(describe(&package.package_data)),
+                Action::Remove(package) => plan.removes.push(describe(&package.package_data)),
+            }
+        }
+
+        plan
+    }
+}
+
+fn describe(package_data: &PackageData) -> String {
+    format!("{} {}", package_data.name, package_data.version)
+}
+
 pub struct UIWriteHandle {
     messages: Mutex<UnboundedSender<(String, MessageColor)>>,
     actions: Mutex<UnboundedSender<Action>>,
-    progressbar: Mutex<UnboundedSender<f32>>,
+    plan: Mutex<UnboundedSender<TransactionPlan>>,
+    progressbar: Mutex<UnboundedSender<ProgressUpdate>>,
     exit: Mutex<UnboundedSender<()>>,
 
     /// The frontend will need to send to this receiver through [UIReadHandle::exit_finish]
     /// once the [Self::exit] procedure is finished.
     pub exit_finish: Mutex<UnboundedReceiver<()>>,
+    /// Key presses the frontend doesn't handle itself, sent through [UIReadHandle::key_press]
+    pub key_press: Mutex<UnboundedReceiver<KeyCode>>,
+    /// Requests to cancel the operation, sent through [UIReadHandle::cancel]
+    pub cancel: Mutex<UnboundedReceiver<()>>,
 }
 
 pub struct UIReadHandle {
     pub messages: UnboundedReceiver<(String, MessageColor)>,
     pub actions: UnboundedReceiver<Action>,
-    pub progressbar: UnboundedReceiver<f32>,
+    pub plan: UnboundedReceiver<TransactionPlan>,
+    pub progressbar: UnboundedReceiver<ProgressUpdate>,
     pub exit: UnboundedReceiver<()>,
     pub exit_finish: Mutex<UnboundedSender<()>>,
+    pub key_press: UnboundedSender<KeyCode>,
+    pub cancel: UnboundedSender<()>,
 }
 
 pub fn generate_message_pair() -> (UIWriteHandle, UIReadHandle) {
     let (mw, mr) = mpsc::unbounded_channel();
     let (aw, ar) = mpsc::unbounded_channel();
+    let (plw, plr) = mpsc::unbounded_channel();
     let (pw, pr) = mpsc::unbounded_channel();
     let (ew, er) = mpsc::unbounded_channel();
     let (efw, efr) = mpsc::unbounded_channel();
+    let (kw, kr) = mpsc::unbounded_channel();
+    let (cw, cr) = mpsc::unbounded_channel();
 
     (
         UIWriteHandle {
             messages: mw.into(),
             actions: aw.into(),
+            plan: plw.into(),
             progressbar: pw.into(),
             exit: ew.into(),
             exit_finish: efr.into(),
+            key_press: kr.into(),
+            cancel: cr.into(),
         },
         UIReadHandle {
             messages: mr,
             actions: ar,
+            plan: plr,
             progressbar: pr,
             exit: er,
             exit_finish: efw.into(),
+            key_press: kw,
+            cancel: cw,
         },
     )
 }
@@ -62,8 +108,12 @@ impl UIWriteHandle {
         self.actions.lock().await.send(action.clone()).unwrap();
     }
 
-    pub async fn set_progressbar(&self, percentage: f32) {
-        self.progressbar.lock().await.send(percentage).unwrap();
+    pub async fn display_plan(&self, plan: TransactionPlan) {
+        self.plan.lock().await.send(plan).unwrap();
+    }
+
+    pub async fn set_progressbar(&self, update: ProgressUpdate) {
+        self.progressbar.lock().await.send(update).unwrap();
     }
 
     pub async fn exit(&self) {
@@ -0,0 +1,58 @@
+use tokio::select;
+
+use crate::frontends::messaging::UIReadHandle;
+
+use super::MessageColor;
+
+/// Writes every message as a plain line prefixed with its severity, without colors or a
+/// progressbar, so the output stays readable when redirected to a file.
+struct PlainHandle {
+    messaging_handle: UIReadHandle,
+}
+
+pub fn init(read_handle: UIReadHandle) {
+    let mut handle = PlainHandle {
+        messaging_handle: read_handle,
+    };
+    tokio::spawn(async move { handle.update_cycle().await });
+}
+
+impl PlainHandle {
+    pub(self) async fn update_cycle(&mut self) {
+        loop {
+            if self.handle_input().await {
+                return;
+            }
+        }
+    }
+
+    async fn handle_input(&mut self) -> bool {
+        select! {
+            Some((message, color)) = self.messaging_handle.messages.recv() => {
+                let prefix = match color {
+                    MessageColor::White => "trace",
+                    MessageColor::Cyan => "debug",
+                    MessageColor::Green => "info",
+                    MessageColor::Yellow => "warn",
+                    MessageColor::Purple | MessageColor::Red => "error",
+                };
+
+                for line in message.lines() {
+                    eprintln!("[{prefix}] {line}");
+                }
+
+                false
+            }
+            Some(plan) = self.messaging_handle.plan.recv() => {
+                for (line, _) in super::plan_lines(&plan) {
+                    eprintln!("[plan] {line}");
+                }
+
+                false
+            }
+            // Progress is not displayed, but still needs to be received
+            Some(_) = self.messaging_handle.progressbar.recv() => false,
+            Some(_) = self.messaging_handle.exit.recv() => true,
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::io;
 
 use tokio::select;
 
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 use indicatif::{ProgressBar, ProgressStyle};
 
 use crate::frontends::messaging::UIReadHandle;
@@ -28,7 +28,7 @@ impl StdHandle {
         let (width, _) = crossterm::terminal::size()?;
         let progressbar = ProgressBar::new(width as u64);
         progressbar.set_style(
-            ProgressStyle::with_template("{wide_bar}")
+            ProgressStyle::with_template("{wide_bar} {msg}")
                 .unwrap()
                 .progress_chars("██ "),
         );
@@ -51,19 +51,21 @@ impl StdHandle {
     async fn handle_input(&mut self) -> bool {
         select! {
             Some((message, color)) = self.messaging_handle.messages.recv() => {
-                match color {
-                    MessageColor::White => self.progressbar.println(format!("{}", message.white())),
-                    MessageColor::Cyan => self.progressbar.println(format!("{}", message.cyan())),
-                    MessageColor::Green => self.progressbar.println(format!("{}", message.green())),
-                    MessageColor::Yellow => self.progressbar.println(format!("{}", message.yellow())),
-                    MessageColor::Purple => self.progressbar.println(format!("{}", message.purple())),
+                self.progressbar.println(format!("{}", colorize(&message, &color)));
+
+                false
+            }
+            Some(plan) = self.messaging_handle.plan.recv() => {
+                for (line, color) in super::plan_lines(&plan) {
+                    self.progressbar.println(format!("{}", colorize(&line, &color)));
                 }
 
                 false
             }
-            Some(percentage) = self.messaging_handle.progressbar.recv() => {
+            Some(update) = self.messaging_handle.progressbar.recv() => {
                 self.progressbar
-                    .set_position((self.terminal_width as f32 * percentage) as u64);
+                    .set_position((self.terminal_width as f32 * update.progress) as u64);
+                self.progressbar.set_message(update.to_string());
 
                 false
             }
@@ -75,3 +77,14 @@ impl StdHandle {
         }
     }
 }
+
+fn colorize(message: &str, color: &MessageColor) -> ColoredString {
+    match color {
+        MessageColor::White => message.white(),
+        MessageColor::Cyan => message.cyan(),
+        MessageColor::Green => message.green(),
+        MessageColor::Yellow => message.yellow(),
+        MessageColor::Purple => message.purple(),
+        MessageColor::Red => message.red(),
+    }
+}
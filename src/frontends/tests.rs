@@ -0,0 +1,104 @@
+use super::*;
+
+use crate::package::{LocalPackage, PackageData, RemotePackage};
+
+#[test]
+fn test_plan_built_from_actions() {
+    let actions = vec![
+        Action::Remove(Box::new(get_local_package("old-package"))),
+        Action::Install(Box::new(get_remote_package("new-package"))),
+        Action::Install(Box::new(get_remote_package("other-package"))),
+    ];
+
+    assert_eq!(
+        TransactionPlan::from(actions.as_slice()),
+        TransactionPlan {
+            installs: vec![
+                String::from("new-package 0.0.1"),
+                String::from("other-package 0.0.1")
+            ],
+            removes: vec![String::from("old-package 0.0.1")],
+        }
+    );
+}
+
+#[test]
+fn test_plan_groups_installs_before_removals() {
+    let lines: Vec<String> = plan_lines(&get_plan())
+        .into_iter()
+        .map(|(line, _)| line)
+        .collect();
+
+    assert_eq!(
+        lines,
+        vec![
+            "To install (2):",
+            "    new-package 0.0.1",
+            "    other-package 0.0.1",
+            "To remove (1):",
+            "    old-package 0.0.1",
+        ]
+    );
+}
+
+#[test]
+fn test_plan_sections_colored_by_action() {
+    let lines = plan_lines(&get_plan());
+
+    assert!(lines[..3]
+        .iter()
+        .all(|(_, color)| matches!(color, MessageColor::Green)));
+    assert!(lines[3..]
+        .iter()
+        .all(|(_, color)| matches!(color, MessageColor::Red)));
+}
+
+#[test]
+fn test_empty_plan_section_left_out() {
+    let lines = plan_lines(&TransactionPlan {
+        installs: vec![String::from("new-package 0.0.1")],
+        removes: vec![],
+    });
+
+    assert!(!lines.iter().any(|(line, _)| line.starts_with("To remove")));
+}
+
+fn get_plan() -> TransactionPlan {
+    TransactionPlan {
+        installs: vec![
+            String::from("new-package 0.0.1"),
+            String::from("other-package 0.0.1"),
+        ],
+        removes: vec![String::from("old-package 0.0.1")],
+    }
+}
+
+fn get_remote_package(name: &str) -> RemotePackage {
+    RemotePackage {
+        package_data: get_package_data(name),
+        ..Default::default()
+    }
+}
+
+fn get_local_package(name: &str) -> LocalPackage {
+    LocalPackage {
+        id: None,
+        package_data: get_package_data(name),
+        dependencies: vec![],
+        replaces: vec![],
+        source_remote: None,
+        arch: vec![],
+        os: vec![],
+        pre_remove: vec![],
+        package_files: vec![],
+        post_remove: vec![],
+    }
+}
+
+fn get_package_data(name: &str) -> PackageData {
+    PackageData {
+        name: String::from(name),
+        version: String::from("0.0.1"),
+        ..Default::default()
+    }
+}
@@ -2,7 +2,9 @@ use std::io;
 use std::io::Stderr;
 
 use clap::error::Result;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use tokio::select;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 use thiserror::Error;
 
@@ -16,30 +18,62 @@ use ratatui::{
 
 use crate::action::Action;
 use crate::frontends::messaging::UIReadHandle;
+use crate::progress::ProgressUpdate;
 
 use super::MessageColor;
 
-struct TuiHandle<'a> {
+#[cfg(test)]
+mod tests;
+
+struct TuiHandle {
     messaging_handle: UIReadHandle,
 
-    messages_window: TextWindow<'a>,
-    actions_window: TextWindow<'a>,
+    messages_window: TextWindow,
+    /// Not displayed on small terminals
+    actions_window: Option<TextWindow>,
     progressbar_window: ProgressbarWindow,
     terminal: Terminal<CrosstermBackend<Stderr>>,
+
+    key_events: UnboundedReceiver<KeyEvent>,
+    focus: Focus,
 }
 
-struct TextWindow<'a> {
+struct TextWindow {
     title: String,
     render_threshold: u16,
-    buffer: Text<'a>,
+    /// Messages with the color they are displayed in, which also tells their severity
+    buffer: Vec<(String, MessageColor)>,
+    /// Messages less severe than this are hidden
+    min_severity: u8,
     rect: Rect,
+    /// First displayed line while the user has scrolled up, None while following the output
+    scroll_offset: Option<u16>,
+}
+
+/// The text window scrolled with the keyboard
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Messages,
+    Actions,
 }
 
+/// Severities go from trace to error, following the colors the logger uses for each level
+const MIN_SEVERITY: u8 = 1;
+const MAX_SEVERITY: u8 = 5;
+
 struct ProgressbarWindow {
-    progress: f32,
+    update: ProgressUpdate,
     rect: Rect,
 }
 
+/// Where each window is drawn, the actions window is dropped on small terminals
+#[derive(Debug, PartialEq)]
+struct WindowLayout {
+    messages: Rect,
+    actions: Option<Rect>,
+    progressbar: Rect,
+}
+
 #[derive(Error, Debug)]
 pub enum InitializeError {
     #[error("An IO error has occured: {0}")]
@@ -55,79 +89,96 @@ pub fn init(read_handle: UIReadHandle) -> Result<(), InitializeError> {
     Ok(())
 }
 
-impl<'a> TuiHandle<'a> {
-    pub fn init(read_handle: UIReadHandle) -> Result<TuiHandle<'a>, InitializeError> {
-        const PROGRESSBAR_HEIGHT: u16 = 1;
-        const ACTIONS_WINDOW_SCALE: f32 = 0.2;
-
+impl TuiHandle {
+    pub fn init(read_handle: UIReadHandle) -> Result<TuiHandle, InitializeError> {
         let (width, height) = crossterm::terminal::size()?;
-        Self::verify_size(width, height)?;
+        let layout = WindowLayout::new(width, height)?;
 
         crossterm::terminal::enable_raw_mode()?;
         crossterm::execute!(std::io::stderr(), crossterm::terminal::EnterAlternateScreen)?;
 
-        let actions_width = (width as f32 * ACTIONS_WINDOW_SCALE) as u16;
-        let messages_width = width - actions_width;
-        let messages_height = height - PROGRESSBAR_HEIGHT;
-
-        let messages_rect = Rect {
-            x: 0,
-            y: 0,
-            height: messages_height,
-            width: messages_width,
-        };
-
-        let progressbar_rect = Rect {
-            x: 0,
-            y: messages_height,
-            height: PROGRESSBAR_HEIGHT,
-            width,
-        };
+        let message_render_threshold = layout.messages.height;
 
-        let actions_rect = Rect {
-            x: messages_width,
-            y: 0,
-            height: messages_height,
-            width: actions_width,
-        };
+        let (key_events_sender, key_events) = mpsc::unbounded_channel();
+        Self::spawn_input_reader(key_events_sender);
 
-        let message_render_threshold = messages_rect.height;
-
-        let handle = TuiHandle::<'a> {
+        let handle = TuiHandle {
             messaging_handle: read_handle,
             messages_window: TextWindow {
                 title: String::from("Output"),
                 render_threshold: message_render_threshold,
-                buffer: Text::default(),
-                rect: messages_rect,
+                buffer: Vec::new(),
+                min_severity: MIN_SEVERITY,
+                rect: layout.messages,
+                scroll_offset: None,
             },
-            actions_window: TextWindow {
-                title: String::from("Completed actions"),
+            actions_window: layout.actions.map(|actions_rect| TextWindow {
+                title: String::from("Actions"),
                 render_threshold: message_render_threshold,
-                buffer: Text::default(),
+                buffer: Vec::new(),
+                min_severity: MIN_SEVERITY,
                 rect: actions_rect,
-            },
+                scroll_offset: None,
+            }),
             progressbar_window: ProgressbarWindow {
-                progress: 0.0,
-                rect: progressbar_rect,
+                update: ProgressUpdate::default(),
+                rect: layout.progressbar,
             },
             terminal: Terminal::new(CrosstermBackend::new(std::io::stderr()))?,
+            key_events,
+            focus: Focus::Messages,
         };
 
         Ok(handle)
     }
 
-    fn verify_size(width: u16, height: u16) -> Result<(), InitializeError> {
-        const MIN_HEIGHT: u16 = 40;
-        const MIN_WIDTH: u16 = 100;
+    /// Scrolls the focused window, any other key is passed on to whoever waits for a key press.
+    /// Ctrl-C and `q` also request cancelling the operation.
+    fn handle_key(&mut self, key_event: KeyEvent) {
+        if is_cancel_key(&key_event) {
+            let _ = self.messaging_handle.cancel.send(());
+        }
 
-        if height < MIN_HEIGHT || width < MIN_WIDTH {
-            Err(InitializeError::Size(width, height, MIN_WIDTH, MIN_HEIGHT))
-        } else {
-            Ok(())
+        let focused_window = match (self.focus, &mut self.actions_window) {
+            (Focus::Actions, Some(actions_window)) => actions_window,
+            _ => &mut self.messages_window,
+        };
+
+        match key_event.code {
+            KeyCode::Up => focused_window.scroll_by(-1),
+            KeyCode::Down => focused_window.scroll_by(1),
+            KeyCode::PageUp => focused_window.scroll_by(-focused_window.page_size()),
+            KeyCode::PageDown => focused_window.scroll_by(focused_window.page_size()),
+            KeyCode::Char(level @ '1'..='5') => {
+                self.messages_window.set_min_severity(level as u8 - b'0')
+            }
+            KeyCode::Tab if self.actions_window.is_some() => {
+                self.focus = match self.focus {
+                    Focus::Messages => Focus::Actions,
+                    Focus::Actions => Focus::Messages,
+                }
+            }
+            key_code => {
+                let _ = self.messaging_handle.key_press.send(key_code);
+            }
         }
     }
 
+    /// Terminal input can only be read blocking, so it's read on its own thread.
+    fn spawn_input_reader(key_events: UnboundedSender<KeyEvent>) {
+        std::thread::spawn(move || loop {
+            match crossterm::event::read() {
+                Ok(Event::Key(key_event)) if key_event.kind == KeyEventKind::Press => {
+                    if key_events.send(key_event).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => (),
+                Err(_) => return,
+            }
+        });
+    }
+
     pub(self) async fn update_cycle(&mut self) {
         loop {
             if self.handle_input().await {
@@ -136,11 +187,16 @@ impl<'a> TuiHandle<'a> {
 
             self.terminal
                 .draw(|frame| {
-                    self.messages_window.render(frame);
-                    self.actions_window.render(frame);
+                    self.messages_window
+                        .render(frame, self.focus == Focus::Messages);
+                    if let Some(actions_window) = &self.actions_window {
+                        actions_window.render(frame, self.focus == Focus::Actions);
+                    }
 
                     frame.render_widget(
-                        Gauge::default().percent((self.progressbar_window.progress * 100.0) as u16),
+                        Gauge::default()
+                            .ratio(self.progressbar_window.update.progress as f64)
+                            .label(self.progressbar_window.update.to_string()),
                         self.progressbar_window.rect,
                     )
                 })
@@ -151,36 +207,37 @@ impl<'a> TuiHandle<'a> {
     async fn handle_input(&mut self) -> bool {
         select! {
             Some((message, color)) = self.messaging_handle.messages.recv() => {
-                let style = match color {
-                    MessageColor::White => Style::default().white(),
-                    MessageColor::Cyan => Style::default().cyan(),
-                    MessageColor::Green => Style::default().green(),
-                    MessageColor::Yellow => Style::default().yellow(),
-                    MessageColor::Purple => Style::default().magenta(),
-                };
-
-                self.messages_window
-                    .buffer
-                    .lines
-                    .push(Line::styled(message, style));
+                self.messages_window.buffer.push((message, color));
 
                 false
             }
             Some(action) = self.messaging_handle.actions.recv() => {
-                let style = match action {
-                    Action::Remove(_) => Style::default().red(),
-                    Action::Install(_) => Style::default().green(),
+                let color = match action {
+                    Action::Remove(_) => MessageColor::Red,
+                    Action::Install(_) => MessageColor::Green,
                 };
 
-                self.actions_window
-                    .buffer
-                    .lines
-                    .push(Line::styled(format!("{action}"), style));
+                if let Some(actions_window) = &mut self.actions_window {
+                    actions_window.buffer.push((format!("{action}"), color));
+                }
+
+                false
+            }
+            Some(plan) = self.messaging_handle.plan.recv() => {
+                if let Some(actions_window) = &mut self.actions_window {
+                    actions_window.buffer.extend(super::plan_lines(&plan));
+                    actions_window.buffer.push((String::from("Completed:"), MessageColor::White));
+                }
+
+                false
+            }
+            Some(update) = self.messaging_handle.progressbar.recv() => {
+                self.progressbar_window.update = update;
 
                 false
             }
-            Some(percentage) = self.messaging_handle.progressbar.recv() => {
-                self.progressbar_window.progress = percentage;
+            Some(key_event) = self.key_events.recv() => {
+                self.handle_key(key_event);
 
                 false
             }
@@ -197,24 +254,169 @@ impl<'a> TuiHandle<'a> {
     }
 }
 
-impl<'a> TextWindow<'a> {
-    fn render(&self, frame: &mut Frame) {
-        let mut scroll = self.buffer.lines.len() as i32 - self.render_threshold as i32;
+impl WindowLayout {
+    fn new(width: u16, height: u16) -> Result<WindowLayout, InitializeError> {
+        const MIN_HEIGHT: u16 = 5;
+        const MIN_WIDTH: u16 = 20;
+        // Smaller terminals only display the output and the progress bar
+        const ACTIONS_MIN_HEIGHT: u16 = 40;
+        const ACTIONS_MIN_WIDTH: u16 = 100;
 
-        if scroll < 0 {
-            scroll = 0;
+        const PROGRESSBAR_HEIGHT: u16 = 1;
+        const ACTIONS_WINDOW_SCALE: f32 = 0.2;
+
+        if height < MIN_HEIGHT || width < MIN_WIDTH {
+            return Err(InitializeError::Size(width, height, MIN_WIDTH, MIN_HEIGHT));
         }
 
+        let show_actions = width >= ACTIONS_MIN_WIDTH && height >= ACTIONS_MIN_HEIGHT;
+
+        let actions_width = if show_actions {
+            (width as f32 * ACTIONS_WINDOW_SCALE) as u16
+        } else {
+            0
+        };
+        let messages_width = width - actions_width;
+        let messages_height = height - PROGRESSBAR_HEIGHT;
+
+        Ok(WindowLayout {
+            messages: Rect {
+                x: 0,
+                y: 0,
+                height: messages_height,
+                width: messages_width,
+            },
+            actions: show_actions.then_some(Rect {
+                x: messages_width,
+                y: 0,
+                height: messages_height,
+                width: actions_width,
+            }),
+            progressbar: Rect {
+                x: 0,
+                y: messages_height,
+                height: PROGRESSBAR_HEIGHT,
+                width,
+            },
+        })
+    }
+}
+
+/// Raw mode turns Ctrl-C into a key press instead of a signal.
+fn is_cancel_key(key_event: &KeyEvent) -> bool {
+    match key_event.code {
+        KeyCode::Char('q') => true,
+        KeyCode::Char('c') => key_event.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+impl TextWindow {
+    fn render(&self, frame: &mut Frame, focused: bool) {
+        let border_style = if focused {
+            Style::default().cyan()
+        } else {
+            Style::default()
+        };
+
+        let lines: Vec<Line> = self
+            .displayed_messages()
+            .map(|(message, color)| Line::styled(message.clone(), get_style(color)))
+            .collect();
+
         frame.render_widget(
-            Paragraph::new(self.buffer.clone())
-                .scroll((scroll as u16, 0))
+            Paragraph::new(Text::from(lines))
+                .scroll((self.scroll(), 0))
                 .block(
                     Block::default()
-                        .title(self.title.as_str())
+                        .title(self.get_title())
                         .borders(Borders::ALL)
-                        .border_type(BorderType::Rounded),
+                        .border_type(BorderType::Rounded)
+                        .border_style(border_style),
                 ),
             self.rect,
         );
     }
+
+    fn displayed_messages(&self) -> impl Iterator<Item = &(String, MessageColor)> {
+        self.buffer
+            .iter()
+            .filter(|(_, color)| get_severity(color) >= self.min_severity)
+    }
+
+    /// Hides messages less severe than `min_severity`, and goes back to following the output as
+    /// the scrolled to line may not be displayed anymore.
+    fn set_min_severity(&mut self, min_severity: u8) {
+        self.min_severity = min_severity.clamp(MIN_SEVERITY, MAX_SEVERITY);
+        self.scroll_offset = None;
+    }
+
+    fn get_title(&self) -> String {
+        if self.min_severity == MIN_SEVERITY {
+            return self.title.clone();
+        }
+
+        let severity_name = match self.min_severity {
+            2 => "debug",
+            3 => "info",
+            4 => "warn",
+            _ => "error",
+        };
+
+        format!("{} ({severity_name} and above)", self.title)
+    }
+
+    /// First displayed line
+    fn scroll(&self) -> u16 {
+        let bottom = self.bottom_scroll();
+        match self.scroll_offset {
+            Some(scroll_offset) => scroll_offset.min(bottom),
+            None => bottom,
+        }
+    }
+
+    /// First displayed line when following the output
+    fn bottom_scroll(&self) -> u16 {
+        let scroll = self.displayed_messages().count() as i32 - self.render_threshold as i32;
+        scroll.clamp(0, u16::MAX as i32) as u16
+    }
+
+    /// Scrolls by `lines`, upwards if negative. Scrolling back to the bottom resumes following
+    /// the output.
+    fn scroll_by(&mut self, lines: i32) {
+        let bottom = self.bottom_scroll() as i32;
+        let scroll = (self.scroll() as i32 + lines).clamp(0, bottom);
+
+        self.scroll_offset = if scroll == bottom {
+            None
+        } else {
+            Some(scroll as u16)
+        };
+    }
+
+    /// Lines scrolled with PageUp and PageDown, the displayed lines without the borders
+    fn page_size(&self) -> i32 {
+        (self.rect.height as i32 - 2).max(1)
+    }
+}
+
+fn get_style(color: &MessageColor) -> Style {
+    match color {
+        MessageColor::White => Style::default().white(),
+        MessageColor::Cyan => Style::default().cyan(),
+        MessageColor::Green => Style::default().green(),
+        MessageColor::Yellow => Style::default().yellow(),
+        MessageColor::Purple => Style::default().magenta(),
+        MessageColor::Red => Style::default().red(),
+    }
+}
+
+fn get_severity(color: &MessageColor) -> u8 {
+    match color {
+        MessageColor::White => 1,
+        MessageColor::Cyan => 2,
+        MessageColor::Green => 3,
+        MessageColor::Yellow => 4,
+        MessageColor::Purple | MessageColor::Red => 5,
+    }
 }
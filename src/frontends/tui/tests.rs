@@ -0,0 +1,44 @@
+use super::*;
+
+#[test]
+fn test_large_terminal_shows_actions_window() {
+    let layout = WindowLayout::new(100, 40).unwrap();
+
+    assert_eq!(layout.messages, Rect::new(0, 0, 80, 39));
+    assert_eq!(layout.actions, Some(Rect::new(80, 0, 20, 39)));
+    assert_eq!(layout.progressbar, Rect::new(0, 39, 100, 1));
+}
+
+#[test]
+fn test_small_terminal_drops_actions_window() {
+    for (width, height) in [(99, 40), (100, 39), (20, 5)] {
+        let layout = WindowLayout::new(width, height).unwrap();
+
+        assert_eq!(layout.messages, Rect::new(0, 0, width, height - 1));
+        assert_eq!(layout.actions, None);
+        assert_eq!(layout.progressbar, Rect::new(0, height - 1, width, 1));
+    }
+}
+
+#[test]
+fn test_tiny_terminal_rejected() {
+    assert!(matches!(
+        WindowLayout::new(19, 40),
+        Err(InitializeError::Size(19, 40, 20, 5))
+    ));
+    assert!(matches!(
+        WindowLayout::new(100, 4),
+        Err(InitializeError::Size(100, 4, 20, 5))
+    ));
+}
+
+#[test]
+fn test_cancel_keys_recognized() {
+    assert!(is_cancel_key(&KeyEvent::from(KeyCode::Char('q'))));
+    assert!(is_cancel_key(&KeyEvent::new(
+        KeyCode::Char('c'),
+        KeyModifiers::CONTROL
+    )));
+    assert!(!is_cancel_key(&KeyEvent::from(KeyCode::Char('c'))));
+    assert!(!is_cancel_key(&KeyEvent::from(KeyCode::Char('y'))));
+}
@@ -0,0 +1,275 @@
+use std::io;
+use std::path::Path;
+
+use tokio::fs;
+
+use serde::{Deserialize, Serialize};
+
+use log::trace;
+
+use thiserror::Error;
+
+use crate::action::Action;
+use crate::package::{LocalPackage, PackageFile, RemotePackage};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Error, Debug)]
+pub enum JournalError {
+    #[error("An IO error has occured: {0}")]
+    IO(#[from] io::Error),
+    #[error("A json error has occured: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A built action waiting to be commited to the database. Install scripts are not needed after
+/// the build, so only the data that ends up in the database is kept.
+#[derive(Serialize, Deserialize)]
+enum DeferredAction {
+    Install(DeferredInstall),
+    Remove(LocalPackage),
+}
+
+/// The binaries of a package are registered when it's commited, but are not part of
+/// [LocalPackage].
+#[derive(Serialize, Deserialize)]
+struct DeferredInstall {
+    #[serde(flatten)]
+    package: LocalPackage,
+    #[serde(default)]
+    binaries: Vec<String>,
+}
+
+/// An operation that could not be completed. Built actions are kept as they are already applied
+/// to the filesystem and only need to be commited, outstanding actions still need to be built.
+#[derive(Serialize, Deserialize)]
+struct FailedOperation {
+    built: Vec<DeferredAction>,
+    outstanding: Vec<OutstandingAction>,
+}
+
+/// An action that was not built yet, so the install scripts are still needed.
+#[derive(Serialize, Deserialize)]
+enum OutstandingAction {
+    Install(OutstandingInstall),
+    Remove(LocalPackage),
+    /// An install over the installed version, with the files of the installed version which are
+    /// not part of the package json
+    Overlay(OutstandingInstall, Vec<PackageFile>),
+}
+
+/// The remote a package was found on and the files extracted from its archive are not part of
+/// the package json, so they're kept next to it.
+#[derive(Serialize, Deserialize)]
+struct OutstandingInstall {
+    #[serde(flatten)]
+    package: RemotePackage,
+    #[serde(default)]
+    source_remote: Option<String>,
+    #[serde(default)]
+    archive_files: Option<String>,
+}
+
+impl From<&RemotePackage> for OutstandingInstall {
+    fn from(package: &RemotePackage) -> Self {
+        OutstandingInstall {
+            package: package.clone(),
+            source_remote: package.source_remote.clone(),
+            archive_files: package.archive_files.clone(),
+        }
+    }
+}
+
+impl From<OutstandingInstall> for RemotePackage {
+    fn from(install: OutstandingInstall) -> Self {
+        RemotePackage {
+            source_remote: install.source_remote,
+            archive_files: install.archive_files,
+            ..install.package
+        }
+    }
+}
+
+/// Returns where the actions with a deferred commit are stored for the given database.
+pub fn deferred_commit_path(database_path: &str) -> String {
+    format!("{database_path}.deferred")
+}
+
+/// Appends the built actions to the deferred commit file, to be commited later with
+/// [read_deferred_actions].
+pub async fn defer_actions(actions: &[Action], path: &str) -> Result<(), JournalError> {
+    let mut deferred_actions = read_deferred(path).await?;
+
+    deferred_actions.extend(actions.iter().map(DeferredAction::from));
+
+    trace!(
+        "Writing {} deferred actions to {path}",
+        deferred_actions.len()
+    );
+
+    fs::write(path, serde_json::to_string(&deferred_actions)?).await?;
+
+    Ok(())
+}
+
+pub async fn read_deferred_actions(path: &str) -> Result<Vec<Action>, JournalError> {
+    Ok(read_deferred(path)
+        .await?
+        .into_iter()
+        .map(Action::from)
+        .collect())
+}
+
+pub async fn clear_deferred_actions(path: &str) -> Result<(), io::Error> {
+    remove_journal(path).await
+}
+
+/// Returns where the last failed operation is stored for the given database.
+pub fn failed_operation_path(database_path: &str) -> String {
+    format!("{database_path}.failed")
+}
+
+/// Records the operation so that it can be retried with [read_failed_operation], replacing the
+/// previously recorded one.
+pub async fn record_failed_operation(
+    built: &[Action],
+    outstanding: &[Action],
+    path: &str,
+) -> Result<(), JournalError> {
+    let failed_operation = FailedOperation {
+        built: built.iter().map(DeferredAction::from).collect(),
+        outstanding: outstanding.iter().map(OutstandingAction::from).collect(),
+    };
+
+    trace!(
+        "Recording failed operation with {} built and {} outstanding actions to {path}",
+        built.len(),
+        outstanding.len()
+    );
+
+    fs::write(path, serde_json::to_string(&failed_operation)?).await?;
+
+    Ok(())
+}
+
+/// Returns the built and outstanding actions of the last failed operation, if there is one.
+pub async fn read_failed_operation(
+    path: &str,
+) -> Result<Option<(Vec<Action>, Vec<Action>)>, JournalError> {
+    if !Path::new(path).try_exists()? {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path).await?;
+    let failed_operation: FailedOperation = serde_json::from_str(&content)?;
+
+    Ok(Some((
+        failed_operation
+            .built
+            .into_iter()
+            .map(Action::from)
+            .collect(),
+        failed_operation
+            .outstanding
+            .into_iter()
+            .map(Action::from)
+            .collect(),
+    )))
+}
+
+pub async fn clear_failed_operation(path: &str) -> Result<(), io::Error> {
+    remove_journal(path).await
+}
+
+async fn remove_journal(path: &str) -> Result<(), io::Error> {
+    if Path::new(path).try_exists()? {
+        fs::remove_file(path).await?;
+    }
+
+    Ok(())
+}
+
+async fn read_deferred(path: &str) -> Result<Vec<DeferredAction>, JournalError> {
+    if !Path::new(path).try_exists()? {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(path).await?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+impl From<&Action> for DeferredAction {
+    fn from(action: &Action) -> Self {
+        match action {
+            Action::Install(package) => DeferredAction::Install(DeferredInstall {
+                package: LocalPackage {
+                    id: None,
+                    package_data: package.package_data.clone(),
+                    dependencies: package.dependencies.clone(),
+                    replaces: package.replaces.clone(),
+                    source_remote: package.source_remote.clone(),
+                    arch: package.arch.clone(),
+                    os: package.os.clone(),
+                    pre_remove: package.pre_remove.clone(),
+                    package_files: package.package_files.clone(),
+                    post_remove: package.post_remove.clone(),
+                },
+                binaries: package.binaries.clone(),
+            }),
+            Action::Remove(package) => DeferredAction::Remove((**package).clone()),
+        }
+    }
+}
+
+impl From<DeferredAction> for Action {
+    fn from(action: DeferredAction) -> Self {
+        match action {
+            DeferredAction::Install(DeferredInstall { package, binaries }) => {
+                Action::Install(Box::new(RemotePackage {
+                    package_data: package.package_data,
+                    binaries,
+                    dependencies: package.dependencies,
+                    replaces: package.replaces,
+                    source_remote: package.source_remote,
+                    arch: package.arch,
+                    os: package.os,
+                    pre_remove: package.pre_remove,
+                    package_files: package.package_files,
+                    post_remove: package.post_remove,
+                    ..Default::default()
+                }))
+            }
+            DeferredAction::Remove(package) => Action::Remove(Box::new(package)),
+        }
+    }
+}
+
+impl From<&Action> for OutstandingAction {
+    fn from(action: &Action) -> Self {
+        match action {
+            Action::Install(package) => match &package.overlaid_files {
+                Some(overlaid_files) => {
+                    OutstandingAction::Overlay(package.as_ref().into(), overlaid_files.clone())
+                }
+                None => OutstandingAction::Install(package.as_ref().into()),
+            },
+            Action::Remove(package) => OutstandingAction::Remove((**package).clone()),
+        }
+    }
+}
+
+impl From<OutstandingAction> for Action {
+    fn from(action: OutstandingAction) -> Self {
+        match action {
+            OutstandingAction::Install(install) => Action::Install(Box::new(install.into())),
+            OutstandingAction::Remove(package) => Action::Remove(Box::new(package)),
+            OutstandingAction::Overlay(install, overlaid_files) => {
+                Action::Install(Box::new(RemotePackage {
+                    overlaid_files: Some(overlaid_files),
+                    ..install.into()
+                }))
+            }
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::path::PathBuf;
+
+use tokio::test;
+
+use super::*;
+
+use crate::action::{self, BuildOptions};
+use crate::db::PackagesDb;
+use crate::package::{PackageData, PackageFile};
+use crate::progress;
+use crate::test_helpers::{MockPackagesDb, MockProgressbar};
+
+const DEFERRED_COMMIT_PATH: &str = "/tmp/japm/tests/deferred_commit";
+const FAILED_OPERATION_PATH: &str = "/tmp/japm/tests/failed_operation";
+const RETRY_RUNS_PATH: &str = "/tmp/japm/tests/retry_runs";
+const RETRY_FLAG_PATH: &str = "/tmp/japm/tests/retry_flag";
+const ARCHIVE_OPERATION_PATH: &str = "/tmp/japm/tests/archive_failed_operation";
+const ARCHIVE_FILES_PATH: &str = "/tmp/japm/tests/journal_archive_files";
+const ARCHIVE_ROOT: &str = "/tmp/japm/tests/journal_archive_root";
+
+#[test]
+async fn test_deferred_commit_matches_normal_commit() {
+    progress::set_boxed_progress(Box::new(MockProgressbar));
+
+    clear_deferred_actions(DEFERRED_COMMIT_PATH)
+        .await
+        .expect("Could not remove previous deferred commit");
+    fs::create_dir_all(Path::new(DEFERRED_COMMIT_PATH).parent().unwrap())
+        .await
+        .expect("Could not create test directory");
+
+    let mut installed_package = get_mock_remote_package("test-deferred-installed-package");
+    installed_package.package_files = vec![PackageFile::from("/usr/bin/test-deferred-installed")];
+
+    let mut normal_db = MockPackagesDb::new();
+    let mut deferred_db = MockPackagesDb::new();
+    normal_db.add_package(&installed_package).unwrap();
+    deferred_db.add_package(&installed_package).unwrap();
+
+    let mut new_package = get_mock_remote_package("test-deferred-new-package");
+    new_package.binaries = vec![String::from("test-deferred-binary")];
+    let mut install_action = Action::Install(Box::new(new_package));
+    install_action
+        .build("/tmp/japm/test", &BuildOptions::default())
+        .unwrap();
+
+    let actions = vec![
+        Action::Remove(Box::new(
+            normal_db
+                .get_package("test-deferred-installed-package")
+                .unwrap()
+                .unwrap(),
+        )),
+        install_action,
+    ];
+
+    action::commit_actions(actions.clone(), &mut normal_db)
+        .await
+        .unwrap();
+
+    defer_actions(&actions, DEFERRED_COMMIT_PATH).await.unwrap();
+    let deferred_actions = read_deferred_actions(DEFERRED_COMMIT_PATH).await.unwrap();
+    action::commit_actions(deferred_actions, &mut deferred_db)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        normal_db.get_all_packages().unwrap(),
+        deferred_db.get_all_packages().unwrap()
+    );
+    assert_eq!(
+        deferred_db
+            .get_binary_owner("test-deferred-binary")
+            .unwrap(),
+        Some(String::from("test-deferred-new-package"))
+    );
+
+    clear_deferred_actions(DEFERRED_COMMIT_PATH).await.unwrap();
+}
+
+#[test]
+async fn test_retry_failed_applies_only_outstanding_actions() {
+    progress::set_boxed_progress(Box::new(MockProgressbar));
+
+    fs::create_dir_all("/tmp/japm/tests").await.unwrap();
+    for path in [RETRY_RUNS_PATH, RETRY_FLAG_PATH] {
+        if Path::new(path).exists() {
+            fs::remove_file(path).await.unwrap();
+        }
+    }
+
+    let mut succeeding_package = get_mock_remote_package("test-retry-succeeding-package");
+    succeeding_package.install = vec![format!("sh -c 'echo run >> {RETRY_RUNS_PATH}'")];
+
+    // Fails until the flag exists
+    let mut failing_package = get_mock_remote_package("test-retry-failing-package");
+    failing_package.install = vec![format!("test -f {RETRY_FLAG_PATH}")];
+    failing_package.source_remote = Some(String::from("https://example.com/"));
+
+    let actions = vec![
+        Action::Install(Box::new(succeeding_package)),
+        Action::Install(Box::new(failing_package)),
+    ];
+
+    let failed_build =
+        action::build_actions(actions, "/tmp/japm/test", &BuildOptions::default(), |_| {})
+            .unwrap_err();
+    assert_eq!(failed_build.built.len(), 1);
+    assert_eq!(failed_build.outstanding.len(), 1);
+
+    record_failed_operation(
+        &failed_build.built,
+        &failed_build.outstanding,
+        FAILED_OPERATION_PATH,
+    )
+    .await
+    .unwrap();
+
+    fs::write(RETRY_FLAG_PATH, "").await.unwrap();
+
+    let (mut actions, outstanding) = read_failed_operation(FAILED_OPERATION_PATH)
+        .await
+        .unwrap()
+        .unwrap();
+    actions.extend(
+        action::build_actions(
+            outstanding,
+            "/tmp/japm/test",
+            &BuildOptions::default(),
+            |_| {},
+        )
+        .unwrap(),
+    );
+
+    let mut db = MockPackagesDb::new();
+    action::commit_actions(actions, &mut db).await.unwrap();
+    clear_failed_operation(FAILED_OPERATION_PATH).await.unwrap();
+
+    assert!(db.contains("test-retry-succeeding-package").unwrap());
+    assert_eq!(
+        db.get_package("test-retry-failing-package")
+            .unwrap()
+            .unwrap()
+            .source_remote,
+        Some(String::from("https://example.com/"))
+    );
+    assert_eq!(fs::read_to_string(RETRY_RUNS_PATH).await.unwrap(), "run\n");
+    assert!(read_failed_operation(FAILED_OPERATION_PATH)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+async fn test_retried_archive_install_keeps_archive_files() {
+    progress::set_boxed_progress(Box::new(MockProgressbar));
+
+    for path in [ARCHIVE_FILES_PATH, ARCHIVE_ROOT] {
+        if Path::new(path).exists() {
+            fs::remove_dir_all(path).await.unwrap();
+        }
+    }
+    fs::create_dir_all(format!("{ARCHIVE_FILES_PATH}/opt/test-journal-archive"))
+        .await
+        .unwrap();
+    fs::write(
+        format!("{ARCHIVE_FILES_PATH}/opt/test-journal-archive/file"),
+        "archived",
+    )
+    .await
+    .unwrap();
+    fs::create_dir_all(ARCHIVE_ROOT).await.unwrap();
+
+    let mut package = get_mock_remote_package("test-journal-archive-package");
+    package.install = vec![];
+    package.archive_files = Some(String::from(ARCHIVE_FILES_PATH));
+
+    record_failed_operation(
+        &[],
+        &[Action::Install(Box::new(package))],
+        ARCHIVE_OPERATION_PATH,
+    )
+    .await
+    .unwrap();
+
+    let (_, outstanding) = read_failed_operation(ARCHIVE_OPERATION_PATH)
+        .await
+        .unwrap()
+        .unwrap();
+    clear_failed_operation(ARCHIVE_OPERATION_PATH)
+        .await
+        .unwrap();
+
+    let Some(Action::Install(package)) = outstanding.first() else {
+        panic!("Expected the outstanding archive install");
+    };
+    assert_eq!(package.archive_files.as_deref(), Some(ARCHIVE_FILES_PATH));
+
+    let options = BuildOptions {
+        root: Some(PathBuf::from(ARCHIVE_ROOT)),
+        ..Default::default()
+    };
+    action::build_actions(outstanding, "/tmp/japm/test", &options, |_| {}).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(format!("{ARCHIVE_ROOT}/opt/test-journal-archive/file"))
+            .await
+            .unwrap(),
+        "archived"
+    );
+
+    fs::remove_dir_all(ARCHIVE_ROOT).await.unwrap();
+}
+
+fn get_mock_remote_package(name: &str) -> RemotePackage {
+    RemotePackage {
+        package_data: PackageData {
+            name: String::from(name),
+            version: String::from("0.0.1"),
+            ..Default::default()
+        },
+        dependencies: vec![String::from("some-dependency")],
+        install: vec![String::from("true")],
+        ..Default::default()
+    }
+}
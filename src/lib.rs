@@ -0,0 +1,52 @@
+//! Package resolution, building and tracking behind the `japm` binary.
+//!
+//! The resolver is exposed through [commands], whose functions plan the [Action]s of an operation
+//! against any [PackagesDb] and [PackageFinder]:
+//!
+//! ```no_run
+//! # async fn plan(
+//! #     db: &mut japm_rs::db::SqlitePackagesDb,
+//! #     finder: &mut japm_rs::package_finder::DefaultPackageFinder,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! use japm_rs::commands::{self, InstallOptions, ReinstallOptions};
+//!
+//! let actions = commands::install_packages(
+//!     vec![String::from("some-package")],
+//!     finder,
+//!     &ReinstallOptions::Ignore,
+//!     &InstallOptions::default(),
+//!     db,
+//! )
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The planned actions are then built with [action::build_actions] and commited with
+//! [action::commit_actions]. Progress is reported to the progress set with
+//! [progress::set_boxed_progress], which has to be set before using the resolver.
+
+pub mod action;
+pub mod archive;
+pub mod commands;
+pub mod config;
+pub mod db;
+pub mod frontends;
+pub mod journal;
+pub mod lock;
+pub mod logger;
+pub mod manifest;
+pub mod messages;
+pub mod output;
+pub mod package;
+pub mod package_finder;
+pub mod progress;
+pub mod snapshot;
+
+#[cfg(test)]
+mod test_helpers;
+
+pub use action::Action;
+pub use db::PackagesDb;
+pub use package::*;
+pub use package_finder::PackageFinder;
@@ -0,0 +1,68 @@
+use std::fs::{self, File, TryLockError};
+use std::io;
+use std::path::Path;
+
+use log::{info, trace};
+
+use thiserror::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// Lock held for the whole run, as concurrent runs would race on the database and the build
+/// directories.
+pub const LOCK_PATH: &str = "/var/lib/japm/japm.lock";
+
+#[derive(Error, Debug)]
+pub enum LockError {
+    #[error("An IO error has occured: {0}")]
+    IO(#[from] io::Error),
+    #[error("Another japm process is running, as {0} is locked. Use --wait-lock to wait for it")]
+    Held(String),
+}
+
+/// Exclusive advisory lock on a file, released when dropped. The lock is also released by the
+/// operating system once the process exits, even without the guard being dropped.
+#[derive(Debug)]
+pub struct LockGuard {
+    file: File,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if let Err(error) = self.file.unlock() {
+            trace!("Could not unlock lock file: {error}");
+        }
+    }
+}
+
+/// Acquires an exclusive lock on the file at `path`, creating it if needed. Fails with
+/// [LockError::Held] if another process holds the lock, unless `wait` is set, in which case it
+/// blocks until the lock is released.
+pub fn lock(path: &Path, wait: bool) -> Result<LockGuard, LockError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?;
+
+    match file.try_lock() {
+        Ok(()) => (),
+        Err(TryLockError::WouldBlock) if wait => {
+            info!("Waiting for another japm process to finish");
+            file.lock()?;
+        }
+        Err(TryLockError::WouldBlock) => {
+            return Err(LockError::Held(path.to_string_lossy().to_string()))
+        }
+        Err(TryLockError::Error(error)) => return Err(LockError::IO(error)),
+    }
+
+    trace!("Acquired lock {path:?}");
+
+    Ok(LockGuard { file })
+}
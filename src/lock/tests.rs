@@ -0,0 +1,17 @@
+use super::*;
+
+const TEST_LOCK_PATH: &str = "/tmp/japm/tests/japm.lock";
+
+#[test]
+fn test_lock_is_exclusive_until_dropped() {
+    let guard = lock(Path::new(TEST_LOCK_PATH), false).unwrap();
+
+    assert!(matches!(
+        lock(Path::new(TEST_LOCK_PATH), false),
+        Err(LockError::Held(_))
+    ));
+
+    drop(guard);
+
+    assert!(lock(Path::new(TEST_LOCK_PATH), false).is_ok());
+}
@@ -1,25 +1,189 @@
-use std::io;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use log::{Level, Log};
+use log::{Level, LevelFilter, Log, Metadata};
 
 use crate::frontends::{self, MessageColor};
 
+#[cfg(test)]
+mod tests;
+
 const LINE_START: &str = "==>";
 
+/// Target of the records that make up the final summary of an operation, which are displayed even
+/// in summary only mode.
+pub const SUMMARY_TARGET: &str = "summary";
+
+/// Environment variable with comma separated log directives, either a level for all targets or
+/// `target=level` for a module and its submodules. For example
+/// `JAPM_LOG=warn,japm_rs::commands=debug`.
+pub const LOG_FILTER_ENV: &str = "JAPM_LOG";
+
+/// Log files bigger than this are rotated when opened
+const MAX_LOG_FILE_SIZE: u64 = 1024 * 1024;
+
 pub struct FrontendLogger {
     rt: tokio::runtime::Runtime,
+    /// Only display errors and the final summary
+    summary_only: bool,
+    filter: LogFilter,
+    file_sink: Option<FileSink>,
+}
+
+/// Log file every record is written to, including the ones hidden in summary only mode.
+pub struct FileSink {
+    file: Mutex<File>,
+}
+
+/// Maximum level of records per target
+#[derive(Debug, PartialEq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
 }
 
 impl FrontendLogger {
-    pub fn new() -> Result<FrontendLogger, io::Error> {
+    pub fn new(summary_only: bool, filter: LogFilter) -> Result<FrontendLogger, io::Error> {
         Ok(FrontendLogger {
             rt: tokio::runtime::Runtime::new()?,
+            summary_only,
+            filter,
+            file_sink: None,
+        })
+    }
+
+    pub fn with_file_sink(mut self, file_sink: FileSink) -> FrontendLogger {
+        self.file_sink = Some(file_sink);
+        self
+    }
+
+    fn is_displayed(&self, metadata: &Metadata) -> bool {
+        let shown_in_summary =
+            metadata.level() == Level::Error || metadata.target() == SUMMARY_TARGET;
+
+        if self.summary_only {
+            shown_in_summary
+        } else {
+            shown_in_summary || self.filter.enabled(metadata)
+        }
+    }
+}
+
+impl FileSink {
+    /// Opens the log file for appending, moving it to `<path>.1` first if it grew bigger than
+    /// [MAX_LOG_FILE_SIZE].
+    pub fn open(path: &str) -> Result<FileSink, io::Error> {
+        let path = Path::new(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if fs::metadata(path).is_ok_and(|metadata| metadata.len() > MAX_LOG_FILE_SIZE) {
+            let mut rotated_path = PathBuf::from(path).into_os_string();
+            rotated_path.push(".1");
+            fs::rename(path, rotated_path)?;
+        }
+
+        Ok(FileSink {
+            file: Mutex::new(OpenOptions::new().create(true).append(true).open(path)?),
         })
     }
+
+    /// Writes synchronously so that records are not lost if japm crashes right after.
+    fn write(&self, record: &log::Record) {
+        let line = format!(
+            "{} [{}] {}: {}\n",
+            format_timestamp(SystemTime::now()),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+
+        if let Ok(mut file) = self.file.lock() {
+            // There is nowhere left to report a failing logger to
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn new(default: LevelFilter) -> LogFilter {
+        LogFilter {
+            default,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Applies the directives of [LOG_FILTER_ENV] if set on top of `default`.
+    pub fn from_env(default: LevelFilter) -> LogFilter {
+        match std::env::var(LOG_FILTER_ENV) {
+            Ok(directives) => Self::parse(&directives, default),
+            Err(_) => Self::new(default),
+        }
+    }
+
+    /// Invalid directives are ignored, as they can't be reported before the logger is set.
+    pub fn parse(directives: &str, default: LevelFilter) -> LogFilter {
+        let mut filter = Self::new(default);
+
+        for directive in directives.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if let Ok(level) = level.trim().parse() {
+                        filter.targets.push((String::from(target.trim()), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = directive.parse() {
+                        filter.default = level;
+                    }
+                }
+            }
+        }
+
+        filter
+    }
+
+    /// Level of the most specific directive matching the target, a directive matches its module
+    /// and all submodules.
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(filter_target, _)| {
+                target
+                    .strip_prefix(filter_target.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(filter_target, _)| filter_target.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// Most verbose level of any target, records above it are never logged.
+    pub fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
 }
 
 impl Log for FrontendLogger {
     fn log(&self, record: &log::Record) {
+        if let Some(file_sink) = &self.file_sink {
+            file_sink.write(record);
+        }
+
+        if !self.is_displayed(record.metadata()) {
+            return;
+        }
+
         let msg = format!("{}", record.args());
         let message = format!("{} [{}] {}", LINE_START, record.level(), msg);
 
@@ -42,11 +206,40 @@ impl Log for FrontendLogger {
         });
     }
 
-    fn enabled(&self, _: &log::Metadata) -> bool {
-        // Idk how this is supposed to work if we set the logging level when setting the global
-        // logger. And this function afaik doesn't even get called
-        true
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.is_displayed(metadata)
     }
 
     fn flush(&self) {}
 }
+
+/// Formats the time as an UTC `YYYY-MM-DD HH:MM:SS` timestamp.
+fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use log::{MetadataBuilder, Record};
+
+use super::*;
+
+#[test]
+fn test_summary_only_displays_summary_and_errors() {
+    let logger = FrontendLogger::new(true, LogFilter::new(LevelFilter::Trace)).unwrap();
+
+    let package_line = MetadataBuilder::new()
+        .level(Level::Info)
+        .target("japm::commands")
+        .build();
+    let summary_line = MetadataBuilder::new()
+        .level(Level::Info)
+        .target(SUMMARY_TARGET)
+        .build();
+    let error_line = MetadataBuilder::new()
+        .level(Level::Error)
+        .target("japm::commands")
+        .build();
+
+    assert!(!logger.is_displayed(&package_line));
+    assert!(logger.is_displayed(&summary_line));
+    assert!(logger.is_displayed(&error_line));
+}
+
+#[test]
+fn test_all_lines_displayed_by_default() {
+    let logger = FrontendLogger::new(false, LogFilter::new(LevelFilter::Trace)).unwrap();
+
+    let package_line = MetadataBuilder::new()
+        .level(Level::Info)
+        .target("japm::commands")
+        .build();
+
+    assert!(logger.is_displayed(&package_line));
+}
+
+#[test]
+fn test_log_filter_parsed() {
+    let filter = LogFilter::parse(
+        "warn, japm_rs::commands=debug,japm_rs::action=trace,bad=level,loud",
+        LevelFilter::Info,
+    );
+
+    assert_eq!(
+        filter,
+        LogFilter {
+            default: LevelFilter::Warn,
+            targets: vec![
+                (String::from("japm_rs::commands"), LevelFilter::Debug),
+                (String::from("japm_rs::action"), LevelFilter::Trace),
+            ],
+        }
+    );
+    assert_eq!(filter.max_level(), LevelFilter::Trace);
+}
+
+#[test]
+fn test_log_filter_matches_most_specific_module() {
+    let filter = LogFilter::parse(
+        "japm_rs::commands=debug,japm_rs::commands::install=error",
+        LevelFilter::Info,
+    );
+
+    assert_eq!(filter.level_for("japm_rs::commands"), LevelFilter::Debug);
+    assert_eq!(
+        filter.level_for("japm_rs::commands::remove"),
+        LevelFilter::Debug
+    );
+    assert_eq!(
+        filter.level_for("japm_rs::commands::install::deps"),
+        LevelFilter::Error
+    );
+    // A module sharing the name prefix is not a submodule
+    assert_eq!(filter.level_for("japm_rs::commandsx"), LevelFilter::Info);
+    assert_eq!(filter.level_for("japm_rs::action"), LevelFilter::Info);
+}
+
+#[test]
+fn test_records_displayed_per_target() {
+    let logger = FrontendLogger::new(
+        false,
+        LogFilter::parse("japm_rs::commands=debug", LevelFilter::Info),
+    )
+    .unwrap();
+
+    let commands_debug = MetadataBuilder::new()
+        .level(Level::Debug)
+        .target("japm_rs::commands")
+        .build();
+    let action_debug = MetadataBuilder::new()
+        .level(Level::Debug)
+        .target("japm_rs::action")
+        .build();
+    let action_info = MetadataBuilder::new()
+        .level(Level::Info)
+        .target("japm_rs::action")
+        .build();
+
+    assert!(logger.is_displayed(&commands_debug));
+    assert!(!logger.is_displayed(&action_debug));
+    assert!(logger.is_displayed(&action_info));
+}
+
+#[test]
+fn test_file_sink_writes_every_record() {
+    const LOG_FILE_PATH: &str = "/tmp/japm/tests/logger/japm.log";
+    let _ = fs::remove_file(LOG_FILE_PATH);
+
+    let logger = FrontendLogger::new(true, LogFilter::new(LevelFilter::Trace))
+        .unwrap()
+        .with_file_sink(FileSink::open(LOG_FILE_PATH).unwrap());
+
+    logger.log(
+        &Record::builder()
+            .level(Level::Info)
+            .target("japm_rs::commands")
+            .args(format_args!("Installing package"))
+            .build(),
+    );
+    logger.log(
+        &Record::builder()
+            .level(Level::Error)
+            .target("japm_rs::action")
+            .args(format_args!("Command failed"))
+            .build(),
+    );
+
+    let lines: Vec<String> = fs::read_to_string(LOG_FILE_PATH)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+
+    // The info record is hidden in summary only mode, but still written to the file
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("[INFO] japm_rs::commands: Installing package"));
+    assert!(lines[1].ends_with("[ERROR] japm_rs::action: Command failed"));
+}
+
+#[test]
+fn test_big_log_file_rotated() {
+    const LOG_FILE_PATH: &str = "/tmp/japm/tests/logger/rotated.log";
+    fs::create_dir_all("/tmp/japm/tests/logger").unwrap();
+    fs::write(LOG_FILE_PATH, vec![b'a'; MAX_LOG_FILE_SIZE as usize + 1]).unwrap();
+
+    FileSink::open(LOG_FILE_PATH).unwrap();
+
+    assert_eq!(fs::metadata(LOG_FILE_PATH).unwrap().len(), 0);
+    assert_eq!(
+        fs::metadata(format!("{LOG_FILE_PATH}.1")).unwrap().len(),
+        MAX_LOG_FILE_SIZE + 1
+    );
+}
+
+#[test]
+fn test_timestamp_formatted() {
+    let time = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+    assert_eq!(format_timestamp(time), "2024-02-29 23:59:59");
+    assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01 00:00:00");
+}
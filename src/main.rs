@@ -1,43 +1,93 @@
 use std::error::Error;
-use std::fmt::Display;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use tokio::join;
+use clap::error::ErrorKind;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use crossterm::event::KeyCode;
+use crossterm::tty::IsTty;
 
-use clap::{ArgAction, Parser, Subcommand};
+use log::{debug, error, info, warn};
+use semver::Version;
 
-use log::{debug, error, info};
+use japm_rs::action::{self, Action};
+use japm_rs::commands::BuildArchiveError;
+use japm_rs::config::{self, Config, Remote};
+use japm_rs::db::{SqlitePackagesDb, TranslatedPackageQueryError};
+use japm_rs::journal::JournalError;
+use japm_rs::lock::{self, LockGuard};
+use japm_rs::logger::{FileSink, FrontendLogger, LogFilter, SUMMARY_TARGET};
+use japm_rs::manifest::{self, Manifest};
+use japm_rs::output::{self, OutputFormat};
+use japm_rs::package::{self, LintSeverity, RemotePackage};
+use japm_rs::package_finder::DefaultPackageFinder;
+use japm_rs::progress::{self, FrontendProgress, ProgressType};
+use japm_rs::{commands, frontends, journal, snapshot};
 
-use action::Action;
-use config::Config;
-use db::{PackagesDb, SqlitePackagesDb};
-use logger::FrontendLogger;
-use package_finder::DefaultPackageFinder;
-use progress::{FrontendProgress, ProgressType};
-
-mod action;
-mod commands;
-mod config;
-mod db;
-mod frontends;
-mod logger;
-mod package;
-mod package_finder;
-mod progress;
+mod completions;
 
 #[cfg(test)]
-mod test_helpers;
+mod tests;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    arg_required_else_help = true,
+    after_help = EXIT_CODES_HELP
+)]
 struct Args {
     #[arg(short, long, action=ArgAction::SetTrue)]
     verbose: bool,
     #[arg(long, action=ArgAction::SetTrue)]
     no_tui: bool,
+    /// Display plain lines without colors or progressbar, the default when stderr is not a
+    /// terminal
+    #[arg(long, action=ArgAction::SetTrue)]
+    plain: bool,
+    /// Build the actions without commiting them to the database, use commit-deferred to commit
+    /// them later
+    #[arg(long, action=ArgAction::SetTrue)]
+    defer_commit: bool,
+    /// Only display errors and the final summary of what was installed and removed
+    #[arg(long, action=ArgAction::SetTrue)]
+    summary_only: bool,
+    /// Seconds after which package commands are killed, overrides the command_timeout config
+    #[arg(long)]
+    command_timeout: Option<u64>,
+    /// Take a filesystem snapshot with the configured snapshot_command before building actions
+    #[arg(long, action=ArgAction::SetTrue)]
+    snapshot: bool,
+    /// Install packages into this directory instead of /. The config, database, lock, build
+    /// directory and archive cache are also taken from under it
+    #[arg(long)]
+    root: Option<String>,
+    /// Also write every log record to this file, with timestamps
+    #[arg(long)]
+    log_file: Option<String>,
+    /// Format of the results of info, versions and update --check. Json is printed to stdout and
+    /// implies --plain
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+    /// Maximum amount of packages built at once, all cores by default. 1 builds them one after
+    /// another
+    #[arg(short, long)]
+    jobs: Option<NonZeroUsize>,
+    /// Proceed without confirmation even if the confirm policy of the config requires it
+    #[arg(short, long, action=ArgAction::SetTrue)]
+    yes: bool,
+    /// Wait for other running japm processes to finish instead of failing
+    #[arg(long, action=ArgAction::SetTrue)]
+    wait_lock: bool,
     #[command(subcommand)]
     /// Command to perform
-    command: Option<CommandType>,
+    command: CommandType,
 }
 
 #[derive(Debug, Subcommand)]
@@ -47,178 +97,1045 @@ enum CommandType {
         from_file: bool,
         #[arg(short, long, action=ArgAction::SetTrue)]
         reinstall: bool,
+        /// Reinstall the dependencies of the packages too, recursively. Implies --reinstall
+        #[arg(long, action=ArgAction::SetTrue)]
+        reinstall_deps: bool,
+        /// Reinstall over the installed files instead of removing the packages first, for
+        /// packages whose install can be repeated safely
+        #[arg(long, action=ArgAction::SetTrue, requires = "reinstall")]
+        overlay: bool,
+        /// Explain why already installed packages are skipped
+        #[arg(long, action=ArgAction::SetTrue)]
+        explain_skip: bool,
+        /// Reinstall already installed packages that are missing some of their files
+        #[arg(long, action=ArgAction::SetTrue)]
+        reinstall_if_files_missing: bool,
+        /// Install the packages one after another in the given order, each after its dependencies
+        #[arg(long, action=ArgAction::SetTrue)]
+        preserve_order: bool,
+        /// Also install the packages of a manifest written with --record-manifest
+        #[arg(long)]
+        manifest: Option<String>,
+        /// Record the given packages and their installed versions to a manifest, merging them
+        /// into it if it exists
+        #[arg(long, conflicts_with = "from_file")]
+        record_manifest: Option<String>,
+        #[arg(required_unless_present = "manifest")]
         packages: Vec<String>,
     },
     Remove {
         #[arg(short, long, action=ArgAction::SetTrue)]
         recursive: bool,
+        /// List the packages and files that would be removed without removing them
+        #[arg(long, alias = "simulate", action=ArgAction::SetTrue)]
+        dry_run: bool,
+        /// Remove the packages even if other packages depend on them, leaving those broken
+        #[arg(short, long, action=ArgAction::SetTrue, conflicts_with = "recursive")]
+        force: bool,
+        #[arg(required = true)]
         packages: Vec<String>,
     },
     Update {
         #[arg(short, long, action=ArgAction::SetTrue)]
         system: bool,
+        /// Explain why packages are not updated
+        #[arg(long, action=ArgAction::SetTrue)]
+        explain_skip: bool,
+        /// Reinstall packages that are missing some of their files even if they are up to date
+        #[arg(long, action=ArgAction::SetTrue)]
+        reinstall_if_files_missing: bool,
+        /// Only report the available updates without installing them
+        #[arg(long, action=ArgAction::SetTrue)]
+        check: bool,
+        /// Keep checking for updates, reporting the ones that became available since the last check
+        #[arg(long, action=ArgAction::SetTrue, requires = "check")]
+        watch: bool,
+        /// Seconds between the checks of --watch
+        #[arg(long, default_value_t = 3600, requires = "watch")]
+        interval: u64,
+        /// Package to leave out of the system update, can be given multiple times
+        #[arg(long, requires = "system")]
+        exclude: Vec<String>,
+        /// Packages to update, all installed packages with --check if none are given
+        #[arg(required_unless_present_any = ["system", "check"])]
         packages: Vec<String>,
     },
     Info {
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+    /// Hold installed packages back from updates. Removing or reinstalling a package drops its
+    /// hold
+    Hold {
+        #[arg(required = true)]
         packages: Vec<String>,
     },
+    /// Allow held packages to be updated again
+    Unhold {
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+    /// List the packages the given packages depend on, directly or transitively, without
+    /// resolving what would be installed
+    Depends {
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+    /// List the versions of a package available on the remotes, newest first
+    Versions { package: String },
+    /// List the installed packages with a newer version on the remotes, without updating them
+    Outdated,
+    /// Check the health of all installed packages without modifying anything, and print a json
+    /// report, meant to be used with --no-tui. Exits with 1 if any package is unhealthy
+    Check,
+    /// Check a package manifest for problems before publishing it. Exits with 1 if any error is
+    /// found
+    Lint { path: String },
+    /// Check that the files of the installed packages still exist and are unmodified, all
+    /// packages if none are given
+    Verify { packages: Vec<String> },
+    /// Find the installed package that owns a file
+    Owns { path: String },
+    /// Find the installed package that provides a command
+    Which { command: String },
+    /// Write an archive of an installed package that can be installed offline
+    BuildArchive {
+        package: String,
+        /// Path of the archive, defaults to <name>-<version>.japm
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Remove the build directories left behind by failed builds. Must not be run while another
+    /// japm process is running
+    Clean,
+    /// Commit the actions built with --defer-commit to the database
+    CommitDeferred,
+    /// Retry the actions of the last operation that failed, without rebuilding the ones that
+    /// succeeded
+    RetryFailed,
+    /// Manage the remotes of the system config
+    Remote {
+        #[command(subcommand)]
+        command: RemoteCommandType,
+    },
+    /// Print the completion script for a shell
+    Completions { shell: clap_complete::Shell },
+    /// Print the names of the installed packages, used by the completion scripts
+    #[command(hide = true)]
+    CompleteInstalled,
+}
+
+#[derive(Debug, Subcommand)]
+enum RemoteCommandType {
+    Add {
+        name: String,
+        url: String,
+        /// Value of the Authorization header sent to the remote
+        #[arg(long)]
+        auth_header: Option<String>,
+        /// Reject redirects of the remote to other hosts
+        #[arg(long, action=ArgAction::SetTrue)]
+        trusted: bool,
+    },
+    Remove {
+        name: String,
+    },
+    List,
+}
+
+/// Exit codes japm exits with, so that scripts can tell failures apart
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ExitCode {
+    Success = 0,
+    /// Aborted, unhealthy or modified packages, and failures without a more specific code
+    Failure = 1,
+    // 2 is used by clap for invalid arguments
+    /// The actions of the command could not be resolved, such as a package not being found
+    Resolution = 3,
+    /// Building the actions failed
+    Build = 4,
+    /// Reading or commiting to the package database failed
+    Database = 5,
+    /// The config could not be read or is incomplete
+    Config = 6,
+    /// Another japm process holds the lock, or it could not be taken
+    Lock = 7,
 }
 
-static mut GATHER_KEY_BEFORE_EXIT: bool = false;
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0  Success
+  1  Aborted, unhealthy or modified packages, or any other failure
+  2  Invalid arguments
+  3  The command could not be resolved, such as a package not being found
+  4  Building the actions failed
+  5  Reading or commiting to the package database failed
+  6  The config could not be read or is incomplete
+  7  Another japm process is running, or the lock could not be taken";
+
+const SYSTEM_CONFIG_PATH: &str = "/etc/japm/config.json";
+const PACKAGE_BUILD_PATH: &str = "/var/lib/japm/install_pkgs/";
+
+/// How often [wait_for_cancel_request] checks whether cancelling was requested
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static GATHER_KEY_BEFORE_EXIT: AtomicBool = AtomicBool::new(false);
+/// Released by [exit], as the process is exited without dropping the guard
+static HELD_LOCK: Mutex<Option<LockGuard>> = Mutex::new(None);
 
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    if let Some(packages) = command_packages(&mut args.command) {
+        match normalize_package_names(std::mem::take(packages)) {
+            Ok(normalized) => *packages = normalized,
+            Err(error) => Args::command().error(ErrorKind::InvalidValue, error).exit(),
+        }
+    }
+
+    // Completions are printed as they are, without any frontend
+    match args.command {
+        CommandType::Completions { shell } => {
+            print!("{}", completions::generate(shell));
+            return;
+        }
+        CommandType::CompleteInstalled => {
+            complete_installed().await;
+            return;
+        }
+        _ => (),
+    }
 
     {
         let (write_handle, read_handle) = frontends::messaging::generate_message_pair();
         frontends::set_ui_messenger(write_handle);
-        if args.no_tui {
+        if args.plain || args.output == OutputFormat::Json || !std::io::stderr().is_tty() {
+            frontends::plain::init(read_handle);
+        } else if args.no_tui {
             frontends::stdout::init(read_handle).expect("Could not initialize STD frontend.");
         } else {
             frontends::tui::init(read_handle).expect("Could not initialize TUI frontend.");
-            unsafe {
-                GATHER_KEY_BEFORE_EXIT = true;
-            }
+            GATHER_KEY_BEFORE_EXIT.store(true, Ordering::Relaxed);
         }
     }
 
     progress::set_boxed_progress(Box::new(FrontendProgress::new()));
 
-    match log::set_boxed_logger(Box::new(
-        FrontendLogger::new().expect("Could not initialize frontend logger."),
-    )) {
-        Ok(()) => log::set_max_level(if args.verbose {
-            log::LevelFilter::Trace
-        } else {
-            log::LevelFilter::Info
-        }),
+    let log_filter = LogFilter::from_env(if args.verbose {
+        log::LevelFilter::Trace
+    } else {
+        log::LevelFilter::Info
+    });
+    // Summary records are info, and displayed regardless of the filter
+    let max_log_level = log_filter.max_level().max(log::LevelFilter::Info);
+
+    let mut logger = FrontendLogger::new(args.summary_only, log_filter)
+        .expect("Could not initialize frontend logger.");
+    if let Some(log_file) = &args.log_file {
+        match FileSink::open(log_file) {
+            Ok(file_sink) => logger = logger.with_file_sink(file_sink),
+            Err(error) => eprintln!("Could not open log file {log_file}: {error}"),
+        }
+    }
+
+    match log::set_boxed_logger(Box::new(logger)) {
+        Ok(()) => log::set_max_level(max_log_level),
         Err(error) => {
             eprintln!("Could not setup logger: {error}");
         }
     };
 
-    let (config, mut db) = join!(get_config(), get_db());
+    // Getting the config and database normally creates them if they don't exist
+    let root = args.root.as_ref().map(PathBuf::from);
+    if let CommandType::Check = args.command {
+        check(root.as_deref()).await
+    }
+    if let CommandType::Lint { path } = &args.command {
+        lint_manifest(path).await
+    }
 
-    if let Some(command) = args.command {
-        debug!("Generating actions for command {command:?}");
-        let result: Result<Vec<action::Action>, Box<dyn Error>> = match command {
-            CommandType::Install {
-                from_file,
-                reinstall,
-                packages,
-            } => {
-                let reinstall_options = if reinstall {
-                    commands::ReinstallOptions::ForceReinstall
-                } else {
-                    commands::ReinstallOptions::Ignore
-                };
+    if requires_lock(&args.command) {
+        let lock_path = config::rooted(root.as_deref(), lock::LOCK_PATH);
+        match lock::lock(Path::new(&lock_path), args.wait_lock) {
+            Ok(guard) => *HELD_LOCK.lock().unwrap_or_else(|error| error.into_inner()) = Some(guard),
+            Err(error) => {
+                error!("Could not lock japm: {error}");
+                exit(ExitCode::Lock).await
+            }
+        }
+    }
+
+    let config = get_config(root.as_deref()).await;
+    let mut db = get_db(&config.database_path).await;
+    let deferred_commit_path = journal::deferred_commit_path(&config.database_path);
+    let failed_operation_path = journal::failed_operation_path(&config.database_path);
+    let build_options = get_build_options(&args, &config);
+    listen_for_cancel(build_options.cancel_requested.clone());
+
+    let command = args.command;
+    debug!("Generating actions for command {command:?}");
+
+    // Actions of a retried operation that are already built
+    let mut prebuilt_actions: Vec<Action> = Vec::new();
+    let mut retrying = false;
+    // Manifest path and the explicitly requested packages to record to it after the commit
+    let mut manifest_record: Option<(String, Vec<String>)> = None;
 
-                let mut package_finder = DefaultPackageFinder::new(from_file, &config);
+    let result: Result<Vec<action::Action>, Box<dyn Error>> = match command {
+        CommandType::Install {
+            from_file,
+            reinstall,
+            reinstall_deps,
+            overlay,
+            explain_skip,
+            reinstall_if_files_missing,
+            preserve_order,
+            manifest,
+            record_manifest,
+            mut packages,
+        } => {
+            if let Some(manifest) = manifest {
+                match Manifest::read(&manifest).await {
+                    Ok(manifest) => packages.extend(manifest.package_names()),
+                    Err(error) => {
+                        error!("Could not read manifest {manifest}: {error}");
+                        exit(ExitCode::Failure).await
+                    }
+                }
+            }
+
+            if let Some(record_manifest) = record_manifest {
+                manifest_record = Some((record_manifest, packages.clone()));
+            }
+
+            let reinstall_options = if overlay {
+                commands::ReinstallOptions::Overlay
+            } else if reinstall || reinstall_deps {
+                commands::ReinstallOptions::ForceReinstall
+            } else {
+                commands::ReinstallOptions::Ignore
+            };
+
+            let install_options = commands::InstallOptions {
+                explain_skip,
+                reinstall_if_files_missing,
+                preserve_order,
+                reinstall_dependencies: reinstall_deps,
+                root: config.root.clone(),
+            };
 
-                commands::install_packages(
+            let mut package_finder = DefaultPackageFinder::new(from_file, &config);
+
+            commands::install_packages(
+                packages,
+                &mut package_finder,
+                &reinstall_options,
+                &install_options,
+                &mut db,
+            )
+            .await
+            .map_err(Box::from)
+        }
+        CommandType::Remove {
+            packages,
+            recursive,
+            dry_run: true,
+            force,
+        } => match commands::simulate_remove(
+            packages,
+            recursive,
+            force,
+            config.root.as_deref(),
+            &mut db,
+        )
+        .await
+        {
+            Err(error) => Err(Box::from(error)),
+            Ok(previews) => {
+                for preview in previews {
+                    info!("{preview}");
+                }
+                Ok(vec![])
+            }
+        },
+        CommandType::Remove {
+            packages,
+            recursive,
+            dry_run: false,
+            force,
+        } => commands::remove_packages(packages, recursive, force, &mut db)
+            .await
+            .map_err(Box::from),
+        CommandType::Update {
+            system,
+            explain_skip,
+            reinstall_if_files_missing,
+            check,
+            watch,
+            interval,
+            exclude,
+            packages,
+        } => {
+            if watch {
+                watch_updates(
                     packages,
-                    &mut package_finder,
-                    &reinstall_options,
+                    interval,
+                    &config,
+                    &build_options.cancel_requested,
                     &mut db,
                 )
                 .await
-                .map_err(Box::from)
-            }
-            CommandType::Remove {
-                packages,
-                recursive,
-            } => commands::remove_packages(packages, recursive, &mut db)
-                .await
-                .map_err(Box::from),
-            CommandType::Update { system, packages } => {
+            } else if check {
+                check_updates(packages, args.output, &config, &mut db).await
+            } else {
+                let install_options = commands::InstallOptions {
+                    explain_skip,
+                    reinstall_if_files_missing,
+                    root: config.root.clone(),
+                    ..Default::default()
+                };
+
                 let mut package_finder = DefaultPackageFinder::new(false, &config);
                 if system {
-                    commands::update_all_packages(&mut package_finder, &mut db).await
+                    commands::update_all_packages(
+                        exclude,
+                        &mut package_finder,
+                        &install_options,
+                        &mut db,
+                    )
+                    .await
                 } else {
-                    commands::update_packages(packages, &mut package_finder, &mut db).await
+                    commands::update_packages(
+                        packages,
+                        &mut package_finder,
+                        &install_options,
+                        &mut db,
+                    )
+                    .await
                 }
+                .map_err(Box::from)
             }
+        }
+        CommandType::Info { packages } => match commands::get_package_info(packages, &mut db) {
+            Err(error) => Err(Box::from(error)),
+            Ok(infos) => output::print_items(&infos, args.output)
+                .map(|()| vec![])
+                .map_err(Box::from),
+        },
+        CommandType::Hold { packages } => commands::set_held(packages, true, &mut db)
+            .map(|()| vec![])
+            .map_err(Box::from),
+        CommandType::Unhold { packages } => commands::set_held(packages, false, &mut db)
+            .map(|()| vec![])
             .map_err(Box::from),
-            CommandType::Info { packages } => {
-                match commands::print_package_info(packages, &mut db) {
-                    Err(error) => Err(Box::from(error)),
-                    Ok(()) => Ok(vec![]),
+        CommandType::Versions { package } => {
+            let mut package_finder = DefaultPackageFinder::new(false, &config);
+            match commands::list_versions(&package, &mut package_finder).await {
+                Err(error) => Err(Box::from(error)),
+                Ok(versions) => {
+                    if versions.is_empty() && args.output == OutputFormat::Text {
+                        info!("No versions of {package} found");
+                    }
+
+                    let versions: Vec<String> = versions.iter().map(Version::to_string).collect();
+                    output::print_items(&versions, args.output)
+                        .map(|()| vec![])
+                        .map_err(Box::from)
                 }
             }
-        };
+        }
+        CommandType::Depends { packages } => {
+            let mut package_finder = DefaultPackageFinder::new(false, &config);
+            match commands::find_dependencies(packages, &mut package_finder).await {
+                Err(error) => Err(Box::from(error)),
+                Ok(dependencies) => output::print_items(&dependencies, args.output)
+                    .map(|()| vec![])
+                    .map_err(Box::from),
+            }
+        }
+        CommandType::Outdated => {
+            let mut package_finder = DefaultPackageFinder::new(false, &config);
+            match commands::list_outdated(&mut package_finder, &mut db).await {
+                Err(error) => Err(Box::from(error)),
+                Ok(outdated) => {
+                    if outdated.is_empty() && args.output == OutputFormat::Text {
+                        info!("All packages are up to date");
+                    }
+
+                    let outdated: Vec<String> = outdated
+                        .iter()
+                        .map(|(name, local, remote)| format!("{name}: {local} -> {remote}"))
+                        .collect();
+                    output::print_items(&outdated, args.output)
+                        .map(|()| vec![])
+                        .map_err(Box::from)
+                }
+            }
+        }
+        CommandType::Check => unreachable!("check is performed before setup"),
+        CommandType::Lint { .. } => unreachable!("lint is performed before setup"),
+        CommandType::Completions { .. } | CommandType::CompleteInstalled => {
+            unreachable!("completions are printed before setup")
+        }
+        CommandType::Verify { packages } => {
+            match commands::verify_packages(packages, config.root.as_deref(), &mut db) {
+                Err(error) => Err(Box::from(error)),
+                Ok(mismatches) => {
+                    report_mismatched_files(&mismatches).await;
+                    Ok(vec![])
+                }
+            }
+        }
+        CommandType::Owns { path } => {
+            match commands::find_owner(&path, config.root.as_deref(), &mut db) {
+                Err(error) => Err(Box::from(error)),
+                Ok(owner) => {
+                    match owner {
+                        Some(owner) => info!("{path} is owned by {owner}"),
+                        None => info!("{path} is not owned by any package"),
+                    }
+                    Ok(vec![])
+                }
+            }
+        }
+        CommandType::Which { command } => match commands::which(&command, &mut db) {
+            Err(error) => Err(Box::from(error)),
+            Ok(owner) => {
+                match owner {
+                    Some(owner) => info!("{command} is provided by {owner}"),
+                    None => info!("{command} is not provided by any package"),
+                }
+                Ok(vec![])
+            }
+        },
+        CommandType::BuildArchive { package, output } => {
+            match commands::build_archive(&package, output, config.root.as_deref(), &mut db) {
+                Err(error) => Err(Box::from(error)),
+                Ok(()) => Ok(vec![]),
+            }
+        }
+        CommandType::RetryFailed => {
+            match journal::read_failed_operation(&failed_operation_path).await {
+                Ok(Some((built, outstanding))) => {
+                    info!(
+                        "Retrying {} outstanding actions, {} actions are already built",
+                        outstanding.len(),
+                        built.len()
+                    );
+                    prebuilt_actions = built;
+                    retrying = true;
+                    Ok(outstanding)
+                }
+                Ok(None) => {
+                    info!("No failed operation to retry");
+                    Ok(vec![])
+                }
+                Err(error) => Err(Box::from(error)),
+            }
+        }
+        CommandType::Clean => match commands::clean(Path::new(&config::rooted(
+            config.root.as_deref(),
+            PACKAGE_BUILD_PATH,
+        ))) {
+            Err(error) => Err(Box::from(error)),
+            Ok(report) => {
+                info!(
+                    "Removed {} build directories, freeing {} bytes",
+                    report.removed_paths.len(),
+                    report.freed_bytes
+                );
+                Ok(vec![])
+            }
+        },
+        CommandType::CommitDeferred => {
+            commit_deferred(&deferred_commit_path, &config, &build_options, &mut db).await;
+            Ok(vec![])
+        }
+        CommandType::Remote { command } => {
+            match manage_remotes(command, config.root.as_deref()).await {
+                Err(error) => Err(Box::from(error)),
+                Ok(()) => Ok(vec![]),
+            }
+        }
+    };
+
+    match result {
+        Ok(actions_to_build) => {
+            if !actions_to_build.is_empty() {
+                info!("{}", action::count_summary(&actions_to_build));
+                log_download_size(&actions_to_build, &config).await;
+                if !args.summary_only {
+                    frontends::display_plan(&actions_to_build).await;
+                }
+            }
+
+            if !args.yes
+                && config.confirm.requires_confirmation(&actions_to_build)
+                && !confirm_actions(&actions_to_build).await
+            {
+                info!("Aborted");
+                exit(ExitCode::Failure).await
+            }
+
+            let snapshot_id = if args.snapshot && !actions_to_build.is_empty() {
+                Some(take_snapshot(&actions_to_build, &config).await)
+            } else {
+                None
+            };
 
-        match result {
-            // TODO: make a pretty actions display screen
-            Ok(actions) => {
-                if let Err(error) = build_actions(actions.clone()).await {
-                    error!("Error while building actions: {error}");
-                    exit(-1).await
+            let mut actions = prebuilt_actions;
+            match build_actions(actions_to_build, &build_options, !args.summary_only).await {
+                Ok(built_actions) => actions.extend(built_actions),
+                Err(failed_build) => {
+                    let cancelled = build_options.cancel_requested.load(Ordering::Relaxed);
+                    if cancelled {
+                        info!(
+                            "Cancelled, {} actions were not built",
+                            failed_build.outstanding.len()
+                        );
+                    } else {
+                        error!("Error while building actions: {}", failed_build.error);
+                    }
+                    actions.extend(failed_build.built);
+                    record_failed_operation(
+                        &actions,
+                        &failed_build.outstanding,
+                        &failed_operation_path,
+                    )
+                    .await;
+                    exit(if cancelled {
+                        ExitCode::Failure
+                    } else {
+                        ExitCode::Build
+                    })
+                    .await
                 }
-                if let Err(error) = commit_actions(actions, &mut db).await {
+            };
+
+            if args.defer_commit {
+                defer_commit(actions, &deferred_commit_path).await;
+            } else {
+                let summary = action::summarize(&actions);
+                if let Err(error) = action::commit_actions(actions.clone(), &mut db).await {
                     error!("Error while commiting actions: {error}");
-                    exit(-1).await
+                    record_failed_operation(&actions, &[], &failed_operation_path).await;
+                    exit(ExitCode::Database).await
+                }
+
+                run_post_transaction_hooks(&config, &build_options).await;
+
+                if let Some((manifest_path, packages)) = &manifest_record {
+                    record_explicit_packages(manifest_path, packages, &actions, &mut db).await;
+                }
+
+                for line in summary {
+                    info!(target: SUMMARY_TARGET, "{line}");
+                }
+                if let Some(snapshot_id) = snapshot_id {
+                    info!(target: SUMMARY_TARGET, "Snapshot: {snapshot_id}");
                 }
             }
-            Err(error) => {
-                error!("Error while performing command:\n{error}");
-                exit(-1).await
+
+            if retrying {
+                if let Err(error) = journal::clear_failed_operation(&failed_operation_path).await {
+                    error!("Could not clear failed operation: {error}");
+                    exit(ExitCode::Failure).await
+                }
             }
         }
+        Err(error) => {
+            error!("Error while performing command:\n{error}");
+            exit(command_exit_code(error.as_ref())).await
+        }
+    }
+
+    exit(ExitCode::Success).await
+}
+
+/// Package names given to the command, if it takes any
+fn command_packages(command: &mut CommandType) -> Option<&mut Vec<String>> {
+    match command {
+        CommandType::Install { packages, .. }
+        | CommandType::Remove { packages, .. }
+        | CommandType::Update { packages, .. }
+        | CommandType::Info { packages }
+        | CommandType::Depends { packages }
+        | CommandType::Hold { packages }
+        | CommandType::Unhold { packages }
+        | CommandType::Verify { packages } => Some(packages),
+        _ => None,
+    }
+}
+
+/// Trims the package names and removes duplicates, keeping the order they were first given in.
+/// Empty names are rejected.
+fn normalize_package_names(packages: Vec<String>) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(packages.len());
+
+    for package in packages.iter() {
+        let package = package.trim();
+        if package.is_empty() {
+            return Err(String::from("Package names can not be empty"));
+        }
+
+        if !normalized.iter().any(|normalized| normalized == package) {
+            normalized.push(String::from(package));
+        }
     }
 
-    exit(0).await
+    Ok(normalized)
 }
 
-async fn get_config() -> Config {
-    const CONFIG_PATH: &str = "/etc/japm/config.json";
+/// Whether the command changes the installed packages, the database or the config. Only those
+/// take the lock, so that read-only commands, such as a long running `update --check --watch`,
+/// don't block other japm processes.
+fn requires_lock(command: &CommandType) -> bool {
+    !matches!(
+        command,
+        CommandType::Info { .. }
+            | CommandType::Depends { .. }
+            | CommandType::Versions { .. }
+            | CommandType::Outdated
+            | CommandType::Verify { .. }
+            | CommandType::Owns { .. }
+            | CommandType::Which { .. }
+            | CommandType::BuildArchive { .. }
+            | CommandType::Update { check: true, .. }
+            | CommandType::Remove { dry_run: true, .. }
+            | CommandType::Remote {
+                command: RemoteCommandType::List
+            }
+    )
+}
 
+/// Loads the config of `root`, creating its system config if needed, and moves its paths under
+/// `root`.
+async fn get_config(root: Option<&Path>) -> Config {
     progress::increment_target(ProgressType::Setup, 1).await;
 
-    match Config::create_default_config_if_necessary(CONFIG_PATH).await {
+    let system_config_path = config::rooted(root, SYSTEM_CONFIG_PATH);
+    match Config::create_default_config_if_necessary(&system_config_path).await {
         Ok(created) => {
             if created {
-                if let Err(error) = Config::write_default_config(CONFIG_PATH).await {
+                if let Err(error) = Config::write_default_config(&system_config_path).await {
                     error!("Could not write default config: {error}");
-                    exit(-1).await
+                    exit(ExitCode::Config).await
                 }
             }
         }
         Err(error) => {
             error!("Could not create default config if necessary: {error}");
-            exit(-1).await
+            exit(ExitCode::Config).await
         }
     }
 
-    match Config::from_file(CONFIG_PATH).await {
-        Ok(config) => {
+    let user_config_path = Config::user_config_path();
+
+    let mut config_paths = vec![system_config_path.as_str()];
+    if let Some(user_config_path) = &user_config_path {
+        config_paths.push(user_config_path);
+    }
+
+    match Config::load_layered(&config_paths).await {
+        Ok(mut config) => {
+            if let Some(root) = root {
+                config.set_root(root);
+            }
             progress::increment_completed(ProgressType::Setup, 1).await;
             config
         }
         Err(error) => {
             error!("Could not get config: {error}");
-            exit(-1).await
+            exit(ExitCode::Config).await
+        }
+    }
+}
+
+/// Remotes are edited in the system config only, as the layered config also contains the remotes
+/// of the user config and environment.
+async fn manage_remotes(
+    command: RemoteCommandType,
+    root: Option<&Path>,
+) -> Result<(), config::Error> {
+    let system_config_path = config::rooted(root, SYSTEM_CONFIG_PATH);
+    let mut config = Config::from_file(&system_config_path).await?;
+
+    match command {
+        RemoteCommandType::Add {
+            name,
+            url,
+            auth_header,
+            trusted,
+        } => {
+            config.add_remote(
+                &name,
+                Remote {
+                    url,
+                    auth_header,
+                    trusted,
+                },
+            )?;
+            config.save(&system_config_path).await?;
+            info!("Added remote {name}");
+        }
+        RemoteCommandType::Remove { name } => {
+            if config.remove_remote(&name).is_none() {
+                return Err(config::Error::RemoteNotFound(name));
+            }
+            config.save(&system_config_path).await?;
+            info!("Removed remote {name}");
+        }
+        RemoteCommandType::List => {
+            for (name, remote) in config.remotes.iter() {
+                match remote.auth_header {
+                    Some(_) => info!("{name}: {} (authenticated)", remote.url),
+                    None => info!("{name}: {}", remote.url),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Logs the estimated download size of the packages to install, only a warning if it can't be
+/// estimated.
+async fn log_download_size(actions: &[Action], config: &Config) {
+    if !actions
+        .iter()
+        .any(|action| matches!(action, Action::Install(_)))
+    {
+        return;
+    }
+
+    let mut package_finder = DefaultPackageFinder::new(false, config);
+    match commands::estimated_download_size(actions, &mut package_finder).await {
+        Ok(size) => info!("Download size: {size}"),
+        Err(error) => warn!("Could not estimate the download size: {error}"),
+    }
+}
+
+/// Asks whether to proceed with the planned actions, already displayed by
+/// [frontends::display_plan]. The TUI reads the answer as a key press, the other frontends from
+/// stdin.
+async fn confirm_actions(actions: &[Action]) -> bool {
+    info!("Proceed with {} actions? [y/N]", actions.len());
+
+    if GATHER_KEY_BEFORE_EXIT.load(Ordering::Relaxed) {
+        return matches!(
+            frontends::wait_for_key().await,
+            Some(KeyCode::Char('y' | 'Y'))
+        );
+    }
+
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).map(|_| answer)
+    })
+    .await;
+
+    match answer {
+        Ok(Ok(answer)) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        _ => false,
+    }
+}
+
+async fn record_explicit_packages(
+    manifest_path: &str,
+    packages: &[String],
+    actions: &[Action],
+    db: &mut SqlitePackagesDb,
+) {
+    let versions = match commands::explicit_package_versions(packages, actions, db) {
+        Ok(versions) => versions,
+        Err(error) => {
+            error!("Could not get the installed versions to record: {error}");
+            exit(ExitCode::Database).await
+        }
+    };
+
+    if let Err(error) = manifest::record_manifest(manifest_path, versions).await {
+        error!("Could not record manifest {manifest_path}: {error}");
+        exit(ExitCode::Failure).await
+    }
+}
+
+async fn take_snapshot(actions: &[Action], config: &Config) -> String {
+    let Some(snapshot_command) = &config.snapshot_command else {
+        error!("--snapshot requires snapshot_command to be set in the config");
+        exit(ExitCode::Config).await
+    };
+
+    let paths = snapshot::get_mutated_paths(actions, &config.database_path);
+    match snapshot::take_snapshot(snapshot_command, &paths) {
+        Ok(snapshot_id) => snapshot_id,
+        Err(error) => {
+            error!("Could not take snapshot: {error}");
+            exit(ExitCode::Failure).await
+        }
+    }
+}
+
+/// Prints the installed package names, failing silently as the output is read by the shell.
+async fn complete_installed() {
+    let user_config_path = Config::user_config_path();
+
+    let mut config_paths = vec![SYSTEM_CONFIG_PATH];
+    if let Some(user_config_path) = &user_config_path {
+        config_paths.push(user_config_path);
+    }
+
+    let Ok(config) = Config::load_layered(&config_paths).await else {
+        return;
+    };
+    let Ok(mut db) = SqlitePackagesDb::open_read_only(&config.database_path) else {
+        return;
+    };
+
+    if let Ok(names) = commands::installed_package_names(&mut db) {
+        for name in names {
+            println!("{name}");
+        }
+    }
+}
+
+/// Lints a manifest without needing the config or database.
+async fn lint_manifest(path: &str) -> ! {
+    let content = match tokio::fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(error) => {
+            error!("Could not read {path}: {error}");
+            exit(ExitCode::Failure).await
+        }
+    };
+
+    let package = match RemotePackage::from_json(&content) {
+        Ok(package) => package,
+        Err(error) => {
+            error!("{error}");
+            exit(ExitCode::Failure).await
+        }
+    };
+
+    let warnings = package::lint(&package);
+    if warnings.is_empty() {
+        info!("No problems found in {path}");
+    }
+    for warning in warnings.iter() {
+        match warning.severity {
+            LintSeverity::Error => error!("{warning}"),
+            LintSeverity::Warning => warn!("{warning}"),
+        }
+    }
+
+    exit(
+        if warnings
+            .iter()
+            .any(|warning| warning.severity == LintSeverity::Error)
+        {
+            ExitCode::Failure
+        } else {
+            ExitCode::Success
+        },
+    )
+    .await
+}
+
+/// Reports the health of the installed packages without creating or writing anything.
+async fn check(root: Option<&Path>) -> ! {
+    let user_config_path = Config::user_config_path();
+
+    let system_config_path = config::rooted(root, SYSTEM_CONFIG_PATH);
+    let mut config_paths = vec![system_config_path.as_str()];
+    if let Some(user_config_path) = &user_config_path {
+        config_paths.push(user_config_path);
+    }
+
+    let config = match Config::load_layered(&config_paths).await {
+        Ok(mut config) => {
+            if let Some(root) = root {
+                config.set_root(root);
+            }
+            config
+        }
+        Err(error) => {
+            error!("Could not get config: {error}");
+            exit(ExitCode::Config).await
+        }
+    };
+
+    let mut db = match SqlitePackagesDb::open_read_only(&config.database_path) {
+        Ok(db) => db,
+        Err(error) => {
+            error!("Could not open the database: {error}");
+            exit(ExitCode::Database).await
+        }
+    };
+
+    let report = match commands::check_packages(root, &mut db) {
+        Ok(report) => report,
+        Err(error) => {
+            error!("Could not check packages: {error}");
+            exit(ExitCode::Database).await
+        }
+    };
+
+    match serde_json::to_string_pretty(&report) {
+        Ok(report) => println!("{report}"),
+        Err(error) => {
+            error!("Could not serialize check report: {error}");
+            exit(ExitCode::Failure).await
+        }
+    }
+
+    exit(if report.healthy {
+        ExitCode::Success
+    } else {
+        ExitCode::Failure
+    })
+    .await
+}
+
+async fn report_mismatched_files(mismatches: &[commands::FileMismatch]) {
+    if mismatches.is_empty() {
+        info!("All package files are present and unmodified");
+        return;
+    }
+
+    for mismatch in mismatches.iter() {
+        let package_name = &mismatch.package;
+        if !mismatch.missing_files.is_empty() {
+            error!(
+                "Package {package_name} is missing files {:?}",
+                mismatch.missing_files
+            );
+        }
+        if !mismatch.modified_files.is_empty() {
+            error!(
+                "Package {package_name} has modified files {:?}",
+                mismatch.modified_files
+            );
         }
     }
+
+    exit(ExitCode::Failure).await
 }
 
-async fn get_db() -> SqlitePackagesDb {
+async fn get_db(database_path: &str) -> SqlitePackagesDb {
     progress::increment_target(ProgressType::Setup, 1).await;
-    match SqlitePackagesDb::create_db_file_if_necessary().await {
-        Ok(created) => {
-            let mut db = match SqlitePackagesDb::new() {
+    match SqlitePackagesDb::create_db_file_if_necessary(database_path).await {
+        Ok(_) => {
+            let mut db = match SqlitePackagesDb::new(database_path) {
                 Ok(db) => db,
                 Err(error) => {
                     error!("Could not connect to the database: {error}");
-                    exit(-1).await
+                    exit(ExitCode::Database).await
                 }
             };
 
-            if created {
-                if let Err(error) = db.initialize_database() {
-                    error!("Could not initialize database: {error}");
-                    exit(-1).await
-                }
+            if let Err(error) = db.migrate_database() {
+                error!("Could not migrate database: {error}");
+                exit(ExitCode::Database).await
             }
 
             progress::increment_completed(ProgressType::Setup, 1).await;
@@ -226,62 +1143,266 @@ async fn get_db() -> SqlitePackagesDb {
         }
         Err(error) => {
             error!("Could not create db file if necessary: {error}");
-            exit(-1).await
+            exit(ExitCode::Database).await
         }
     }
 }
 
-async fn build_actions(actions: Vec<Action>) -> Result<(), action::BuildError> {
+async fn build_actions(
+    actions: Vec<Action>,
+    options: &action::BuildOptions,
+    display_actions: bool,
+) -> Result<Vec<Action>, action::FailedBuild> {
     if actions.is_empty() {
-        progress::set_comleted(progress::ProgressType::ActionsBuild).await;
+        progress::set_completed(progress::ProgressType::ActionsBuild).await;
     } else {
         progress::increment_target(ProgressType::ActionsBuild, actions.len() as i32).await;
     }
 
+    let removed_files = action::count_removed_files(&actions);
+    if removed_files > 0 {
+        progress::increment_target(ProgressType::Files, removed_files as i32).await;
+    }
+
     let rt = tokio::runtime::Handle::current();
-    actions.into_par_iter().try_for_each(|mut action| {
-        action.build("/var/lib/japm/install_pkgs/")?;
-        rt.spawn(async move {
-            frontends::display_action(&action).await;
-        });
-        Ok(())
+    let build_path = config::rooted(options.root.as_deref(), PACKAGE_BUILD_PATH);
+    action::build_actions(actions, &build_path, options, |action| {
+        if display_actions {
+            let displayed_action = action.clone();
+            rt.spawn(async move {
+                frontends::display_action(&displayed_action).await;
+            });
+        }
     })
 }
 
-async fn commit_actions<DB, EDatabaseAdd, EDatabaseRemove>(
-    actions: Vec<Action>,
-    db: &mut DB,
-) -> Result<(), action::CommitError<EDatabaseAdd, EDatabaseRemove>>
-where
-    EDatabaseAdd: Display,
-    EDatabaseRemove: Display,
-    DB: PackagesDb<AddError = EDatabaseAdd, RemoveError = EDatabaseRemove>,
-{
+async fn check_updates(
+    packages: Vec<String>,
+    output_format: OutputFormat,
+    config: &Config,
+    db: &mut SqlitePackagesDb,
+) -> Result<Vec<Action>, Box<dyn Error>> {
+    let mut package_finder = DefaultPackageFinder::new(false, config);
+    let updates = commands::find_available_updates(packages, &mut package_finder, db).await?;
+
+    if output_format == OutputFormat::Json {
+        output::print_items(&updates, output_format)?;
+        return Ok(vec![]);
+    }
+
+    if updates.is_empty() {
+        info!("All packages are up to date");
+    }
+    for update in updates {
+        info!("Update available: {update}");
+    }
+
+    Ok(vec![])
+}
+
+/// Checks for updates every `interval` seconds until interrupted with Ctrl-C, or cancelled from
+/// the TUI, where Ctrl-C is a key press instead of a signal.
+async fn watch_updates(
+    packages: Vec<String>,
+    interval: u64,
+    config: &Config,
+    cancel_requested: &AtomicBool,
+    db: &mut SqlitePackagesDb,
+) -> Result<Vec<Action>, Box<dyn Error>> {
+    let mut watcher = commands::UpdateWatcher::default();
+    let mut interval = tokio::time::interval(Duration::from_secs(interval));
+
+    loop {
+        let stopped = tokio::select! {
+            _ = interval.tick() => false,
+            _ = tokio::signal::ctrl_c() => true,
+            _ = wait_for_cancel_request(cancel_requested) => true,
+        };
+        if stopped {
+            info!("Stopped watching for updates");
+            return Ok(vec![]);
+        }
+
+        // A new finder every check, so that packages are not served from the search cache
+        let mut package_finder = DefaultPackageFinder::new(false, config);
+        for update in watcher
+            .check(packages.clone(), &mut package_finder, db)
+            .await?
+        {
+            info!("Update available: {update}");
+        }
+    }
+}
+
+fn get_build_options(args: &Args, config: &Config) -> action::BuildOptions {
+    action::BuildOptions {
+        command_timeout: args
+            .command_timeout
+            .or(config.command_timeout)
+            .map(Duration::from_secs),
+        output_runtime: if args.summary_only {
+            None
+        } else {
+            Some(tokio::runtime::Handle::current())
+        },
+        progress_runtime: Some(tokio::runtime::Handle::current()),
+        // Preserving the order requires building one package after another
+        jobs: if matches!(
+            args.command,
+            CommandType::Install {
+                preserve_order: true,
+                ..
+            }
+        ) {
+            NonZeroUsize::new(1)
+        } else {
+            args.jobs
+        },
+        root: args.root.as_ref().map(PathBuf::from),
+        cancel_requested: Arc::default(),
+    }
+}
+
+/// Stops building further actions once the user asks to cancel from the TUI. Resolution is not
+/// interrupted, and actions already being built are finished, see
+/// [action::BuildOptions::cancel_requested].
+fn listen_for_cancel(cancel_requested: Arc<AtomicBool>) {
+    tokio::spawn(async move {
+        if frontends::wait_for_cancel().await.is_some() {
+            warn!("Cancelling, waiting for the actions being built to finish...");
+            cancel_requested.store(true, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Resolves once cancelling is requested, see [listen_for_cancel].
+async fn wait_for_cancel_request(cancel_requested: &AtomicBool) {
+    while !cancel_requested.load(Ordering::Relaxed) {
+        tokio::time::sleep(CANCEL_POLL_INTERVAL).await;
+    }
+}
+
+/// Runs the configured post transaction hooks, streaming their output to the frontend requires
+/// running them outside of the async context.
+async fn run_post_transaction_hooks(config: &Config, options: &action::BuildOptions) {
+    if config.hooks.post_transaction.is_empty() {
+        return;
+    }
+
+    let hooks = config.hooks.post_transaction.clone();
+    let options = options.clone();
+    if let Err(error) =
+        tokio::task::spawn_blocking(move || action::run_post_transaction_hooks(&hooks, &options))
+            .await
+    {
+        error!("Could not run post transaction hooks: {error}");
+    }
+}
+
+async fn record_failed_operation(built: &[Action], outstanding: &[Action], path: &str) {
+    if let Err(error) = journal::record_failed_operation(built, outstanding, path).await {
+        error!("Could not record failed operation: {error}");
+        return;
+    }
+
+    info!("Run retry-failed to retry the failed operation");
+}
+
+async fn defer_commit(actions: Vec<Action>, deferred_commit_path: &str) {
+    progress::set_completed(ProgressType::ActionsCommit).await;
+
     if actions.is_empty() {
-        progress::set_comleted(progress::ProgressType::ActionsCommit).await;
-    } else {
-        progress::increment_target(ProgressType::ActionsCommit, actions.len() as i32).await;
+        return;
     }
 
-    for action in actions {
-        action.commit(db)?;
-        progress::increment_completed(ProgressType::ActionsCommit, 1).await;
+    if let Err(error) = journal::defer_actions(&actions, deferred_commit_path).await {
+        error!("Could not defer commit of actions: {error}");
+        exit(ExitCode::Failure).await
     }
 
-    Ok(())
+    info!(
+        "Deferred commit of {} actions, run commit-deferred to commit them",
+        actions.len()
+    );
+}
+
+async fn commit_deferred(
+    deferred_commit_path: &str,
+    config: &Config,
+    options: &action::BuildOptions,
+    db: &mut SqlitePackagesDb,
+) {
+    let actions = match journal::read_deferred_actions(deferred_commit_path).await {
+        Ok(actions) => actions,
+        Err(error) => {
+            error!("Could not read deferred actions: {error}");
+            exit(ExitCode::Failure).await
+        }
+    };
+
+    if actions.is_empty() {
+        info!("No deferred actions to commit");
+    }
+
+    if let Err(error) = action::commit_actions(actions, db).await {
+        error!("Error while commiting deferred actions: {error}");
+        exit(ExitCode::Database).await
+    }
+
+    run_post_transaction_hooks(config, options).await;
+
+    if let Err(error) = journal::clear_deferred_actions(deferred_commit_path).await {
+        error!("Could not clear deferred actions: {error}");
+        exit(ExitCode::Failure).await
+    }
+}
+
+/// Returns the code of the first error of the chain that has a specific one. Commands otherwise
+/// fail to resolve their actions, such as when a package is not found.
+fn command_exit_code(error: &(dyn Error + 'static)) -> ExitCode {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if error.is::<config::Error>() {
+            return ExitCode::Config;
+        }
+        if error.is::<TranslatedPackageQueryError>() {
+            return ExitCode::Database;
+        }
+        if error.is::<JournalError>()
+            || error.is::<io::Error>()
+            || error.is::<serde_json::Error>()
+            || matches!(
+                error.downcast_ref::<BuildArchiveError<TranslatedPackageQueryError>>(),
+                Some(BuildArchiveError::Archive(_))
+            )
+        {
+            return ExitCode::Failure;
+        }
+
+        current = error.source();
+    }
+
+    ExitCode::Resolution
 }
 
-async fn exit(code: i32) -> ! {
+async fn exit(code: ExitCode) -> ! {
     // Due to the async nature of the logging/frontend implementation, we need to make sure all
     // needed messages have logged before showing the "press any key to exit" screen
     std::thread::sleep(std::time::Duration::from_millis(100));
 
-    if unsafe { GATHER_KEY_BEFORE_EXIT } {
+    if GATHER_KEY_BEFORE_EXIT.load(Ordering::Relaxed) {
         info!("Press any key to exit");
-        crossterm::event::read().expect("Could not read input");
+        frontends::wait_for_key().await;
     }
 
     frontends::exit().await;
 
-    std::process::exit(code);
+    drop(
+        HELD_LOCK
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .take(),
+    );
+
+    std::process::exit(code as i32);
 }
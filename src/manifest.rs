@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use tokio::fs;
+
+use serde::{Deserialize, Serialize};
+
+use log::trace;
+
+use thiserror::Error;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("An IO error has occured: {0}")]
+    IO(#[from] io::Error),
+    #[error("A json error has occured: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The packages a user explicitly asked to install and the versions they resolved to, without
+/// their dependencies.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub packages: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`, an empty one if it doesn't exist.
+    pub async fn read(path: &str) -> Result<Manifest, ManifestError> {
+        if !Path::new(path).try_exists()? {
+            return Ok(Manifest::default());
+        }
+
+        let content = fs::read_to_string(path).await?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub async fn write(&self, path: &str) -> Result<(), ManifestError> {
+        trace!(
+            "Writing manifest of {} packages to {path}",
+            self.packages.len()
+        );
+
+        fs::write(path, serde_json::to_string_pretty(self)?).await?;
+        Ok(())
+    }
+
+    /// Adds the packages to the manifest, replacing the versions of packages already in it.
+    pub fn record(&mut self, packages: impl IntoIterator<Item = (String, String)>) {
+        self.packages.extend(packages);
+    }
+
+    pub fn package_names(&self) -> Vec<String> {
+        self.packages.keys().cloned().collect()
+    }
+}
+
+/// Merges the packages into the manifest at `path`, creating it if necessary.
+pub async fn record_manifest(
+    path: &str,
+    packages: impl IntoIterator<Item = (String, String)>,
+) -> Result<(), ManifestError> {
+    let mut manifest = Manifest::read(path).await?;
+    manifest.record(packages);
+    manifest.write(path).await
+}
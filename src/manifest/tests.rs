@@ -0,0 +1,49 @@
+use tokio::test;
+
+use super::*;
+
+const MANIFEST_PATH: &str = "/tmp/japm/tests/manifest.json";
+
+#[test]
+async fn test_missing_manifest_is_empty() {
+    let manifest = Manifest::read("/tmp/japm/tests/missing_manifest.json")
+        .await
+        .unwrap();
+    assert_eq!(manifest, Manifest::default());
+}
+
+#[test]
+async fn test_recorded_manifest_merged() {
+    fs::create_dir_all("/tmp/japm/tests").await.unwrap();
+    let _ = fs::remove_file(MANIFEST_PATH).await;
+
+    record_manifest(
+        MANIFEST_PATH,
+        [
+            (String::from("editor"), String::from("1.0.0")),
+            (String::from("shell"), String::from("2.0.0")),
+        ],
+    )
+    .await
+    .unwrap();
+    record_manifest(
+        MANIFEST_PATH,
+        [
+            (String::from("shell"), String::from("2.1.0")),
+            (String::from("browser"), String::from("0.3.0")),
+        ],
+    )
+    .await
+    .unwrap();
+
+    let manifest = Manifest::read(MANIFEST_PATH).await.unwrap();
+    assert_eq!(
+        manifest.packages,
+        BTreeMap::from([
+            (String::from("browser"), String::from("0.3.0")),
+            (String::from("editor"), String::from("1.0.0")),
+            (String::from("shell"), String::from("2.1.0")),
+        ])
+    );
+    assert_eq!(manifest.package_names(), ["browser", "editor", "shell"]);
+}
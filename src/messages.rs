@@ -0,0 +1,73 @@
+use std::fmt::Display;
+
+use crate::action::Action;
+use crate::commands::SkipReason;
+
+#[cfg(test)]
+mod tests;
+
+/// User facing messages. Keeping their text in one place allows localizing them later, and lets
+/// tests refer to a message instead of its text.
+#[derive(Debug)]
+pub enum Message<'a> {
+    SearchingPackage(&'a str),
+    PackageNotFound(&'a str),
+    PackageNotInstalled(&'a str),
+    PackageAlreadyInstalled(&'a str),
+    PackageUpToDate(&'a str),
+    PackageHeld(&'a str),
+    PackageSkipped(&'a str, &'a SkipReason),
+    PackageReinstalling(&'a str),
+    PackageMissingFilesReinstalling(&'a str, &'a [String]),
+    RemovingDepending,
+    RemovingReplaced(&'a str, &'a str),
+    BreakingDependencies(&'a str, &'a [String]),
+    BuildingAction(&'a Action),
+    CommitingAction(&'a Action),
+    DeletingPath(&'a str),
+}
+
+impl Display for Message<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::SearchingPackage(name) => write!(f, "Searching for package {name}"),
+            Message::PackageNotFound(name) => write!(f, "Package {name} not found."),
+            Message::PackageNotInstalled(name) => write!(f, "Package {name} not installed"),
+            Message::PackageAlreadyInstalled(name) => {
+                write!(f, "Package {name} already installed. Ignoring...")
+            }
+            Message::PackageUpToDate(name) => {
+                write!(
+                    f,
+                    "Package {name} is already at latest version. Ignoring..."
+                )
+            }
+            Message::PackageHeld(name) => {
+                write!(f, "Package {name} is held, not updating. Ignoring...")
+            }
+            Message::PackageSkipped(name, reason) => {
+                write!(f, "Skipping package {name}: {reason}")
+            }
+            Message::PackageReinstalling(name) => {
+                write!(f, "Package {name} already installed, reinstalling...")
+            }
+            Message::PackageMissingFilesReinstalling(name, files) => {
+                write!(
+                    f,
+                    "Package {name} is missing files {files:?}, reinstalling..."
+                )
+            }
+            Message::RemovingDepending => write!(f, "Found depending packages, uninstalling..."),
+            Message::RemovingReplaced(name, replaced) => {
+                write!(f, "Package {name} replaces {replaced}, uninstalling it...")
+            }
+            Message::BreakingDependencies(name, depending) => write!(
+                f,
+                "Removing package {name} breaks dependencies {depending:?}, removing anyway"
+            ),
+            Message::BuildingAction(action) => write!(f, "Building action {action}"),
+            Message::CommitingAction(action) => write!(f, "Commiting action {action}"),
+            Message::DeletingPath(path) => write!(f, "Deleting path {path:?}"),
+        }
+    }
+}
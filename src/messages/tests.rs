@@ -0,0 +1,21 @@
+use super::*;
+
+#[test]
+fn test_messages_render_expected_text() {
+    assert_eq!(
+        Message::PackageAlreadyInstalled("some-package").to_string(),
+        "Package some-package already installed. Ignoring..."
+    );
+    assert_eq!(
+        Message::PackageSkipped(
+            "some-package",
+            &SkipReason::AlreadyInstalled(String::from("1.0.0"))
+        )
+        .to_string(),
+        "Skipping package some-package: already installed at version 1.0.0, use --reinstall to reinstall it"
+    );
+    assert_eq!(
+        Message::PackageNotFound("some-package").to_string(),
+        "Package some-package not found."
+    );
+}
@@ -0,0 +1,41 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+
+use log::info;
+
+use serde::Serialize;
+
+#[cfg(test)]
+mod tests;
+
+/// How command results are printed
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Log lines displayed by the frontend
+    #[default]
+    Text,
+    /// A json array printed to stdout, bypassing the frontend
+    Json,
+}
+
+/// Prints the results of a command in the given format.
+pub fn print_items<T: Serialize + Display>(
+    items: &[T],
+    format: OutputFormat,
+) -> Result<(), serde_json::Error> {
+    match format {
+        OutputFormat::Text => {
+            for item in items {
+                info!("{item}");
+            }
+        }
+        OutputFormat::Json => println!("{}", to_json(items)?),
+    }
+
+    Ok(())
+}
+
+fn to_json<T: Serialize>(items: &[T]) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(items)
+}
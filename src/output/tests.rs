@@ -0,0 +1,37 @@
+use serde_json::Value as JsonValue;
+
+use super::*;
+
+#[derive(Serialize)]
+struct Item {
+    name: String,
+}
+
+impl Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Item {}", self.name)
+    }
+}
+
+#[test]
+fn test_items_printed_as_json_array() {
+    let items = [
+        Item {
+            name: String::from("first"),
+        },
+        Item {
+            name: String::from("second"),
+        },
+    ];
+
+    let json: JsonValue = serde_json::from_str(&to_json(&items).unwrap()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([{ "name": "first" }, { "name": "second" }])
+    );
+}
+
+#[test]
+fn test_no_items_printed_as_empty_array() {
+    assert_eq!(to_json::<Item>(&[]).unwrap(), "[]");
+}
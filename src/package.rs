@@ -1,11 +1,38 @@
-use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
 
-#[derive(Default, Debug, Deserialize, Clone, Hash, PartialEq, Eq)]
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use thiserror::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// Latest manifest format understood by this version of japm. Manifests of older versions are
+/// still accepted.
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum PackageParseError {
+    #[error("The package manifest is invalid: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Manifest version {0} is newer than the supported version {MANIFEST_VERSION}, update japm to install this package")]
+    UnsupportedVersion(u32),
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
 pub struct RemotePackage {
+    /// Version of the manifest format, see [MANIFEST_VERSION]
+    pub manifest_version: u32,
     pub package_data: PackageData,
 
     #[serde(default)]
     pub dependencies: Vec<String>,
+    /// Names of packages this package replaces, such as its name before being renamed. They are
+    /// removed when this package is installed.
+    #[serde(default)]
+    pub replaces: Vec<String>,
 
     #[serde(default)]
     pub pre_install: Vec<String>,
@@ -17,31 +44,294 @@ pub struct RemotePackage {
     pub pre_remove: Vec<String>,
     /// Is empty until install action on package is performed
     #[serde(skip_deserializing)]
-    pub package_files: Vec<String>,
+    pub package_files: Vec<PackageFile>,
     #[serde(default)]
     pub post_remove: Vec<String>,
+
+    /// Architectures the package can be installed on, any architecture if empty
+    #[serde(default)]
+    pub arch: Vec<String>,
+    /// Operating systems the package can be installed on, any operating system if empty
+    #[serde(default)]
+    pub os: Vec<String>,
+
+    /// Commands provided by the package, registered so they can be resolved back to it
+    #[serde(default)]
+    pub binaries: Vec<String>,
+
+    /// Environment variables set for the install commands, overriding inherited ones
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Bytes downloaded by the install commands, if declared by the package
+    #[serde(default)]
+    pub download_size: Option<u64>,
+    /// Urls downloaded by the install commands. Their size is requested from their servers when
+    /// the download size is not declared.
+    #[serde(default)]
+    pub sources: Vec<String>,
+
+    /// Directory with the package files extracted from a package archive, moved into the install
+    /// directory before running the install commands
+    #[serde(skip)]
+    pub archive_files: Option<String>,
+
+    /// Files of the installed version when reinstalling over it without removing it first. They
+    /// are replaced by the files of this package, and deleted if this package doesn't install
+    /// them anymore.
+    #[serde(skip)]
+    pub overlaid_files: Option<Vec<PackageFile>>,
+    /// Url of the remote the package was found on, set by the package finder. None for packages
+    /// read from files.
+    #[serde(skip)]
+    pub source_remote: Option<String>,
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LocalPackage {
+    /// Stable database id of the installed package. None if the package does not come from the
+    /// database, or is not installed anymore.
+    #[serde(default)]
+    pub id: Option<i32>,
+
     pub package_data: PackageData,
 
     pub dependencies: Vec<String>,
+    /// Deferred actions journaled before packages could replace others don't have it
+    #[serde(default)]
+    pub replaces: Vec<String>,
+    /// Url of the remote the package was installed from, None if installed from a file
+    #[serde(default)]
+    pub source_remote: Option<String>,
+    /// Architectures the package can be installed on, see [RemotePackage::arch]
+    #[serde(default)]
+    pub arch: Vec<String>,
+    /// Operating systems the package can be installed on, see [RemotePackage::os]
+    #[serde(default)]
+    pub os: Vec<String>,
 
     pub pre_remove: Vec<String>,
-    pub package_files: Vec<String>,
+    pub package_files: Vec<PackageFile>,
     pub post_remove: Vec<String>,
 }
 
-#[derive(Default, Debug, Deserialize, Clone, Hash, PartialEq, Eq)]
+/// A file or directory installed by a package. The checksum and size are only recorded for
+/// regular files, and are missing for files installed before they were recorded.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
+#[serde(from = "StoredPackageFile")]
+pub struct PackageFile {
+    pub path: String,
+    /// Hex encoded SHA-256 of the content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+/// Package files used to be stored as plain paths, which are still accepted.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredPackageFile {
+    Path(String),
+    File {
+        path: String,
+        #[serde(default)]
+        sha256: Option<String>,
+        #[serde(default)]
+        size: Option<u64>,
+    },
+}
+
+impl From<StoredPackageFile> for PackageFile {
+    fn from(stored: StoredPackageFile) -> Self {
+        match stored {
+            StoredPackageFile::Path(path) => PackageFile {
+                path,
+                ..Default::default()
+            },
+            StoredPackageFile::File { path, sha256, size } => PackageFile { path, sha256, size },
+        }
+    }
+}
+
+/// A file without a recorded checksum and size.
+impl From<&str> for PackageFile {
+    fn from(path: &str) -> Self {
+        PackageFile {
+            path: String::from(path),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
 pub struct PackageData {
     pub name: String,
     pub version: String,
     pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintainer: Option<String>,
+}
+
+/// Only the version of a manifest, read first so that manifests of newer versions are reported
+/// as such even if the rest of their format changed.
+#[derive(Deserialize)]
+struct VersionedManifest {
+    manifest_version: u32,
 }
 
 impl RemotePackage {
-    pub fn from_json(json: &str) -> Result<RemotePackage, serde_json::Error> {
-        serde_json::from_str(json)
+    pub fn from_json(json: &str) -> Result<RemotePackage, PackageParseError> {
+        let VersionedManifest { manifest_version } = serde_json::from_str(json)?;
+        if manifest_version > MANIFEST_VERSION {
+            return Err(PackageParseError::UnsupportedVersion(manifest_version));
+        }
+
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Returns the name of the field that excludes the current target, if any.
+    pub fn incompatible_field(&self) -> Option<&'static str> {
+        self.incompatible_field_for(std::env::consts::ARCH, std::env::consts::OS)
+    }
+
+    fn incompatible_field_for(&self, arch: &str, os: &str) -> Option<&'static str> {
+        let supports = |targets: &Vec<String>, target: &str| {
+            targets.is_empty() || targets.iter().any(|t| t == target)
+        };
+
+        if !supports(&self.arch, arch) {
+            return Some("arch");
+        }
+        if !supports(&self.os, os) {
+            return Some("os");
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintSeverity {
+    /// The package can't be installed as is
+    Error,
+    /// The package can be installed, but likely doesn't behave as intended
+    Warning,
+}
+
+/// Problem found in a package manifest by [lint].
+#[derive(Debug, PartialEq)]
+pub struct LintWarning {
+    pub severity: LintSeverity,
+    pub message: String,
+}
+
+impl Display for LintWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            LintSeverity::Error => write!(f, "error: {}", self.message),
+            LintSeverity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+/// Checks a package manifest for problems that parsing it doesn't catch, meant for package
+/// authors before publishing.
+///
+/// Which paths the install commands produce is only known once they ran, so remove commands are
+/// only checked to reference absolute paths that also appear in an install command.
+pub fn lint(package: &RemotePackage) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let mut report =
+        |severity: LintSeverity, message: String| warnings.push(LintWarning { severity, message });
+
+    let package_data = &package.package_data;
+    if package_data.name.trim().is_empty() {
+        report(LintSeverity::Error, String::from("Name is empty"));
+    }
+    if let Err(parse_error) = Version::parse(&package_data.version) {
+        report(
+            LintSeverity::Error,
+            format!(
+                "Version {} is not valid semver: {parse_error}",
+                package_data.version
+            ),
+        );
+    }
+    if package.install.is_empty() {
+        report(
+            LintSeverity::Error,
+            String::from("There are no install commands"),
+        );
     }
+
+    let mut dependencies = HashSet::new();
+    for dependency in package.dependencies.iter() {
+        if !dependencies.insert(dependency) {
+            report(
+                LintSeverity::Error,
+                format!("Dependency {dependency} is listed more than once"),
+            );
+        }
+    }
+
+    let install_commands: Vec<&String> = package
+        .pre_install
+        .iter()
+        .chain(package.install.iter())
+        .chain(package.post_install.iter())
+        .collect();
+    for command in install_commands.iter() {
+        match shell_words::split(command) {
+            Ok(args) if args.is_empty() => report(
+                LintSeverity::Error,
+                String::from("An install command is empty"),
+            ),
+            Ok(_) => (),
+            Err(parse_error) => report(
+                LintSeverity::Error,
+                format!("Could not parse command {command}: {parse_error}"),
+            ),
+        }
+    }
+
+    for command in package.pre_remove.iter().chain(package.post_remove.iter()) {
+        let args = match shell_words::split(command) {
+            Ok(args) => args,
+            Err(parse_error) => {
+                report(
+                    LintSeverity::Error,
+                    format!("Could not parse command {command}: {parse_error}"),
+                );
+                continue;
+            }
+        };
+
+        // Words of arguments are checked too, to find paths in scripts passed to a shell
+        let paths = args
+            .iter()
+            .flat_map(|arg| arg.split_whitespace())
+            .map(|word| word.trim_end_matches(';'))
+            .filter(|word| word.starts_with('/'));
+        for path in paths {
+            if !install_commands
+                .iter()
+                .any(|command| command.contains(path))
+            {
+                report(
+                    LintSeverity::Warning,
+                    format!(
+                        "Remove command {command} references {path}, which no install command does"
+                    ),
+                );
+            }
+        }
+    }
+
+    warnings
 }
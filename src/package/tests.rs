@@ -0,0 +1,97 @@
+use super::*;
+
+#[test]
+fn test_supported_manifest_versions_parsed() {
+    for manifest_version in 0..=MANIFEST_VERSION {
+        let package = RemotePackage::from_json(&get_manifest(manifest_version)).unwrap();
+        assert_eq!(package.manifest_version, manifest_version);
+    }
+}
+
+#[test]
+fn test_newer_manifest_version_rejected() {
+    let result = RemotePackage::from_json(&get_manifest(MANIFEST_VERSION + 1));
+
+    assert!(matches!(
+        result,
+        Err(PackageParseError::UnsupportedVersion(version)) if version == MANIFEST_VERSION + 1
+    ));
+}
+
+#[test]
+fn test_manifest_without_version_rejected() {
+    let result = RemotePackage::from_json(
+        r#"{ "package_data": { "name": "test-package", "version": "0.0.1", "description": "" }, "install": [] }"#,
+    );
+
+    assert!(matches!(result, Err(PackageParseError::Json(_))));
+}
+
+#[test]
+fn test_valid_package_has_no_lint_warnings() {
+    let package = get_lint_package();
+
+    assert_eq!(lint(&package), vec![]);
+}
+
+#[test]
+fn test_invalid_package_fields_linted_as_errors() {
+    let mut package = get_lint_package();
+    package.package_data.name = String::from(" ");
+    package.package_data.version = String::from("1.0");
+    package.dependencies = vec![String::from("dependency"), String::from("dependency")];
+    package.install = vec![];
+    package.post_remove = vec![];
+
+    let warnings = lint(&package);
+
+    assert_eq!(warnings.len(), 4);
+    assert!(warnings
+        .iter()
+        .all(|warning| warning.severity == LintSeverity::Error));
+}
+
+#[test]
+fn test_unparseable_install_command_linted() {
+    let mut package = get_lint_package();
+    package.install.push(String::from("echo 'unbalanced"));
+
+    assert_eq!(lint(&package)[0].severity, LintSeverity::Error);
+}
+
+#[test]
+fn test_remove_command_of_path_not_installed_linted() {
+    let mut package = get_lint_package();
+    package.post_remove = vec![String::from("sh -c 'rm -r /etc/other-package;'")];
+
+    assert_eq!(
+        lint(&package),
+        vec![LintWarning {
+            severity: LintSeverity::Warning,
+            message: String::from(
+                "Remove command sh -c 'rm -r /etc/other-package;' references /etc/other-package, which no install command does"
+            ),
+        }]
+    );
+}
+
+fn get_lint_package() -> RemotePackage {
+    RemotePackage {
+        manifest_version: MANIFEST_VERSION,
+        package_data: PackageData {
+            name: String::from("test-package"),
+            version: String::from("1.0.0"),
+            ..Default::default()
+        },
+        dependencies: vec![String::from("dependency")],
+        install: vec![String::from("sh -c 'mkdir -p /etc/test-package'")],
+        post_remove: vec![String::from("rm -r /etc/test-package")],
+        ..Default::default()
+    }
+}
+
+fn get_manifest(manifest_version: u32) -> String {
+    format!(
+        r#"{{ "manifest_version": {manifest_version}, "package_data": {{ "name": "test-package", "version": "0.0.1", "description": "" }}, "install": [] }}"#
+    )
+}
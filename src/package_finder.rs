@@ -1,25 +1,86 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::Display;
-use std::io;
-use std::path::Path;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::fs;
 
-use log::{debug, info, warn};
+use log::{debug, info, trace, warn};
 
-use reqwest::StatusCode;
+use reqwest::header::{ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH};
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::{Client, Response, StatusCode, Url};
+
+use semver::Version;
+
+use flate2::read::GzDecoder;
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
 
 use thiserror::Error;
 
-use crate::config::Config;
-use crate::package::RemotePackage;
+use crate::archive::{self, ArchiveError};
+use crate::config::{self, Config, Remote};
+use crate::messages::Message;
+use crate::package::{PackageParseError, RemotePackage};
+use crate::progress::{self, ProgressType};
+
+pub use chained::{ChainedPackageFinder, ErrorPolicy};
+pub use directory::DirectoryPackageFinder;
+
+mod chained;
+mod directory;
+#[cfg(test)]
+mod tests;
+
+/// How many times a single request to a remote is retried before moving on to the next remote.
+const MAX_RETRIES_PER_REQUEST: u32 = 3;
+/// How many retries are allowed in total during a whole operation, across all requests.
+const DEFAULT_RETRY_BUDGET: u32 = 10;
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+const MAX_REDIRECTS: usize = 10;
+/// Package archives are extracted in a subdirectory named after the archive
+const ARCHIVES_EXTRACT_PATH: &str = "/var/lib/japm/archives/";
 
+/// Futures are boxed by [async_trait::async_trait] so that finders can be used as trait objects
+/// with their error type specified, such as in [ChainedPackageFinder]. They are not required to
+/// be [Send], as packages are searched from a single task.
+#[async_trait::async_trait(?Send)]
 pub trait PackageFinder {
     type Error: Display;
     async fn find_package(
         &mut self,
         package_name: &str,
     ) -> Result<Option<RemotePackage>, Self::Error>;
+    /// Finds the exact version of the package, instead of the latest one
+    async fn find_package_version(
+        &mut self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<Option<RemotePackage>, Self::Error>;
+    /// Versions of the package available on any remote, oldest first
+    async fn available_versions(&mut self, package_name: &str)
+        -> Result<Vec<Version>, Self::Error>;
+    /// Names of the dependencies of the package, None if the package is not found. Finders that
+    /// can get them without the whole manifest should override it, by default they are taken
+    /// from the found package.
+    async fn dependencies_of(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Option<Vec<String>>, Self::Error> {
+        Ok(self
+            .find_package(package_name)
+            .await?
+            .map(|package| package.dependencies))
+    }
+    /// Bytes the install commands of the package download, None if unknown. By default only the
+    /// size declared by the package is known.
+    async fn download_size(&mut self, package: &RemotePackage) -> Result<Option<u64>, Self::Error> {
+        Ok(package.download_size)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -30,94 +91,686 @@ pub enum PackageFindError {
     Reqwest(#[from] reqwest::Error),
     #[error("A json error has occured: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Package(#[from] PackageParseError),
+    #[error("Could not decompress {encoding} response: {error}")]
+    Decompress { encoding: String, error: io::Error },
+    #[error("Package is not valid utf-8: {0}")]
+    Encoding(#[from] std::string::FromUtf8Error),
+    #[error("Could not read package archive: {0}")]
+    Archive(#[from] ArchiveError),
+    #[error("Retry budget exhausted while downloading {0}, remotes seem unreachable")]
+    RetryBudgetExhausted(String),
+    /// Every remote failed with a network error, so it's unknown whether the package exists.
+    /// Contains the url of every remote and its error.
+    #[error("Every remote failed:\n{}", format_remote_errors(.0))]
+    AllRemotesFailed(Vec<(String, String)>),
+    #[error("Response of {url} ended after {got} of {expected} bytes")]
+    IncompleteResponse {
+        url: String,
+        expected: u64,
+        got: u64,
+    },
+    #[error("{url} served invalid json for package {package}: {error}")]
+    InvalidJson {
+        package: String,
+        url: String,
+        error: serde_json::Error,
+    },
+}
+
+/// Amount of retries shared across every request performed during an operation. Cloning the
+/// budget shares it, so that a systemically failing remote aborts the whole operation promptly
+/// instead of every request retrying on its own.
+#[derive(Clone, Debug)]
+pub struct RetryBudget {
+    remaining: Arc<AtomicU32>,
 }
 
 pub struct DefaultPackageFinder {
     from_file: bool,
-    remotes: Vec<String>,
+    remotes: Vec<Remote>,
+    client: Client,
     search_cache: HashMap<String, RemotePackage>,
+    /// Url each package was finally downloaded from, after following redirects
+    resolved_urls: HashMap<String, String>,
+    /// Urls of the remotes that were not used for each package served differently by several
+    /// remotes
+    ambiguous_packages: HashMap<String, Vec<String>>,
+    retry_budget: RetryBudget,
+    /// Where package archives are extracted, under the root japm operates on
+    archives_path: PathBuf,
 }
 impl DefaultPackageFinder {
     pub fn new(from_file: bool, config: &Config) -> DefaultPackageFinder {
         DefaultPackageFinder {
             from_file,
             remotes: config.remotes.values().cloned().collect(),
+            client: build_client(config.remotes.values()),
             search_cache: HashMap::new(),
+            resolved_urls: HashMap::new(),
+            ambiguous_packages: HashMap::new(),
+            retry_budget: RetryBudget::new(DEFAULT_RETRY_BUDGET),
+            archives_path: PathBuf::from(config::rooted(
+                config.root.as_deref(),
+                ARCHIVES_EXTRACT_PATH,
+            )),
+        }
+    }
+}
+
+impl RetryBudget {
+    pub fn new(retries: u32) -> RetryBudget {
+        RetryBudget {
+            remaining: Arc::new(AtomicU32::new(retries)),
         }
     }
+
+    /// Takes a single retry from the budget. Returns false if the budget is already exhausted.
+    pub fn try_consume(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |remaining| {
+                remaining.checked_sub(1)
+            })
+            .is_ok()
+    }
 }
+#[async_trait::async_trait(?Send)]
 impl PackageFinder for DefaultPackageFinder {
     type Error = PackageFindError;
     async fn find_package(
         &mut self,
         package_name: &str,
     ) -> Result<Option<RemotePackage>, Self::Error> {
-        info!("Searching for package {package_name}");
+        info!("{}", Message::SearchingPackage(package_name));
 
         if let Some(remote_package) = self.search_cache.get(package_name) {
-            debug!("Package search cache hit");
+            debug!(
+                "Package search cache hit, downloaded from {}",
+                self.resolved_urls
+                    .get(package_name)
+                    .map_or("a file", String::as_str)
+            );
             return Ok(Some(remote_package.clone()));
         }
 
+        if archive::is_archive(package_name) {
+            return find_from_archive(&resolve_package_path(package_name), &self.archives_path)
+                .map(Some);
+        }
+
+        // Along with the path or url it was read from, and the remote serving it
         let json_content = if self.from_file {
-            find_from_file(package_name).await?
+            let package_path = resolve_package_path(package_name);
+            find_from_file(&package_path).await?.map(|json_content| {
+                (
+                    json_content,
+                    package_path.to_string_lossy().into_owned(),
+                    None,
+                )
+            })
         } else {
-            find_from_remote(package_name, &self.remotes).await?
+            match find_from_remote(
+                package_name,
+                &self.remotes,
+                &self.client,
+                &self.retry_budget,
+            )
+            .await?
+            {
+                Some(found) => {
+                    if !found.conflicting_urls.is_empty() {
+                        let conflicting_urls = self
+                            .ambiguous_packages
+                            .entry(String::from(package_name))
+                            .or_insert(found.conflicting_urls);
+                        warn!(
+                            "Package {package_name} differs between remotes, using {} over {}",
+                            found.resolved_url,
+                            conflicting_urls.join(", ")
+                        );
+                    }
+
+                    self.resolved_urls
+                        .insert(String::from(package_name), found.resolved_url.clone());
+                    Some((
+                        found.json_content,
+                        found.resolved_url,
+                        Some(found.remote_url),
+                    ))
+                }
+                None => None,
+            }
         };
 
         match json_content {
             None => Ok(None),
-            Some(json_content) => {
-                let package = RemotePackage::from_json(&json_content)?;
+            Some((json_content, source, source_remote)) => {
+                let mut package = parse_package(&json_content, package_name, &source)?;
+                package.source_remote = source_remote;
                 self.search_cache
                     .insert(String::from(package_name), package.clone());
                 Ok(Some(package))
             }
         }
     }
+
+    /// Remotes serve every version at `packages/<name>/<version>/package.json`. Files and
+    /// archives contain a single version, which is only used if it's the requested one.
+    async fn find_package_version(
+        &mut self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<Option<RemotePackage>, Self::Error> {
+        if self.from_file || archive::is_archive(package_name) {
+            return Ok(self
+                .find_package(package_name)
+                .await?
+                .filter(|package| package.package_data.version == version));
+        }
+
+        let versioned_name = format!("{package_name}@{version}");
+        info!("{}", Message::SearchingPackage(&versioned_name));
+
+        if let Some(remote_package) = self.search_cache.get(&versioned_name) {
+            return Ok(Some(remote_package.clone()));
+        }
+
+        let versioned_path = format!("{package_name}/{version}");
+        let Some(found) = find_from_remote(
+            &versioned_path,
+            &self.remotes,
+            &self.client,
+            &self.retry_budget,
+        )
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let mut package = parse_package(&found.json_content, package_name, &found.resolved_url)?;
+        package.source_remote = Some(found.remote_url);
+        if package.package_data.version != version {
+            warn!(
+                "{} serves version {} instead of the requested {version}",
+                found.resolved_url, package.package_data.version
+            );
+            return Ok(None);
+        }
+
+        self.resolved_urls
+            .insert(versioned_name.clone(), found.resolved_url);
+        self.search_cache.insert(versioned_name, package.clone());
+        Ok(Some(package))
+    }
+
+    /// Merges the `packages/<name>/versions.json` arrays of every remote, remotes without one are
+    /// skipped.
+    async fn available_versions(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Vec<Version>, Self::Error> {
+        let resource = format!("packages/{package_name}/versions.json");
+        let mut versions = BTreeSet::new();
+        let mut remote_errors = Vec::new();
+
+        for remote in self.remotes.iter() {
+            let (json_content, url) = match fetch_from_remote(
+                &resource,
+                remote,
+                &self.client,
+                &self.retry_budget,
+                MAX_RETRIES_PER_REQUEST,
+            )
+            .await?
+            {
+                Fetched::Found(json_content, url) => (json_content, url),
+                Fetched::Missing => {
+                    debug!("Remote {} has no versions of {package_name}", remote.url);
+                    continue;
+                }
+                Fetched::Failed(url, error) => {
+                    remote_errors.push((url, error));
+                    continue;
+                }
+            };
+
+            for version in parse_json::<Vec<String>>(&json_content, package_name, &url)? {
+                match Version::parse(&version) {
+                    Ok(version) => {
+                        versions.insert(version);
+                    }
+                    Err(error) => debug!("Skipping invalid version {version} from {url}: {error}"),
+                }
+            }
+        }
+
+        if !remote_errors.is_empty() && remote_errors.len() == self.remotes.len() {
+            return Err(PackageFindError::AllRemotesFailed(remote_errors));
+        }
+
+        Ok(versions.into_iter().collect())
+    }
+
+    /// Undeclared sizes are the sum of the sizes of the sources, as reported by their servers to
+    /// HEAD requests. Sources that can't be reached leave the size unknown.
+    async fn download_size(&mut self, package: &RemotePackage) -> Result<Option<u64>, Self::Error> {
+        if package.download_size.is_some() || package.sources.is_empty() {
+            return Ok(package.download_size);
+        }
+
+        let mut total = 0;
+        for source in package.sources.iter() {
+            match get_source_size(source, &self.client).await {
+                Some(size) => total += size,
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(total))
+    }
+}
+
+/// Size of a source from the `Content-Length` of a HEAD request.
+async fn get_source_size(source: &str, client: &Client) -> Option<u64> {
+    let res = match client.head(source).send().await {
+        Ok(res) if res.status().is_success() => res,
+        Ok(res) => {
+            debug!(
+                "Could not get the size of {source}, status {}",
+                res.status()
+            );
+            return None;
+        }
+        Err(error) => {
+            debug!("Could not get the size of {source}: {error}");
+            return None;
+        }
+    };
+
+    // The body of a HEAD response is empty, so its length is only known from the header
+    res.headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse().ok())
 }
 
-async fn find_from_file(package_name: &str) -> Result<Option<String>, io::Error> {
-    if !Path::new(package_name).exists() {
+async fn find_from_file(package_path: &Path) -> Result<Option<String>, io::Error> {
+    if !package_path.exists() {
         return Ok(None);
     }
 
-    let json_content = fs::read_to_string(package_name).await?;
+    let json_content = fs::read_to_string(package_path).await?;
     Ok(Some(json_content))
 }
 
+/// Archives contain everything needed to install the package, so no remote is queried.
+fn find_from_archive(
+    archive_path: &Path,
+    archives_path: &Path,
+) -> Result<RemotePackage, PackageFindError> {
+    let archive_name = archive_path.file_stem().unwrap_or_default();
+    let extract_path = archives_path.join(archive_name);
+
+    Ok(archive::read_archive(archive_path, &extract_path)?)
+}
+
+/// Path of a package given as a file, see [resolve_package_path_in].
+fn resolve_package_path(package_name: &str) -> PathBuf {
+    let home = std::env::var("HOME").ok();
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    resolve_package_path_in(package_name, home.as_deref(), &current_dir)
+}
+
+/// Expands a leading `~` to `home`, makes the path absolute against `current_dir` and appends
+/// `.json` unless the path is a json file or a package archive.
+fn resolve_package_path_in(package_name: &str, home: Option<&str>, current_dir: &Path) -> PathBuf {
+    let path = match (package_name.strip_prefix('~'), home) {
+        (Some(""), Some(home)) => PathBuf::from(home),
+        (Some(relative), Some(home)) if relative.starts_with('/') => {
+            Path::new(home).join(relative.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(package_name),
+    };
+    let mut path = current_dir.join(path);
+
+    let has_extension = path
+        .extension()
+        .is_some_and(|extension| extension == "json" || extension == archive::ARCHIVE_EXTENSION);
+    if !has_extension {
+        path.as_mut_os_string().push(".json");
+    }
+
+    path
+}
+
+/// A package found on a remote
+struct FoundPackage {
+    json_content: String,
+    /// Url the package was finally downloaded from, after following redirects
+    resolved_url: String,
+    /// Url of the remote serving the package, as configured
+    remote_url: String,
+    /// Urls of the other remotes that serve a different package with the same name
+    conflicting_urls: Vec<String>,
+}
+
+/// Searches the remotes in order, the first remote serving the package wins. The remaining remotes
+/// are also checked for the package, to report ones serving different content under the same name.
 async fn find_from_remote(
     package_name: &str,
-    remotes: &[String],
-) -> Result<Option<String>, reqwest::Error> {
+    remotes: &[Remote],
+    client: &Client,
+    retry_budget: &RetryBudget,
+) -> Result<Option<FoundPackage>, PackageFindError> {
+    let remote_count = remotes.len();
     let mut remotes = remotes.iter();
-    let json_content = loop {
-        let mut remote = match remotes.next() {
-            Some(remote) => remote.clone(),
-            None => return Ok(None),
+    let mut remote_errors = Vec::new();
+
+    let (json_content, resolved_url, remote_url) = loop {
+        let Some(remote) = remotes.next() else {
+            // A single remote answering without the package means it was not found
+            if remote_errors.is_empty() || remote_errors.len() < remote_count {
+                return Ok(None);
+            }
+            return Err(PackageFindError::AllRemotesFailed(remote_errors));
         };
 
-        if remote.ends_with('/') {
-            remote.push_str(format!("/packages/{package_name}/package.json").as_str());
-        } else {
-            remote.push_str(format!("packages/{package_name}/package.json").as_str());
+        match fetch_from_remote(
+            &package_resource(package_name),
+            remote,
+            client,
+            retry_budget,
+            MAX_RETRIES_PER_REQUEST,
+        )
+        .await?
+        {
+            Fetched::Found(json_content, resolved_url) => {
+                break (json_content, resolved_url, remote.url.clone())
+            }
+            Fetched::Missing => (),
+            Fetched::Failed(url, error) => remote_errors.push((url, error)),
+        }
+    };
+
+    let mut conflicting_urls = Vec::new();
+    if remotes.len() > 0 {
+        let content: JsonValue = parse_json(&json_content, package_name, &resolved_url)?;
+
+        for remote in remotes {
+            // Only a best effort check, so failing remotes are not retried
+            let Ok(Fetched::Found(other_content, other_url)) = fetch_from_remote(
+                &package_resource(package_name),
+                remote,
+                client,
+                retry_budget,
+                0,
+            )
+            .await
+            else {
+                continue;
+            };
+
+            match serde_json::from_str::<JsonValue>(&other_content) {
+                Ok(other_content) if other_content == content => (),
+                _ => conflicting_urls.push(other_url),
+            }
         }
+    }
 
-        match reqwest::get(&remote).await {
+    Ok(Some(FoundPackage {
+        json_content,
+        resolved_url,
+        remote_url,
+        conflicting_urls,
+    }))
+}
+
+/// Path of the package json of a package, or of a version of it, relative to the remote url
+fn package_resource(package_name: &str) -> String {
+    format!("packages/{package_name}/package.json")
+}
+
+/// Outcome of downloading a resource from a single remote
+enum Fetched {
+    /// Content of the resource and the url it was finally downloaded from
+    Found(String, String),
+    Missing,
+    /// Url of the resource and the error it failed with, once out of retries for network errors
+    Failed(String, String),
+}
+
+/// Downloads a resource such as a package json from a single remote, retrying failed requests up
+/// to `max_retries` times.
+async fn fetch_from_remote(
+    resource: &str,
+    remote: &Remote,
+    client: &Client,
+    retry_budget: &RetryBudget,
+    max_retries: u32,
+) -> Result<Fetched, PackageFindError> {
+    let mut url = remote.url.clone();
+    if url.ends_with('/') {
+        url.push_str(format!("/{resource}").as_str());
+    } else {
+        url.push_str(resource);
+    }
+
+    let mut retries = 0;
+    loop {
+        let mut request = client.get(&url).header(ACCEPT_ENCODING, "gzip, zstd");
+        if let Some(auth_header) = &remote.auth_header {
+            request = request.header(AUTHORIZATION, auth_header);
+        }
+
+        match request.send().await {
             Ok(res) => {
-                if res.status() != StatusCode::OK {
-                    debug!("Package {package_name} not found in remote {remote}");
-                    continue;
+                // Other statuses such as server errors or denied access say nothing about
+                // whether the remote has the resource
+                match res.status() {
+                    StatusCode::OK => (),
+                    StatusCode::NOT_FOUND | StatusCode::GONE => {
+                        debug!("{resource} not found in remote {url}");
+                        return Ok(Fetched::Missing);
+                    }
+                    status => {
+                        warn!("Remote {url} answered with {status}");
+                        return Ok(Fetched::Failed(url, format!("Answered with {status}")));
+                    }
+                }
+
+                let resolved_url = res.url().to_string();
+                if resolved_url != url {
+                    debug!("{resource} was redirected to {resolved_url}");
                 }
 
-                break res.text().await?;
+                return Ok(Fetched::Found(download_body(res).await?, resolved_url));
             }
             Err(error) => {
                 warn!("Error while attempting to download package:\n{error}");
-                continue;
+
+                // Rejected redirects will be rejected again
+                if error.is_redirect() {
+                    // The reason given by redirect_policy names the redirect target
+                    let reason = std::error::Error::source(&error)
+                        .map(|source| source.to_string())
+                        .unwrap_or_else(|| error.to_string());
+                    return Ok(Fetched::Failed(url, format!("Rejected redirect: {reason}")));
+                }
+
+                if retries == max_retries {
+                    return Ok(Fetched::Failed(url, error.to_string()));
+                }
+
+                if !retry_budget.try_consume() {
+                    return Err(PackageFindError::RetryBudgetExhausted(String::from(
+                        resource,
+                    )));
+                }
+
+                retries += 1;
+                debug!("Retrying {url} ({retries}/{max_retries})");
+                tokio::time::sleep(RETRY_DELAY).await;
             }
         };
+    }
+}
+
+/// Reads the response body chunk by chunk, reporting the downloaded bytes to the progressbar when
+/// the size of the body is known. Fails with [PackageFindError::IncompleteResponse] if the body
+/// is shorter than its announced size. Compressed bodies are decompressed, see [decode_body].
+async fn download_body(mut res: Response) -> Result<String, PackageFindError> {
+    let url = res.url().to_string();
+    let expected = res.content_length();
+    let encoding = res
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|encoding| encoding.to_str().ok())
+        .map(String::from);
+
+    progress::reset(ProgressType::Download).await;
+    if let Some(expected) = expected {
+        let target = i32::try_from(expected).unwrap_or(i32::MAX);
+        progress::increment_target(ProgressType::Download, target).await;
+    }
+
+    let mut body = Vec::new();
+    let result = loop {
+        match res.chunk().await {
+            Ok(Some(chunk)) => {
+                body.extend_from_slice(&chunk);
+                if expected.is_some() {
+                    progress::increment_completed(ProgressType::Download, chunk.len() as i32).await;
+                }
+            }
+            Ok(None) => break Ok(()),
+            Err(error) => break Err(error),
+        }
     };
 
-    Ok(Some(json_content))
+    progress::reset(ProgressType::Download).await;
+
+    // The connection closing early is reported as an error of the body, which doesn't tell how
+    // much was received
+    let got = body.len() as u64;
+    if let Some(expected) = expected.filter(|expected| got < *expected) {
+        return Err(PackageFindError::IncompleteResponse { url, expected, got });
+    }
+    result?;
+
+    Ok(String::from_utf8(decode_body(body, encoding.as_deref())?)?)
+}
+
+/// Decompresses a body according to its `Content-Encoding`. Only the encodings requested from
+/// remotes are supported.
+fn decode_body(body: Vec<u8>, encoding: Option<&str>) -> Result<Vec<u8>, PackageFindError> {
+    let decompress_error = |encoding: &str, error: io::Error| PackageFindError::Decompress {
+        encoding: String::from(encoding),
+        error,
+    };
+
+    match encoding.map(str::trim) {
+        None | Some("identity") => Ok(body),
+        Some(encoding @ ("gzip" | "x-gzip")) => {
+            let mut decoded = Vec::new();
+            GzDecoder::new(body.as_slice())
+                .read_to_end(&mut decoded)
+                .map_err(|error| decompress_error(encoding, error))?;
+            Ok(decoded)
+        }
+        Some(encoding @ "zstd") => zstd::stream::decode_all(body.as_slice())
+            .map_err(|error| decompress_error(encoding, error)),
+        Some(encoding) => Err(decompress_error(
+            encoding,
+            io::Error::new(io::ErrorKind::Unsupported, "unsupported encoding"),
+        )),
+    }
+}
+
+fn format_remote_errors(remote_errors: &[(String, String)]) -> String {
+    remote_errors
+        .iter()
+        .map(|(url, error)| format!("{url}: {error}"))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Parses json downloaded from `url` for the package, naming both in the error.
+fn parse_json<T: DeserializeOwned>(
+    json_content: &str,
+    package_name: &str,
+    url: &str,
+) -> Result<T, PackageFindError> {
+    serde_json::from_str(json_content).map_err(|error| PackageFindError::InvalidJson {
+        package: String::from(package_name),
+        url: String::from(url),
+        error,
+    })
+}
+
+/// Parses a package manifest downloaded from `url`, naming both in json errors.
+fn parse_package(
+    json_content: &str,
+    package_name: &str,
+    url: &str,
+) -> Result<RemotePackage, PackageFindError> {
+    RemotePackage::from_json(json_content).map_err(|error| match error {
+        PackageParseError::Json(error) => PackageFindError::InvalidJson {
+            package: String::from(package_name),
+            url: String::from(url),
+            error,
+        },
+        error => PackageFindError::Package(error),
+    })
+}
+
+/// Creates the client used for all remotes, which rejects redirects of trusted remotes to other
+/// hosts.
+fn build_client<'a>(remotes: impl Iterator<Item = &'a Remote>) -> Client {
+    let trusted_hosts: HashSet<String> = remotes
+        .filter(|remote| remote.trusted)
+        .filter_map(|remote| Url::parse(&remote.url).ok()?.host_str().map(String::from))
+        .collect();
+
+    let policy = Policy::custom(move |attempt| redirect_policy(attempt, &trusted_hosts));
+
+    Client::builder()
+        .redirect(policy)
+        .build()
+        .expect("Could not create http client")
+}
+
+fn redirect_policy(attempt: Attempt, trusted_hosts: &HashSet<String>) -> reqwest::redirect::Action {
+    trace!(
+        "Redirect chain: {:?} -> {}",
+        attempt
+            .previous()
+            .iter()
+            .map(|url| url.as_str())
+            .collect::<Vec<&str>>(),
+        attempt.url()
+    );
+
+    if attempt.previous().len() > MAX_REDIRECTS {
+        return attempt.error("too many redirects");
+    }
+
+    let origin_host = attempt
+        .previous()
+        .first()
+        .and_then(|url| url.host_str())
+        .map(String::from);
+
+    let cross_host = origin_host.as_deref() != attempt.url().host_str();
+
+    match origin_host {
+        Some(origin_host) if cross_host && trusted_hosts.contains(&origin_host) => {
+            let error = format!(
+                "trusted remote {origin_host} redirected to another host {}",
+                attempt.url()
+            );
+            attempt.error(error)
+        }
+        _ => attempt.follow(),
+    }
 }
@@ -0,0 +1,120 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+use log::warn;
+
+use semver::Version;
+
+use super::PackageFinder;
+use crate::package::RemotePackage;
+
+/// What a [ChainedPackageFinder] does when one of its finders fails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Log the error and search the next finder. The error is only returned if no finder found
+    /// the package.
+    #[default]
+    SkipOnError,
+    /// Return the error without searching the remaining finders.
+    FailFast,
+}
+
+/// Searches its finders in order and returns the first package found, for example a local
+/// directory before the remotes.
+pub struct ChainedPackageFinder<E> {
+    finders: Vec<Box<dyn PackageFinder<Error = E>>>,
+    error_policy: ErrorPolicy,
+}
+
+impl<E: Display + 'static> ChainedPackageFinder<E> {
+    pub fn new(
+        finders: Vec<Box<dyn PackageFinder<Error = E>>>,
+        error_policy: ErrorPolicy,
+    ) -> ChainedPackageFinder<E> {
+        ChainedPackageFinder {
+            finders,
+            error_policy,
+        }
+    }
+}
+
+impl ErrorPolicy {
+    /// Returns the error to fail with right away according to the policy, or keeps it to be
+    /// returned once all finders were searched.
+    fn handle<E: Display>(self, error: E, last_error: &mut Option<E>) -> Result<(), E> {
+        match self {
+            ErrorPolicy::FailFast => Err(error),
+            ErrorPolicy::SkipOnError => {
+                warn!("Package finder failed, searching the next one: {error}");
+                *last_error = Some(error);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl<E: Display + 'static> PackageFinder for ChainedPackageFinder<E> {
+    type Error = E;
+
+    async fn find_package(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Option<RemotePackage>, Self::Error> {
+        let mut last_error = None;
+
+        for finder in self.finders.iter_mut() {
+            match finder.find_package(package_name).await {
+                Ok(Some(package)) => return Ok(Some(package)),
+                Ok(None) => (),
+                Err(error) => self.error_policy.handle(error, &mut last_error)?,
+            }
+        }
+
+        last_error.map_or(Ok(None), Err)
+    }
+
+    async fn find_package_version(
+        &mut self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<Option<RemotePackage>, Self::Error> {
+        let mut last_error = None;
+
+        for finder in self.finders.iter_mut() {
+            match finder.find_package_version(package_name, version).await {
+                Ok(Some(package)) => return Ok(Some(package)),
+                Ok(None) => (),
+                Err(error) => self.error_policy.handle(error, &mut last_error)?,
+            }
+        }
+
+        last_error.map_or(Ok(None), Err)
+    }
+
+    /// Merges the versions of every finder. With [ErrorPolicy::SkipOnError] an error is only
+    /// returned if every finder failed.
+    async fn available_versions(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Vec<Version>, Self::Error> {
+        let mut versions = BTreeSet::new();
+        let mut last_error = None;
+        let mut any_succeeded = false;
+
+        for finder in self.finders.iter_mut() {
+            match finder.available_versions(package_name).await {
+                Ok(found) => {
+                    any_succeeded = true;
+                    versions.extend(found);
+                }
+                Err(error) => self.error_policy.handle(error, &mut last_error)?,
+            }
+        }
+
+        match last_error {
+            Some(error) if !any_succeeded => Err(error),
+            _ => Ok(versions.into_iter().collect()),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+
+use log::{debug, info, warn};
+
+use semver::Version;
+
+use super::{PackageFindError, PackageFinder};
+use crate::messages::Message;
+use crate::package::RemotePackage;
+
+#[cfg(test)]
+mod tests;
+
+/// Finds packages in a local directory laid out like a remote, `packages/<name>/package.json`
+/// for the latest version, `packages/<name>/<version>/package.json` for the others and
+/// `packages/<name>/versions.json` listing them. Useful for offline mirrors.
+pub struct DirectoryPackageFinder {
+    root: PathBuf,
+    search_cache: HashMap<String, RemotePackage>,
+}
+
+impl DirectoryPackageFinder {
+    pub fn new(root: impl Into<PathBuf>) -> DirectoryPackageFinder {
+        DirectoryPackageFinder {
+            root: root.into(),
+            search_cache: HashMap::new(),
+        }
+    }
+
+    fn package_directory(&self, package_name: &str) -> PathBuf {
+        self.root.join("packages").join(package_name)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl PackageFinder for DirectoryPackageFinder {
+    type Error = PackageFindError;
+
+    async fn find_package(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Option<RemotePackage>, Self::Error> {
+        info!("{}", Message::SearchingPackage(package_name));
+
+        if let Some(remote_package) = self.search_cache.get(package_name) {
+            return Ok(Some(remote_package.clone()));
+        }
+
+        let package_path = self.package_directory(package_name).join("package.json");
+        let Some(json_content) = read_if_exists(&package_path).await? else {
+            return Ok(None);
+        };
+
+        let package = RemotePackage::from_json(&json_content)?;
+        self.search_cache
+            .insert(String::from(package_name), package.clone());
+        Ok(Some(package))
+    }
+
+    async fn find_package_version(
+        &mut self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<Option<RemotePackage>, Self::Error> {
+        let versioned_name = format!("{package_name}@{version}");
+        info!("{}", Message::SearchingPackage(&versioned_name));
+
+        if let Some(remote_package) = self.search_cache.get(&versioned_name) {
+            return Ok(Some(remote_package.clone()));
+        }
+
+        let package_path = self
+            .package_directory(package_name)
+            .join(version)
+            .join("package.json");
+        let Some(json_content) = read_if_exists(&package_path).await? else {
+            return Ok(None);
+        };
+
+        let package = RemotePackage::from_json(&json_content)?;
+        if package.package_data.version != version {
+            warn!(
+                "{package_path:?} contains version {} instead of the requested {version}",
+                package.package_data.version
+            );
+            return Ok(None);
+        }
+
+        self.search_cache.insert(versioned_name, package.clone());
+        Ok(Some(package))
+    }
+
+    async fn available_versions(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Vec<Version>, Self::Error> {
+        let versions_path = self.package_directory(package_name).join("versions.json");
+        let Some(json_content) = read_if_exists(&versions_path).await? else {
+            debug!("{versions_path:?} does not exist, no versions of {package_name}");
+            return Ok(Vec::new());
+        };
+
+        let mut versions = BTreeSet::new();
+        for version in serde_json::from_str::<Vec<String>>(&json_content)? {
+            match Version::parse(&version) {
+                Ok(version) => {
+                    versions.insert(version);
+                }
+                Err(error) => {
+                    debug!("Skipping invalid version {version} from {versions_path:?}: {error}")
+                }
+            }
+        }
+
+        Ok(versions.into_iter().collect())
+    }
+}
+
+async fn read_if_exists(path: &Path) -> Result<Option<String>, io::Error> {
+    match fs::read_to_string(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs as std_fs;
+
+use tokio::test;
+
+use super::*;
+
+use crate::package::PackageParseError;
+
+#[test]
+async fn test_packages_found_in_directory() {
+    let mut package_finder = get_directory_finder("find");
+    let root = &package_finder.root;
+    write_package(root, "packages/first-package", "first-package", "1.0.0");
+    write_package(root, "packages/second-package", "second-package", "0.2.0");
+
+    let first = package_finder.find_package("first-package").await.unwrap();
+    assert_eq!(first.unwrap().package_data.version, "1.0.0");
+
+    let second = package_finder.find_package("second-package").await.unwrap();
+    assert_eq!(second.unwrap().package_data.name, "second-package");
+
+    assert!(package_finder
+        .find_package("missing-package")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+async fn test_package_versions_found_in_directory() {
+    let mut package_finder = get_directory_finder("versions");
+    let root = &package_finder.root;
+    write_package(root, "packages/test-package/0.1.0", "test-package", "0.1.0");
+    std_fs::write(
+        root.join("packages/test-package/versions.json"),
+        r#"["0.2.0", "not-a-version", "0.1.0"]"#,
+    )
+    .unwrap();
+
+    let package = package_finder
+        .find_package_version("test-package", "0.1.0")
+        .await
+        .unwrap();
+    assert_eq!(package.unwrap().package_data.version, "0.1.0");
+
+    assert!(package_finder
+        .find_package_version("test-package", "0.3.0")
+        .await
+        .unwrap()
+        .is_none());
+
+    assert_eq!(
+        package_finder
+            .available_versions("test-package")
+            .await
+            .unwrap(),
+        vec![Version::new(0, 1, 0), Version::new(0, 2, 0)]
+    );
+    assert!(package_finder
+        .available_versions("missing-package")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+async fn test_invalid_package_in_directory_rejected() {
+    let mut package_finder = get_directory_finder("invalid");
+    let package_directory = package_finder.root.join("packages/invalid-package");
+    std_fs::create_dir_all(&package_directory).unwrap();
+    std_fs::write(package_directory.join("package.json"), "{ not json").unwrap();
+
+    assert!(matches!(
+        package_finder.find_package("invalid-package").await,
+        Err(PackageFindError::Package(PackageParseError::Json(_)))
+    ));
+}
+
+/// Returns a finder for an empty directory unique to the test.
+fn get_directory_finder(name: &str) -> DirectoryPackageFinder {
+    let root = PathBuf::from(format!("/tmp/japm/tests/directory_finder_{name}"));
+    if root.exists() {
+        std_fs::remove_dir_all(&root).expect("Could not remove previous package directory");
+    }
+    std_fs::create_dir_all(&root).unwrap();
+
+    DirectoryPackageFinder::new(root)
+}
+
+fn write_package(root: &Path, directory: &str, name: &str, version: &str) {
+    let directory = root.join(directory);
+    std_fs::create_dir_all(&directory).unwrap();
+    std_fs::write(
+        directory.join("package.json"),
+        format!(
+            r#"{{ "manifest_version": 1, "package_data": {{ "name": "{name}", "version": "{version}", "description": "" }}, "install": [] }}"#
+        ),
+    )
+    .unwrap();
+}
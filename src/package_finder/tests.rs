@@ -0,0 +1,612 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread;
+
+use tokio::test;
+
+use super::*;
+
+use crate::test_helpers::MockProgressbar;
+
+/// Nothing listens on port 1, so requests fail immediately without touching the network.
+const UNREACHABLE_REMOTE: &str = "http://127.0.0.1:1/";
+
+#[test]
+async fn test_retry_budget_is_consumed() {
+    let budget = RetryBudget::new(2);
+
+    assert!(budget.try_consume());
+    assert!(budget.try_consume());
+    assert!(!budget.try_consume());
+    assert_eq!(budget.remaining.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+async fn test_operation_aborts_once_retry_budget_exhausted() {
+    // Enough for exactly 3 packages to exhaust their own retries, and one retry more.
+    let budget = RetryBudget::new(MAX_RETRIES_PER_REQUEST * 3 + 1);
+    let mut package_finder = get_unreachable_finder(budget.clone());
+
+    let mut aborted_at = None;
+    for i in 0..10 {
+        match package_finder.find_package(&format!("package_{i}")).await {
+            Err(PackageFindError::AllRemotesFailed(_)) => (),
+            Err(error) => {
+                assert!(matches!(error, PackageFindError::RetryBudgetExhausted(_)));
+                aborted_at = Some(i);
+                break;
+            }
+            Ok(_) => panic!("Unreachable remote served a package"),
+        }
+    }
+
+    assert_eq!(aborted_at, Some(3));
+    assert_eq!(budget.remaining.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+async fn test_unreachable_remotes_reported_as_failed() {
+    let mut package_finder = get_unreachable_finder(RetryBudget::new(MAX_RETRIES_PER_REQUEST));
+
+    match package_finder.find_package("test-package").await {
+        Err(PackageFindError::AllRemotesFailed(remote_errors)) => {
+            assert_eq!(remote_errors.len(), 1);
+            assert!(remote_errors[0].0.starts_with(UNREACHABLE_REMOTE));
+        }
+        _ => panic!("Expected every remote to fail"),
+    }
+}
+
+#[test]
+async fn test_remote_answering_with_error_status_reported_as_failed() {
+    for response in [
+        "HTTP/1.1 500 Internal Server Error",
+        "HTTP/1.1 403 Forbidden",
+    ] {
+        let port = start_server(move |_, _| {
+            format!("{response}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+        });
+        let mut package_finder = get_multiple_remotes_finder(&[port]);
+
+        match package_finder.find_package("test-package").await {
+            Err(PackageFindError::AllRemotesFailed(remote_errors)) => {
+                assert_eq!(remote_errors.len(), 1);
+                assert!(remote_errors[0]
+                    .0
+                    .starts_with(&format!("http://127.0.0.1:{port}/")));
+            }
+            _ => panic!("Expected the remote answering with {response} to fail"),
+        }
+    }
+}
+
+#[test]
+async fn test_package_missing_from_reachable_remote_not_found() {
+    let port = start_server(|_, _| {
+        String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+    });
+
+    progress::set_boxed_progress(Box::new(MockProgressbar));
+    let config = Config::from_json(&format!(
+        r#"{{ "remotes": {{ "unreachable": "{UNREACHABLE_REMOTE}", "reachable": "http://127.0.0.1:{port}/" }} }}"#
+    ))
+    .unwrap();
+    let mut package_finder = DefaultPackageFinder::new(false, &config);
+    package_finder.retry_budget = RetryBudget::new(MAX_RETRIES_PER_REQUEST);
+
+    assert!(package_finder
+        .find_package("test-package")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+async fn test_redirected_package_records_final_url() {
+    let port = start_redirecting_server();
+    let mut package_finder = get_redirecting_finder(port, false);
+
+    let package = package_finder
+        .find_package("test-redirected-package")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        package.unwrap().package_data.name,
+        "test-redirected-package"
+    );
+    assert_eq!(
+        package_finder.resolved_urls.get("test-redirected-package"),
+        Some(&format!(
+            "http://localhost:{port}/packages/test-redirected-package/package.json"
+        ))
+    );
+}
+
+#[test]
+async fn test_trusted_remote_cross_host_redirect_rejected() {
+    let port = start_redirecting_server();
+    let mut package_finder = get_redirecting_finder(port, true);
+
+    match package_finder.find_package("test-redirected-package").await {
+        Err(PackageFindError::AllRemotesFailed(remote_errors)) => {
+            assert_eq!(remote_errors.len(), 1);
+            assert!(remote_errors[0].1.contains(&format!(
+                "http://localhost:{port}/packages/test-redirected-package/package.json"
+            )));
+        }
+        _ => panic!("Expected the rejected redirect to fail"),
+    }
+    assert!(package_finder.resolved_urls.is_empty());
+}
+
+#[test]
+async fn test_package_differing_between_remotes_reported() {
+    let ports = [
+        start_package_server("first content"),
+        start_package_server("second content"),
+    ];
+    let mut package_finder = get_multiple_remotes_finder(&ports);
+
+    let package = package_finder
+        .find_package("test-ambiguous-package")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Remotes have no priority, so either can be used
+    let resolved_url = package_finder
+        .resolved_urls
+        .get("test-ambiguous-package")
+        .unwrap();
+    let conflicting_urls = package_finder
+        .ambiguous_packages
+        .get("test-ambiguous-package")
+        .unwrap();
+
+    assert_eq!(conflicting_urls.len(), 1);
+    assert_ne!(resolved_url, &conflicting_urls[0]);
+    let expected_description = if resolved_url.contains(&ports[0].to_string()) {
+        "first content"
+    } else {
+        "second content"
+    };
+    assert_eq!(package.package_data.description, expected_description);
+}
+
+#[test]
+async fn test_package_identical_between_remotes_not_reported() {
+    let ports = [
+        start_package_server("same content"),
+        start_package_server("same content"),
+    ];
+    let mut package_finder = get_multiple_remotes_finder(&ports);
+
+    assert!(package_finder
+        .find_package("test-unambiguous-package")
+        .await
+        .unwrap()
+        .is_some());
+    assert!(package_finder.ambiguous_packages.is_empty());
+}
+
+#[test]
+async fn test_package_version_requested_from_versioned_path() {
+    let port = start_server(|path, _| {
+        if path.trim_start_matches('/') != "packages/test-versioned-package/1.2.3/package.json" {
+            return String::from(
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            );
+        }
+
+        let body = r#"{ "manifest_version": 1, "package_data": { "name": "test-versioned-package", "version": "1.2.3", "description": "" }, "install": [] }"#;
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    });
+    let mut package_finder = get_multiple_remotes_finder(&[port]);
+
+    let package = package_finder
+        .find_package_version("test-versioned-package", "1.2.3")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(package.package_data.version, "1.2.3");
+
+    assert!(package_finder
+        .find_package_version("test-versioned-package", "2.0.0")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+async fn test_chained_finder_searches_directory_before_remote() {
+    let directory = get_chained_test_directory("fallback");
+    write_directory_package(&directory, "test-local-package");
+
+    let port = start_package_server("remote content");
+    let mut package_finder = get_chained_finder(
+        vec![
+            Box::new(DirectoryPackageFinder::new(&directory)),
+            Box::new(get_multiple_remotes_finder(&[port])),
+        ],
+        ErrorPolicy::FailFast,
+    );
+
+    let local = package_finder
+        .find_package("test-local-package")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(local.package_data.description, "local content");
+
+    let remote = package_finder
+        .find_package("test-remote-package")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(remote.package_data.name, "test-remote-package");
+    assert_eq!(remote.package_data.description, "remote content");
+}
+
+#[test]
+async fn test_chained_finder_error_policy() {
+    let directory = get_chained_test_directory("policy");
+    write_directory_package(&directory, "test-local-package");
+
+    let mut fail_fast = get_chained_finder(
+        vec![
+            Box::new(get_unreachable_finder(RetryBudget::new(0))),
+            Box::new(DirectoryPackageFinder::new(&directory)),
+        ],
+        ErrorPolicy::FailFast,
+    );
+    assert!(matches!(
+        fail_fast.find_package("test-local-package").await,
+        Err(PackageFindError::RetryBudgetExhausted(_))
+    ));
+
+    let mut skip_on_error = get_chained_finder(
+        vec![
+            Box::new(get_unreachable_finder(RetryBudget::new(0))),
+            Box::new(DirectoryPackageFinder::new(&directory)),
+        ],
+        ErrorPolicy::SkipOnError,
+    );
+    assert!(skip_on_error
+        .find_package("test-local-package")
+        .await
+        .unwrap()
+        .is_some());
+    // No finder found it, so the skipped error is returned
+    assert!(skip_on_error
+        .find_package("test-missing-package")
+        .await
+        .is_err());
+}
+
+fn get_unreachable_finder(retry_budget: RetryBudget) -> DefaultPackageFinder {
+    progress::set_boxed_progress(Box::new(MockProgressbar));
+
+    let config = Config::from_json(&format!(
+        r#"{{ "remotes": {{ "unreachable": "{UNREACHABLE_REMOTE}" }} }}"#
+    ))
+    .unwrap();
+
+    let mut package_finder = DefaultPackageFinder::new(false, &config);
+    package_finder.retry_budget = retry_budget;
+    package_finder
+}
+
+fn get_redirecting_finder(port: u16, trusted: bool) -> DefaultPackageFinder {
+    progress::set_boxed_progress(Box::new(MockProgressbar));
+
+    let config = Config::from_json(&format!(
+        r#"{{ "remotes": {{ "redirecting": {{ "url": "http://127.0.0.1:{port}/redirect/", "trusted": {trusted} }} }} }}"#
+    ))
+    .unwrap();
+
+    DefaultPackageFinder::new(false, &config)
+}
+
+/// Starts a server that redirects requests under `/redirect` to the same path on `localhost`, and
+/// answers every other request with a package named after the requested path. Returns its port.
+fn start_redirecting_server() -> u16 {
+    start_server(|path, port| {
+        let package_path = path.trim_start_matches("/redirect").trim_start_matches('/');
+
+        if path.starts_with("/redirect") {
+            format!(
+                "HTTP/1.1 302 Found\r\nLocation: http://localhost:{port}/{package_path}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+        } else {
+            package_response(package_path, "")
+        }
+    })
+}
+
+/// Starts a server answering every request with a package named after the requested path, with
+/// the given description. Returns its port.
+fn start_package_server(description: &'static str) -> u16 {
+    start_server(move |path, _| package_response(path.trim_start_matches('/'), description))
+}
+
+#[test]
+async fn test_truncated_response_is_reported() {
+    let port = start_server(|_, _| {
+        String::from(
+            "HTTP/1.1 200 OK\r\nContent-Length: 500\r\nConnection: close\r\n\r\n{ \"package_data\": {",
+        )
+    });
+    let mut package_finder = get_multiple_remotes_finder(&[port]);
+
+    assert!(matches!(
+        package_finder.find_package("test-package").await,
+        Err(PackageFindError::IncompleteResponse { expected: 500, .. })
+    ));
+}
+
+#[test]
+async fn test_invalid_json_names_package_and_url() {
+    let port = start_server(|_, _| {
+        String::from("HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\n{ {")
+    });
+    let mut package_finder = get_multiple_remotes_finder(&[port]);
+
+    match package_finder.find_package("test-package").await {
+        Err(PackageFindError::InvalidJson { package, url, .. }) => {
+            assert_eq!(package, "test-package");
+            assert!(url.ends_with("packages/test-package/package.json"));
+        }
+        _ => panic!("Expected invalid json error"),
+    }
+}
+
+#[test]
+async fn test_gzip_body_decompressed() {
+    let json = r#"{ "manifest_version": 1 }"#;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(json.as_bytes()).unwrap();
+
+    let decoded = decode_body(encoder.finish().unwrap(), Some("gzip")).unwrap();
+
+    assert_eq!(decoded, json.as_bytes());
+}
+
+#[test]
+async fn test_zstd_body_decompressed() {
+    let json = r#"{ "manifest_version": 1 }"#;
+    let encoded = zstd::stream::encode_all(json.as_bytes(), 0).unwrap();
+
+    assert_eq!(decode_body(encoded, Some("zstd")).unwrap(), json.as_bytes());
+}
+
+#[test]
+async fn test_uncompressed_body_left_unchanged() {
+    let json = r#"{ "manifest_version": 1 }"#;
+
+    assert_eq!(
+        decode_body(json.as_bytes().to_vec(), None).unwrap(),
+        json.as_bytes()
+    );
+}
+
+#[test]
+async fn test_corrupt_gzip_body_reported() {
+    assert!(matches!(
+        decode_body(b"not gzip".to_vec(), Some("gzip")),
+        Err(PackageFindError::Decompress { .. })
+    ));
+}
+
+#[test]
+async fn test_home_package_path_expanded() {
+    assert_eq!(
+        resolve_package_path_in("~/packages/foo", Some("/home/user"), Path::new("/tmp")),
+        PathBuf::from("/home/user/packages/foo.json")
+    );
+}
+
+#[test]
+async fn test_relative_package_path_made_absolute() {
+    assert_eq!(
+        resolve_package_path_in("./foo", None, Path::new("/tmp/packages")),
+        PathBuf::from("/tmp/packages/./foo.json")
+    );
+    assert_eq!(
+        resolve_package_path_in("foo.v2", None, Path::new("/tmp/packages")),
+        PathBuf::from("/tmp/packages/foo.v2.json")
+    );
+}
+
+#[test]
+async fn test_absolute_package_path_kept() {
+    assert_eq!(
+        resolve_package_path_in("/srv/foo.json", Some("/home/user"), Path::new("/tmp")),
+        PathBuf::from("/srv/foo.json")
+    );
+    assert_eq!(
+        resolve_package_path_in("/srv/foo.japm", None, Path::new("/tmp")),
+        PathBuf::from("/srv/foo.japm")
+    );
+}
+
+#[test]
+async fn test_package_read_from_file_without_extension() {
+    let directory = get_chained_test_directory("from_file");
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(
+        directory.join("foo.json"),
+        r#"{ "manifest_version": 1, "package_data": { "name": "foo", "version": "0.0.1", "description": "" }, "install": [] }"#,
+    )
+    .unwrap();
+    let config = Config::from_json(r#"{ "remotes": {} }"#).unwrap();
+    let mut package_finder = DefaultPackageFinder::new(true, &config);
+
+    let package = package_finder
+        .find_package(&directory.join("foo").to_string_lossy())
+        .await
+        .unwrap();
+
+    assert_eq!(package.unwrap().package_data.name, "foo");
+}
+
+#[test]
+async fn test_source_remote_recorded() {
+    let port = start_package_server("");
+    let mut package_finder = get_multiple_remotes_finder(&[port]);
+
+    let package = package_finder
+        .find_package("test-package")
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        package.source_remote,
+        Some(format!("http://127.0.0.1:{port}/"))
+    );
+}
+
+#[test]
+async fn test_package_from_file_has_no_source_remote() {
+    let directory = get_chained_test_directory("source_remote");
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(
+        directory.join("foo.json"),
+        r#"{ "manifest_version": 1, "package_data": { "name": "foo", "version": "0.0.1", "description": "" }, "install": [] }"#,
+    )
+    .unwrap();
+    let config = Config::from_json(r#"{ "remotes": {} }"#).unwrap();
+    let mut package_finder = DefaultPackageFinder::new(true, &config);
+
+    let package = package_finder
+        .find_package(&directory.join("foo.json").to_string_lossy())
+        .await
+        .unwrap();
+
+    assert_eq!(package.unwrap().source_remote, None);
+}
+
+#[test]
+async fn test_undeclared_download_size_requested_from_sources() {
+    let port = start_server(|_, _| {
+        String::from("HTTP/1.1 200 OK\r\nContent-Length: 512\r\nConnection: close\r\n\r\n")
+    });
+    let mut package_finder = get_multiple_remotes_finder(&[port]);
+    let package = RemotePackage {
+        sources: vec![
+            format!("http://127.0.0.1:{port}/first.tar.gz"),
+            format!("http://127.0.0.1:{port}/second.tar.gz"),
+        ],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        package_finder.download_size(&package).await.unwrap(),
+        Some(1024)
+    );
+}
+
+#[test]
+async fn test_unreachable_source_leaves_download_size_unknown() {
+    let mut package_finder = get_multiple_remotes_finder(&[]);
+    let package = RemotePackage {
+        sources: vec![format!("{UNREACHABLE_REMOTE}source.tar.gz")],
+        ..Default::default()
+    };
+
+    assert_eq!(package_finder.download_size(&package).await.unwrap(), None);
+}
+
+fn get_multiple_remotes_finder(ports: &[u16]) -> DefaultPackageFinder {
+    progress::set_boxed_progress(Box::new(MockProgressbar));
+
+    let remotes: Vec<String> = ports
+        .iter()
+        .map(|port| format!(r#""remote_{port}": "http://127.0.0.1:{port}/""#))
+        .collect();
+    let config =
+        Config::from_json(&format!(r#"{{ "remotes": {{ {} }} }}"#, remotes.join(", "))).unwrap();
+
+    DefaultPackageFinder::new(false, &config)
+}
+
+fn get_chained_finder(
+    finders: Vec<Box<dyn PackageFinder<Error = PackageFindError>>>,
+    error_policy: ErrorPolicy,
+) -> ChainedPackageFinder<PackageFindError> {
+    ChainedPackageFinder::new(finders, error_policy)
+}
+
+/// Returns an empty directory unique to the test, to be searched by a [DirectoryPackageFinder].
+fn get_chained_test_directory(name: &str) -> PathBuf {
+    let directory = PathBuf::from(format!("/tmp/japm/tests/chained_finder_{name}"));
+    if directory.exists() {
+        std::fs::remove_dir_all(&directory).expect("Could not remove previous package directory");
+    }
+
+    directory
+}
+
+fn write_directory_package(directory: &Path, name: &str) {
+    let package_directory = directory.join("packages").join(name);
+    std::fs::create_dir_all(&package_directory).unwrap();
+    std::fs::write(
+        package_directory.join("package.json"),
+        format!(
+            r#"{{ "manifest_version": 1, "package_data": {{ "name": "{name}", "version": "0.0.1", "description": "local content" }}, "install": [] }}"#
+        ),
+    )
+    .unwrap();
+}
+
+fn package_response(package_path: &str, description: &str) -> String {
+    let package_name = package_path
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .nth(1)
+        .unwrap_or_default();
+    let body = format!(
+        r#"{{ "manifest_version": 1, "package_data": {{ "name": "{package_name}", "version": "0.0.1", "description": "{description}" }}, "install": [] }}"#
+    );
+
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// Starts a server answering every request with the response returned by `respond` for the
+/// requested path and the port of the server. Returns its port.
+fn start_server(respond: impl Fn(&str, u16) -> String + Send + 'static) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+
+            // Read the whole request head, closing with unread data would reset the connection
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let mut header = String::new();
+            while reader.read_line(&mut header).is_ok_and(|read| read > 2) {
+                header.clear();
+            }
+
+            let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+            let _ = stream.write_all(respond(path, port).as_bytes());
+        }
+    });
+
+    port
+}
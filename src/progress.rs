@@ -1,13 +1,24 @@
-use tokio::sync::{Mutex, MutexGuard};
+use std::fmt::Display;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{Mutex, OwnedMutexGuard};
 
 use crate::frontends;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Debug)]
 pub enum ProgressType {
     Setup,
     Packages,
     ActionsBuild,
     ActionsCommit,
+    /// Bytes of the package currently being downloaded
+    Download,
+    /// Package files deleted by removals
+    Files,
 }
 
 #[async_trait::async_trait]
@@ -15,7 +26,42 @@ pub trait Progress {
     async fn increment_target(&mut self, progress_type: ProgressType, amount: i32);
     async fn increment_completed(&mut self, progress_type: ProgressType, amount: i32);
 
-    async fn set_comleted(&mut self, progress_type: ProgressType);
+    /// Completes every target of the group, a group without targets is given one so that it
+    /// still counts as done.
+    async fn set_completed(&mut self, progress_type: ProgressType);
+    async fn reset(&mut self, progress_type: ProgressType);
+}
+
+/// Amount of completed targets before an estimated time remaining is given, earlier estimates
+/// are too unreliable.
+const MIN_COMPLETED_FOR_ETA: i32 = 3;
+
+/// Progress displayed by the frontends
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProgressUpdate {
+    /// From 0 to 1
+    pub progress: f32,
+    /// Time since the first progress was made
+    pub elapsed: Duration,
+    /// Estimated time remaining, None until enough progress was made
+    pub eta: Option<Duration>,
+}
+
+impl Display for ProgressUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}% {} elapsed",
+            (self.progress * 100.0) as u16,
+            format_duration(self.elapsed)
+        )?;
+
+        if let Some(eta) = self.eta {
+            write!(f, ", ~{} remaining", format_duration(eta))?;
+        }
+
+        Ok(())
+    }
 }
 
 pub struct FrontendProgress {
@@ -23,6 +69,16 @@ pub struct FrontendProgress {
     packages: ProgressGroup,
     actions_build: ProgressGroup,
     actions_commit: ProgressGroup,
+    download: ProgressGroup,
+    files: ProgressGroup,
+    /// When the first target was added or completed
+    started: Option<Instant>,
+}
+
+impl Default for FrontendProgress {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 struct ProgressGroup {
@@ -37,17 +93,68 @@ impl FrontendProgress {
             packages: ProgressGroup::new(),
             actions_build: ProgressGroup::new(),
             actions_commit: ProgressGroup::new(),
+            download: ProgressGroup::new(),
+            files: ProgressGroup::new(),
+            started: None,
+        }
+    }
+
+    pub async fn refresh(&mut self) {
+        let now = Instant::now();
+        self.started.get_or_insert(now);
+
+        frontends::set_progressbar(self.get_update(now)).await;
+    }
+
+    /// Progress, elapsed time and estimated time remaining at `now`, assuming the remaining
+    /// targets take as long as the completed ones did.
+    pub fn get_update(&self, now: Instant) -> ProgressUpdate {
+        let progress = self.get_progress();
+        let elapsed = self
+            .started
+            .map_or(Duration::ZERO, |started| now.duration_since(started));
+
+        let eta = if self.get_completed() >= MIN_COMPLETED_FOR_ETA && progress > 0.0 {
+            Some(elapsed.mul_f32((1.0 - progress) / progress))
+        } else {
+            None
+        };
+
+        ProgressUpdate {
+            progress,
+            elapsed,
+            eta,
+        }
+    }
+
+    /// Every completed target counts the same regardless of its group, so groups with more
+    /// targets take more space of the progressbar. The current download counts as a fraction of
+    /// a target, so that the progressbar advances while downloading.
+    pub fn get_progress(&self) -> f32 {
+        let completed = self.get_completed();
+        let target: i32 = self.groups().iter().map(|group| group.target).sum();
+
+        if target == 0 {
+            0.0
+        } else {
+            let completed = completed as f32 + self.download.get_progress();
+            (completed / target as f32).min(1.0)
         }
     }
 
-    pub async fn refresh(&self) {
-        let multiplier = 1.0 / 4.0;
-        let progress: f32 = self.setup.get_progress() * multiplier
-            + self.packages.get_progress() * multiplier
-            + self.actions_build.get_progress() * multiplier
-            + self.actions_commit.get_progress() * multiplier;
+    fn get_completed(&self) -> i32 {
+        self.groups().iter().map(|group| group.completed).sum()
+    }
 
-        frontends::set_progressbar(progress).await;
+    /// The groups counting towards the progress, the download is only part of a target
+    fn groups(&self) -> [&ProgressGroup; 5] {
+        [
+            &self.setup,
+            &self.packages,
+            &self.actions_build,
+            &self.actions_commit,
+            &self.files,
+        ]
     }
 
     fn progress_group(&mut self, progress_type: ProgressType) -> &mut ProgressGroup {
@@ -56,6 +163,8 @@ impl FrontendProgress {
             ProgressType::Packages => &mut self.packages,
             ProgressType::ActionsBuild => &mut self.actions_build,
             ProgressType::ActionsCommit => &mut self.actions_commit,
+            ProgressType::Download => &mut self.download,
+            ProgressType::Files => &mut self.files,
         }
     }
 }
@@ -70,10 +179,16 @@ impl Progress for FrontendProgress {
         self.progress_group(progress_type).completed += amount;
         self.refresh().await;
     }
-    async fn set_comleted(&mut self, progress_type: ProgressType) {
+    async fn set_completed(&mut self, progress_type: ProgressType) {
         let progress_group = self.progress_group(progress_type);
-        progress_group.completed = 1;
-        progress_group.target = 1;
+        progress_group.target = progress_group.target.max(1);
+        progress_group.completed = progress_group.target;
+        self.refresh().await;
+    }
+    async fn reset(&mut self, progress_type: ProgressType) {
+        let progress_group = self.progress_group(progress_type);
+        progress_group.completed = 0;
+        progress_group.target = 0;
         self.refresh().await;
     }
 }
@@ -90,19 +205,35 @@ impl ProgressGroup {
         if self.target == 0 {
             0.0
         } else {
-            self.completed as f32 / self.target as f32
+            (self.completed as f32 / self.target as f32).min(1.0)
         }
     }
 }
 
-static mut CURRENT_PROGRESS: Option<Mutex<Box<dyn Progress>>> = None;
+/// Formats the duration as hours, minutes and seconds, leaving out leading zero units.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
 
-pub fn set_boxed_progress(progress: Box<dyn Progress>) {
-    unsafe {
-        CURRENT_PROGRESS = Some(Mutex::new(progress));
+    if hours > 0 {
+        format!("{hours}h {minutes:02}m {seconds:02}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds:02}s")
+    } else {
+        format!("{seconds}s")
     }
 }
 
+type SharedProgress = Arc<Mutex<Box<dyn Progress + Send>>>;
+
+/// Replaced by tests, so it can be set more than once. The outer lock is only held to get the
+/// current progress, never across an await.
+static CURRENT_PROGRESS: RwLock<Option<SharedProgress>> = RwLock::new(None);
+
+pub fn set_boxed_progress(progress: Box<dyn Progress + Send>) {
+    *CURRENT_PROGRESS.write().unwrap() = Some(Arc::new(Mutex::new(progress)));
+}
+
 pub async fn increment_target(progress_type: ProgressType, amount: i32) {
     get_progress()
         .await
@@ -115,13 +246,18 @@ pub async fn increment_completed(progress_type: ProgressType, amount: i32) {
         .increment_completed(progress_type, amount)
         .await;
 }
-pub async fn set_comleted(progress_type: ProgressType) {
-    get_progress().await.set_comleted(progress_type).await;
+pub async fn set_completed(progress_type: ProgressType) {
+    get_progress().await.set_completed(progress_type).await;
+}
+pub async fn reset(progress_type: ProgressType) {
+    get_progress().await.reset(progress_type).await;
 }
 
-async fn get_progress<'a>() -> MutexGuard<'a, Box<dyn Progress>> {
-    unsafe {
-        #[allow(clippy::mut_mutex_lock)]
-        CURRENT_PROGRESS.as_mut().unwrap().lock().await
-    }
+async fn get_progress() -> OwnedMutexGuard<Box<dyn Progress + Send>> {
+    let progress = CURRENT_PROGRESS
+        .read()
+        .unwrap()
+        .clone()
+        .expect("Progress used before being set");
+    progress.lock_owned().await
 }
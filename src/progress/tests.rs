@@ -0,0 +1,129 @@
+use tokio::test;
+
+use super::*;
+
+#[test]
+async fn test_progress_weighted_by_targets() {
+    let mut progress = FrontendProgress::new();
+
+    progress.set_completed(ProgressType::Setup).await;
+    progress.increment_target(ProgressType::Packages, 199).await;
+
+    // The finished setup is 1 of 200 targets, not a quarter of the progressbar
+    assert_eq!(progress.get_progress(), 1.0 / 200.0);
+
+    progress
+        .increment_completed(ProgressType::Packages, 99)
+        .await;
+    assert_eq!(progress.get_progress(), 0.5);
+}
+
+#[test]
+async fn test_download_advances_current_target() {
+    let mut progress = FrontendProgress::new();
+
+    progress.increment_target(ProgressType::Packages, 2).await;
+    progress
+        .increment_target(ProgressType::Download, 1000)
+        .await;
+    progress
+        .increment_completed(ProgressType::Download, 500)
+        .await;
+
+    assert_eq!(progress.get_progress(), 0.25);
+
+    progress.reset(ProgressType::Download).await;
+    assert_eq!(progress.get_progress(), 0.0);
+}
+
+#[test]
+async fn test_deleted_files_weighted_by_count() {
+    let mut progress = FrontendProgress::new();
+
+    progress.increment_target(ProgressType::Packages, 1).await;
+    progress.increment_target(ProgressType::Files, 999).await;
+    progress.increment_completed(ProgressType::Files, 500).await;
+
+    assert_eq!(progress.get_progress(), 0.5);
+}
+
+#[test]
+async fn test_progress_without_targets_is_empty() {
+    let progress = FrontendProgress::new();
+
+    assert_eq!(progress.get_progress(), 0.0);
+}
+
+#[test]
+async fn test_progress_does_not_exceed_completion() {
+    let mut progress = FrontendProgress::new();
+
+    progress
+        .increment_target(ProgressType::ActionsBuild, 2)
+        .await;
+    progress
+        .increment_completed(ProgressType::ActionsBuild, 3)
+        .await;
+
+    assert_eq!(progress.get_progress(), 1.0);
+}
+
+#[test]
+async fn test_eta_hidden_until_enough_completed() {
+    let mut progress = FrontendProgress::new();
+
+    progress.increment_target(ProgressType::Packages, 10).await;
+    progress
+        .increment_completed(ProgressType::Packages, 2)
+        .await;
+
+    let started = progress.started.unwrap();
+    let update = progress.get_update(started + Duration::from_secs(4));
+    assert_eq!(update.elapsed, Duration::from_secs(4));
+    assert_eq!(update.eta, None);
+
+    progress
+        .increment_completed(ProgressType::Packages, 3)
+        .await;
+
+    // Half of the targets took 10 seconds, so the other half should take as long
+    let update = progress.get_update(started + Duration::from_secs(10));
+    assert_eq!(update.eta, Some(Duration::from_secs(10)));
+}
+
+#[test]
+async fn test_progress_update_display() {
+    let update = ProgressUpdate {
+        progress: 0.5,
+        elapsed: Duration::from_secs(75),
+        eta: None,
+    };
+    assert_eq!(update.to_string(), "50% 1m 15s elapsed");
+
+    let update = ProgressUpdate {
+        eta: Some(Duration::from_secs(3725)),
+        ..update
+    };
+    assert_eq!(
+        update.to_string(),
+        "50% 1m 15s elapsed, ~1h 02m 05s remaining"
+    );
+}
+
+#[test]
+async fn test_set_completed_keeps_real_target() {
+    let mut progress = FrontendProgress::new();
+
+    progress.increment_target(ProgressType::Packages, 4).await;
+    progress
+        .increment_completed(ProgressType::Packages, 1)
+        .await;
+    progress.set_completed(ProgressType::Packages).await;
+
+    // The 4 targets are completed, not replaced by a single one
+    assert_eq!(progress.get_progress(), 1.0);
+    progress
+        .increment_target(ProgressType::ActionsBuild, 4)
+        .await;
+    assert_eq!(progress.get_progress(), 0.5);
+}
@@ -0,0 +1,60 @@
+use std::process::Command;
+
+use log::{debug, info};
+
+use thiserror::Error;
+
+use crate::action::Action;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Could not parse snapshot command: {0}")]
+    Parse(#[from] shell_words::ParseError),
+    #[error("Snapshot command is empty")]
+    EmptyCommand,
+    #[error("An IO error has occured: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("Snapshot command failed with exit code {0:?} and stderr:\n{1}")]
+    CommandFail(Option<i32>, String),
+}
+
+/// Returns the paths that are known to be changed by the actions before they are built: the
+/// database and the files of removed packages. The files of installed packages are only known
+/// once the actions are built.
+pub fn get_mutated_paths(actions: &[Action], database_path: &str) -> Vec<String> {
+    let mut paths = vec![String::from(database_path)];
+
+    for action in actions.iter() {
+        if let Action::Remove(package) = action {
+            paths.extend(package.package_files.iter().map(|file| file.path.clone()));
+        }
+    }
+
+    paths
+}
+
+/// Runs the snapshot command with the mutated paths as arguments, and returns the snapshot id
+/// it printed to stdout.
+pub fn take_snapshot(snapshot_command: &str, paths: &[String]) -> Result<String, SnapshotError> {
+    let mut args = shell_words::split(snapshot_command)?.into_iter();
+    let program = args.next().ok_or(SnapshotError::EmptyCommand)?;
+
+    debug!("Taking snapshot with {snapshot_command} of {paths:?}");
+
+    let output = Command::new(program).args(args).args(paths).output()?;
+
+    if !output.status.success() {
+        return Err(SnapshotError::CommandFail(
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+
+    let snapshot_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    info!("Took snapshot {snapshot_id}");
+
+    Ok(snapshot_id)
+}
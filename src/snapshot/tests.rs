@@ -0,0 +1,46 @@
+use crate::package::{LocalPackage, PackageData, PackageFile};
+
+use super::*;
+
+const SNAPSHOT_ARGS_PATH: &str = "/tmp/japm/tests/snapshot_args";
+
+#[test]
+fn test_snapshot_command_invoked_with_mutated_paths() {
+    std::fs::create_dir_all("/tmp/japm/tests").unwrap();
+
+    let actions = vec![Action::Remove(Box::new(LocalPackage {
+        id: Some(1),
+        package_data: PackageData {
+            name: String::from("test-snapshot-package"),
+            ..Default::default()
+        },
+        dependencies: vec![],
+        replaces: vec![],
+        source_remote: None,
+        arch: vec![],
+        os: vec![],
+        pre_remove: vec![],
+        package_files: vec![PackageFile::from("/usr/bin/test-snapshot-package")],
+        post_remove: vec![],
+    }))];
+
+    let paths = get_mutated_paths(&actions, "/var/lib/japm/packages.db");
+    let snapshot_command =
+        format!("sh -c 'echo \"$@\" > {SNAPSHOT_ARGS_PATH} && echo snapshot-1' snapshot");
+
+    let snapshot_id = take_snapshot(&snapshot_command, &paths).unwrap();
+
+    assert_eq!(snapshot_id, "snapshot-1");
+    assert_eq!(
+        std::fs::read_to_string(SNAPSHOT_ARGS_PATH).unwrap(),
+        "/var/lib/japm/packages.db /usr/bin/test-snapshot-package\n"
+    );
+}
+
+#[test]
+fn test_failing_snapshot_command_rejected() {
+    assert!(matches!(
+        take_snapshot("false", &[]),
+        Err(SnapshotError::CommandFail(Some(1), _))
+    ));
+}
@@ -1,15 +1,39 @@
+use std::collections::{HashMap, HashSet};
+
 use super::errors::StringError;
 use crate::db::PackagesDb;
 use crate::package::{LocalPackage, RemotePackage};
 
+/// Installed packages, history, binaries and holds
+type DbState = (
+    Vec<LocalPackage>,
+    Vec<LocalPackage>,
+    HashMap<String, String>,
+    HashSet<String>,
+);
+
 pub struct MockPackagesDb {
     installed_packges: Vec<LocalPackage>,
+    next_id: i32,
+    /// Removed packages, oldest first
+    history: Vec<LocalPackage>,
+    /// Registered binaries and the name of the package that registered them
+    binaries: HashMap<String, String>,
+    /// Names of the held installed packages
+    held: HashSet<String>,
+    /// Copy of the state taken when a transaction begins, restored on rollback.
+    transaction_snapshot: Option<DbState>,
 }
 
 impl MockPackagesDb {
     pub fn new() -> MockPackagesDb {
         MockPackagesDb {
             installed_packges: Vec::new(),
+            next_id: 1,
+            history: Vec::new(),
+            binaries: HashMap::new(),
+            held: HashSet::new(),
+            transaction_snapshot: None,
         }
     }
 }
@@ -18,17 +42,33 @@ impl PackagesDb for MockPackagesDb {
     type AddError = StringError;
     type RemoveError = StringError;
     type GetError = StringError;
+    type TransactionError = StringError;
 
     fn add_package(&mut self, package: &RemotePackage) -> Result<(), Self::AddError> {
+        if self.contains(&package.package_data.name)? {
+            self.remove_package(&package.package_data.name)?;
+        }
+
         let local_packge = LocalPackage {
+            id: Some(self.next_id),
             package_data: package.package_data.clone(),
             pre_remove: package.pre_remove.clone(),
             post_remove: package.post_remove.clone(),
             package_files: package.package_files.clone(),
             dependencies: package.dependencies.clone(),
+            replaces: package.replaces.clone(),
+            source_remote: package.source_remote.clone(),
+            arch: package.arch.clone(),
+            os: package.os.clone(),
         };
 
         self.installed_packges.push(local_packge);
+        self.next_id += 1;
+
+        for binary in package.binaries.iter() {
+            self.binaries
+                .insert(binary.clone(), package.package_data.name.clone());
+        }
 
         Ok(())
     }
@@ -40,7 +80,11 @@ impl PackagesDb for MockPackagesDb {
             .position(|p| p.package_data.name == package_name);
 
         if let Some(index) = index {
-            self.installed_packges.remove(index);
+            let mut removed_package = self.installed_packges.remove(index);
+            removed_package.id = None;
+            self.binaries.retain(|_, package| package != package_name);
+            self.held.remove(package_name);
+            self.history.push(removed_package);
             Ok(())
         } else {
             Err("Package not found".into())
@@ -60,6 +104,13 @@ impl PackagesDb for MockPackagesDb {
         }
     }
 
+    fn contains(&mut self, package_name: &str) -> Result<bool, Self::GetError> {
+        Ok(self
+            .installed_packges
+            .iter()
+            .any(|p| p.package_data.name == package_name))
+    }
+
     fn get_all_packages(&mut self) -> Result<Vec<LocalPackage>, Self::GetError> {
         Ok(self.installed_packges.clone())
     }
@@ -81,4 +132,88 @@ impl PackagesDb for MockPackagesDb {
 
         Ok(depending_packages)
     }
+
+    fn get_binary_owner(&mut self, binary: &str) -> Result<Option<String>, Self::GetError> {
+        Ok(self.binaries.get(binary).cloned())
+    }
+
+    fn get_package_binaries(&mut self, package_name: &str) -> Result<Vec<String>, Self::GetError> {
+        let mut binaries: Vec<String> = self
+            .binaries
+            .iter()
+            .filter(|(_, package)| *package == package_name)
+            .map(|(binary, _)| binary.clone())
+            .collect();
+        binaries.sort();
+
+        Ok(binaries)
+    }
+
+    fn get_package_versions(
+        &mut self,
+        package_name: &str,
+    ) -> Result<Vec<LocalPackage>, Self::GetError> {
+        let mut versions: Vec<LocalPackage> = self
+            .history
+            .iter()
+            .filter(|p| p.package_data.name == package_name)
+            .cloned()
+            .collect();
+
+        versions.extend(self.get_package(package_name)?);
+
+        Ok(versions)
+    }
+
+    fn set_held(&mut self, package_name: &str, held: bool) -> Result<(), Self::AddError> {
+        if !self.contains(package_name)? {
+            return Ok(());
+        }
+
+        if held {
+            self.held.insert(String::from(package_name));
+        } else {
+            self.held.remove(package_name);
+        }
+
+        Ok(())
+    }
+
+    fn is_held(&mut self, package_name: &str) -> Result<bool, Self::GetError> {
+        Ok(self.held.contains(package_name))
+    }
+
+    fn begin_transaction(&mut self) -> Result<(), Self::TransactionError> {
+        if self.transaction_snapshot.is_some() {
+            return Err("Transaction already in progress".into());
+        }
+
+        self.transaction_snapshot = Some((
+            self.installed_packges.clone(),
+            self.history.clone(),
+            self.binaries.clone(),
+            self.held.clone(),
+        ));
+        Ok(())
+    }
+
+    fn commit_transaction(&mut self) -> Result<(), Self::TransactionError> {
+        match self.transaction_snapshot.take() {
+            Some(_) => Ok(()),
+            None => Err("No transaction in progress".into()),
+        }
+    }
+
+    fn rollback_transaction(&mut self) -> Result<(), Self::TransactionError> {
+        match self.transaction_snapshot.take() {
+            Some((installed_packges, history, binaries, held)) => {
+                self.installed_packges = installed_packges;
+                self.history = history;
+                self.binaries = binaries;
+                self.held = held;
+                Ok(())
+            }
+            None => Err("No transaction in progress".into()),
+        }
+    }
 }
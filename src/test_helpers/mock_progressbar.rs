@@ -6,5 +6,6 @@ pub struct MockProgressbar;
 impl Progress for MockProgressbar {
     async fn increment_target(&mut self, _progress_type: ProgressType, _amount: i32) {}
     async fn increment_completed(&mut self, _progress_type: ProgressType, _amount: i32) {}
-    async fn set_comleted(&mut self, _progress_type: ProgressType) {}
+    async fn set_completed(&mut self, _progress_type: ProgressType) {}
+    async fn reset(&mut self, _progress_type: ProgressType) {}
 }
@@ -1,4 +1,6 @@
 pub use mock_db::MockPackagesDb;
+pub use mock_progressbar::MockProgressbar;
 
 pub mod errors;
 pub mod mock_db;
+pub mod mock_progressbar;
@@ -0,0 +1,113 @@
+use clap::error::ErrorKind;
+
+use super::*;
+
+#[test]
+fn test_no_arguments_show_help() {
+    let error = Args::try_parse_from(["japm"]).unwrap_err();
+    assert_eq!(
+        error.kind(),
+        ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+    );
+    assert_eq!(error.exit_code(), 2);
+}
+
+#[test]
+fn test_flags_without_command_rejected() {
+    let error = Args::try_parse_from(["japm", "--verbose"]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::MissingSubcommand);
+    assert!(error.to_string().contains("Usage"));
+}
+
+#[test]
+fn test_command_parsed() {
+    let args = Args::try_parse_from(["japm", "info", "package"]).unwrap();
+    assert!(matches!(args.command, CommandType::Info { packages } if packages == ["package"]));
+}
+
+#[test]
+fn test_lint_parsed_with_path() {
+    let args = Args::try_parse_from(["japm", "lint", "package.json"]).unwrap();
+    assert!(matches!(args.command, CommandType::Lint { path } if path == "package.json"));
+}
+
+#[test]
+fn test_package_names_normalized() {
+    let packages = vec![" foo", "bar", "foo ", "baz", "bar"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+    assert_eq!(
+        normalize_package_names(packages).unwrap(),
+        vec!["foo", "bar", "baz"]
+    );
+}
+
+#[test]
+fn test_empty_package_name_rejected() {
+    let packages = vec![String::from("foo"), String::from("  ")];
+
+    assert!(normalize_package_names(packages).is_err());
+}
+
+#[test]
+fn test_commands_require_packages() {
+    for command in ["install", "remove", "info", "hold", "unhold", "update"] {
+        let error = Args::try_parse_from(["japm", command]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+    }
+
+    assert!(Args::try_parse_from(["japm", "install", "--manifest", "manifest.json"]).is_ok());
+    assert!(Args::try_parse_from(["japm", "update", "--system"]).is_ok());
+    assert!(Args::try_parse_from(["japm", "verify"]).is_ok());
+}
+
+#[test]
+fn test_command_errors_mapped_to_exit_codes() {
+    let config_error: Box<dyn Error> =
+        Box::new(config::Error::RemoteNotFound(String::from("remote")));
+    assert_eq!(command_exit_code(config_error.as_ref()), ExitCode::Config);
+
+    let install_error: Box<dyn Error> = Box::new(commands::InstallError::<
+        config::Error,
+        config::Error,
+    >::PackageNotFound(String::from("package")));
+    assert_eq!(
+        command_exit_code(install_error.as_ref()),
+        ExitCode::Resolution
+    );
+
+    let database_error =
+        TranslatedPackageQueryError::from(serde_json::from_str::<Vec<String>>("{").unwrap_err());
+    let install_error: Box<dyn Error> = Box::new(commands::InstallError::<
+        TranslatedPackageQueryError,
+        config::Error,
+    >::Database(database_error));
+    assert_eq!(
+        command_exit_code(install_error.as_ref()),
+        ExitCode::Database
+    );
+
+    let io_error: Box<dyn Error> = Box::new(io::Error::other("io"));
+    assert_eq!(command_exit_code(io_error.as_ref()), ExitCode::Failure);
+}
+
+#[test]
+fn test_only_state_changing_commands_locked() {
+    let requires_lock = |args: &[&str]| {
+        requires_lock(
+            &Args::try_parse_from(["japm"].iter().chain(args))
+                .unwrap()
+                .command,
+        )
+    };
+
+    assert!(requires_lock(&["install", "package"]));
+    assert!(requires_lock(&["remove", "package"]));
+    assert!(requires_lock(&["update", "package"]));
+    assert!(!requires_lock(&["remove", "--dry-run", "package"]));
+    assert!(!requires_lock(&["update", "--check", "--watch"]));
+    assert!(!requires_lock(&["info", "package"]));
+    assert!(!requires_lock(&["owns", "/usr/bin/package"]));
+}